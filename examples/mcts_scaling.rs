@@ -0,0 +1,44 @@
+//! Measures how Monte Carlo tree search scales with threads on a 9x9 board.
+//!
+//! Run with `cargo run --release --example mcts_scaling [rounds]`.
+
+use std::time::Instant;
+
+use bgai::agent::{FastRandomBot, MctsBot, MctsConfig, Parallelism};
+use bgai::game::go::GoState;
+
+fn main() {
+    let rounds = std::env::args()
+        .nth(1)
+        .map(|arg| arg.parse().expect("rounds must be a number"))
+        .unwrap_or(2000);
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let game = GoState::new(9);
+
+    println!("{:>6} {:>8} {:>12} {:>8}", "mode", "threads", "playouts/s", "speedup");
+    for parallelism in [Parallelism::Root, Parallelism::Tree] {
+        let mut baseline = None;
+        let mut threads = 1;
+        while threads <= max_threads {
+            let config = MctsConfig {
+                rounds,
+                threads,
+                parallelism,
+                ..MctsConfig::default()
+            };
+            let mut bot = MctsBot::with_seed(config, FastRandomBot::with_seed, 1);
+            let start = Instant::now();
+            bot.search(&game);
+            let playouts_per_second = rounds as f64 / start.elapsed().as_secs_f64();
+            let baseline = *baseline.get_or_insert(playouts_per_second);
+            println!(
+                "{:>6} {:>8} {:>12.0} {:>8.2}",
+                format!("{:?}", parallelism),
+                threads,
+                playouts_per_second,
+                playouts_per_second / baseline
+            );
+            threads *= 2;
+        }
+    }
+}
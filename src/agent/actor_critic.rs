@@ -0,0 +1,70 @@
+use anyhow::{ensure, Result};
+
+use crate::agent::{Agent, PolicyAgent};
+use crate::game::go::encoders::Encoder;
+use crate::game::go::{GoState, Move};
+use crate::nn::{Network, POLICY_HEAD, VALUE_HEAD};
+
+/// Plays moves sampled from the policy head of a network whose value head,
+/// the critic, shares its trunk. The value only guides training, and
+/// resigning if a threshold is given.
+pub struct ActorCriticAgent {
+    policy: PolicyAgent,
+}
+
+impl ActorCriticAgent {
+    /// Agent for a network with policy and value heads whose input is the
+    /// encoder's
+    pub fn new(network: Network, encoder: Box<dyn Encoder + Send + Sync>) -> Result<Self> {
+        Self::with_seed(network, encoder, rand::random())
+    }
+
+    pub fn with_seed(network: Network, encoder: Box<dyn Encoder + Send + Sync>, seed: u64) -> Result<Self> {
+        for head in [POLICY_HEAD, VALUE_HEAD].iter() {
+            ensure!(network.head_names().any(|name| name == *head), "The network has no {} head", head);
+        }
+        // Moves follow the policy's probabilities, as the policy gradient
+        // assumes
+        let policy = PolicyAgent::with_seed(network, encoder, seed)?.with_temperature(1.0);
+        Ok(Self { policy })
+    }
+
+    /// Sample with the probabilities raised to `1 / temperature`, zero for
+    /// playing the most likely move
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.policy = self.policy.with_temperature(temperature);
+        self
+    }
+
+    /// Resign when the value of the position is below the threshold
+    pub fn with_resign_threshold(mut self, threshold: f32) -> Self {
+        self.policy = self.policy.with_resign_threshold(threshold);
+        self
+    }
+}
+
+impl Agent<GoState> for ActorCriticAgent {
+    fn select_move(&mut self, game_state: &GoState) -> Move {
+        self.policy.select_move(game_state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::OnePlaneEncoder;
+    use crate::game::GameState;
+
+    #[test]
+    fn test_samples_valid_moves_of_policy_and_value_network() {
+        let network = Network::random((1, 3, 3), &[2], true, 1).unwrap();
+        let mut agent = ActorCriticAgent::with_seed(network, Box::new(OnePlaneEncoder::new(3)), 1).unwrap();
+        let game = GoState::new(3);
+        let moves: Vec<Move> = (0..30).map(|_| agent.select_move(&game)).collect();
+        assert!(moves.iter().all(|m| game.is_valid_move(m)));
+        assert!(moves.iter().any(|m| *m != moves[0]));
+
+        let policy_only = Network::random((1, 3, 3), &[2], false, 1).unwrap();
+        assert!(ActorCriticAgent::new(policy_only, Box::new(OnePlaneEncoder::new(3))).is_err());
+    }
+}
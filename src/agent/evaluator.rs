@@ -0,0 +1,54 @@
+/// Static evaluation of a game state from the point of view of the player
+/// about to move. Larger is better for that player.
+///
+/// Implemented for all functions and closures taking a state, so plain
+/// evaluation functions such as `go::stone_difference` can be used directly.
+/// Implement it by hand for evaluators that carry configuration or state,
+/// e.g. tuned weights or a cache.
+pub trait Evaluator<S> {
+    fn evaluate(&mut self, game_state: &S) -> i32;
+}
+
+impl<S, F> Evaluator<S> for F
+where
+    F: FnMut(&S) -> i32,
+{
+    fn evaluate(&mut self, game_state: &S) -> i32 {
+        self(game_state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::minimax::minimax;
+    use crate::game::one_two_three::*;
+
+    /// Counts evaluations to check that state is carried through the search
+    struct CountingEvaluator {
+        calls: usize,
+    }
+
+    impl Evaluator<OneTwoThreeState> for CountingEvaluator {
+        fn evaluate(&mut self, game_state: &OneTwoThreeState) -> i32 {
+            self.calls += 1;
+            score_difference(game_state)
+        }
+    }
+
+    #[test]
+    fn test_stateful_evaluator_is_called_at_every_leaf() {
+        let game = OneTwoThreeState::new();
+        let mut evaluator = CountingEvaluator { calls: 0 };
+        minimax(&game, 2, &mut evaluator);
+        assert_eq!(evaluator.calls, 9);
+    }
+
+    #[test]
+    fn test_closure_can_be_used_as_evaluator() {
+        let game = OneTwoThreeState::new();
+        let weight = 2;
+        let optimal_move = minimax(&game, 1, &mut |g: &OneTwoThreeState| weight * score_difference(g));
+        assert_eq!(optimal_move.value, 6);
+    }
+}
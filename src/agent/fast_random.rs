@@ -0,0 +1,113 @@
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::agent::{Agent, AgentRng};
+use crate::game::GameState;
+use crate::game::go::{GoState, Move, Point};
+
+/// A random playout bot for Go. Instead of generating all valid moves, tries
+/// the board points in random order and plays the first valid one that does
+/// not fill its own eye. Never resigns, and passes only when no such point
+/// remains.
+pub struct FastRandomBot {
+    rng: AgentRng,
+    /// All points of the board size last played on, shuffled for each move
+    point_cache: Vec<Point>,
+}
+
+impl FastRandomBot {
+    pub fn new() -> Self {
+        Self {
+            rng: AgentRng::from_entropy(),
+            point_cache: Vec::new(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            rng: AgentRng::seed_from_u64(seed),
+            point_cache: Vec::new(),
+        }
+    }
+
+    fn update_point_cache(&mut self, rows: usize, cols: usize) {
+        if self.point_cache.len() != rows * cols {
+            self.point_cache = (1..=rows)
+                .flat_map(|row| (1..=cols).map(move |col| Point::new(row, col)))
+                .collect();
+        }
+    }
+}
+
+impl Default for FastRandomBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Agent<GoState> for FastRandomBot {
+    fn select_move(&mut self, game_state: &GoState) -> Move {
+        let board = &game_state.board;
+        self.update_point_cache(board.rows, board.cols);
+        self.point_cache.shuffle(&mut self.rng);
+
+        let color = game_state.next_player.color;
+        self.point_cache
+            .iter()
+            .filter(|p| board.get(p).is_none() && !board.is_eye(p, color))
+            .map(|p| Move::Play(*p))
+            .find(|the_move| game_state.is_valid_move(the_move))
+            .unwrap_or(Move::Pass)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::{Board, Player};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_fast_random_bot_does_not_fill_own_eyes() {
+        let board = r#"
+        x.x
+        xxx
+        .x."#;
+        let board = Board::from_str(board).unwrap();
+        let game = GoState::from_board(board, Player::black());
+        let mut bot = FastRandomBot::new();
+
+        for _ in 0..20 {
+            assert_eq!(bot.select_move(&game), Move::Pass);
+        }
+    }
+
+    #[test]
+    fn test_fast_random_bot_plays_only_valid_moves() {
+        let board = r#"
+        .o.
+        o.o
+        .o."#;
+        let board = Board::from_str(board).unwrap();
+        let game = GoState::from_board(board, Player::black());
+        let mut bot = FastRandomBot::new();
+
+        for _ in 0..20 {
+            let the_move = bot.select_move(&game);
+            assert!(game.is_valid_move(&the_move));
+            assert_ne!(the_move, Move::Resign);
+        }
+    }
+
+    #[test]
+    fn test_fast_random_bot_self_play_terminates() {
+        let mut game = GoState::new(5);
+        let mut bot = FastRandomBot::new();
+        while !game.is_over() {
+            let the_move = bot.select_move(&game);
+            assert_ne!(the_move, Move::Resign);
+            game = game.apply_move(&the_move);
+            assert!(game.moves.len() < 1000, "Random game did not end");
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt::Display;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Stdin, Stdout, Write};
+use std::str::FromStr;
+
+use crate::agent::Agent;
+use crate::game::GameState;
+
+/// A human player at the terminal. Shows the game state and reads moves
+/// until a valid one is given, e.g. `D4`, `pass` or `resign` in Go.
+pub struct HumanAgent<R, W> {
+    input: R,
+    output: W,
+}
+
+/// Where a HumanAgent reads its moves from, one line at a time
+pub trait LineInput {
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize>;
+}
+
+/// Standard input is locked only while a line is read, so that the rest of
+/// the program can still read from it between moves
+impl LineInput for Stdin {
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.lock().read_line(buf)
+    }
+}
+
+impl<T: AsRef<[u8]>> LineInput for Cursor<T> {
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        BufRead::read_line(self, buf)
+    }
+}
+
+impl<R: Read> LineInput for BufReader<R> {
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        BufRead::read_line(self, buf)
+    }
+}
+
+impl HumanAgent<Stdin, Stdout> {
+    pub fn new() -> Self {
+        Self::with_io(io::stdin(), io::stdout())
+    }
+}
+
+impl Default for HumanAgent<Stdin, Stdout> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: LineInput, W: Write> HumanAgent<R, W> {
+    pub fn with_io(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    fn prompt<S>(&mut self, game_state: &S) -> io::Result<S::Move>
+    where
+        S: GameState + Display,
+        S::Move: FromStr,
+        <S::Move as FromStr>::Err: Display,
+    {
+        writeln!(self.output, "{}", game_state)?;
+        loop {
+            write!(self.output, "Your move: ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Input closed"));
+            }
+            match line.parse::<S::Move>() {
+                Ok(the_move) if game_state.is_valid_move(&the_move) => return Ok(the_move),
+                Ok(the_move) => writeln!(self.output, "Illegal move: {:?}", the_move)?,
+                Err(e) => writeln!(self.output, "{}", e)?,
+            }
+        }
+    }
+}
+
+impl<S, R, W> Agent<S> for HumanAgent<R, W>
+where
+    S: GameState + Display,
+    S::Move: FromStr,
+    <S::Move as FromStr>::Err: Display,
+    R: LineInput,
+    W: Write,
+{
+    fn select_move(&mut self, game_state: &S) -> S::Move {
+        self.prompt(game_state).expect("Failed to read move")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::{GoState, Move, Point};
+    use crate::game::GameState;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_human_agent_reprompts_until_move_is_valid() {
+        let game = GoState::new(5).apply_move(&Move::Play(Point::new(2, 2)));
+        let input = Cursor::new("hello\nZ9\nF1\nB2\nc3\n");
+        let mut output = Vec::new();
+        let the_move = HumanAgent::with_io(input, &mut output).select_move(&game);
+
+        assert_eq!(the_move, Move::Play(Point::new(3, 3)));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("White (o) to move"));
+        assert_eq!(output.matches("Your move: ").count(), 5);
+        assert_eq!(output.matches("Illegal move").count(), 3);
+    }
+
+    #[test]
+    fn test_human_agent_passes_and_resigns() {
+        let game = GoState::new(5);
+        let mut agent = HumanAgent::with_io(Cursor::new("pass\nresign\n"), Vec::new());
+        assert_eq!(agent.select_move(&game), Move::Pass);
+        assert_eq!(agent.select_move(&game), Move::Resign);
+    }
+
+    #[test]
+    fn test_human_agent_plays_any_game_with_parseable_moves() {
+        use crate::game::one_two_three::{Move, OneTwoThreeState};
+        let game = OneTwoThreeState::new();
+        let mut agent = HumanAgent::with_io(Cursor::new("4\nthree\n"), Vec::new());
+        assert_eq!(agent.select_move(&game), Move::Three);
+    }
+
+    #[test]
+    fn test_human_agent_does_not_hold_stdin() {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let _agent = HumanAgent::new();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            drop(io::stdin().lock());
+            sender.send(()).unwrap();
+        });
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok(), "Stdin is locked by the agent");
+    }
+}
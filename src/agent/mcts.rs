@@ -0,0 +1,645 @@
+//! Monte Carlo tree search with UCT selection and random playouts, run on
+//! several threads.
+//!
+//! With root parallelization every thread grows its own tree and the root
+//! statistics are merged at the end. With tree parallelization the threads
+//! share one tree, and a virtual loss is added to the nodes on the path of a
+//! running playout so that other threads explore elsewhere meanwhile.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use rand::{Rng, SeedableRng};
+
+use crate::agent::{Agent, AgentRng};
+use crate::game::{GameOutcome, Outcome};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parallelism {
+    /// Independent trees, merged at the root when the search is finished
+    Root,
+    /// One shared tree with virtual loss
+    Tree,
+}
+
+#[derive(Clone, Debug)]
+pub struct MctsConfig {
+    /// Number of playouts per move, over all threads
+    pub rounds: u32,
+    pub threads: usize,
+    pub parallelism: Parallelism,
+    /// Weight of the exploration term of UCT
+    pub exploration: f64,
+    /// Number of losses temporarily added to each node on the path of a
+    /// running playout, with tree parallelization
+    pub virtual_loss: u32,
+    /// Playouts longer than this are scored as they stand
+    pub max_playout_moves: usize,
+    /// Keep the subtree of the moves played between searches
+    pub reuse_tree: bool,
+    /// Maximum number of playouts on a background thread while waiting for
+    /// the next move, zero for no pondering
+    pub ponder_rounds: u32,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        Self {
+            rounds: 1000,
+            threads: 1,
+            parallelism: Parallelism::Root,
+            exploration: 1.5,
+            virtual_loss: 3,
+            max_playout_moves: 1000,
+            reuse_tree: true,
+            ponder_rounds: 0,
+        }
+    }
+}
+
+/// Search statistics of a root move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveStats<M> {
+    pub the_move: M,
+    pub visits: u32,
+    /// Sum of playout results for the player to move at the root, one for
+    /// a win and a half for a draw
+    pub wins: f64,
+}
+
+impl<M> MoveStats<M> {
+    pub fn win_rate(&self) -> f64 {
+        if self.visits > 0 {
+            self.wins / self.visits as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+fn outcome_value(outcome: Outcome) -> f64 {
+    match outcome {
+        Outcome::Win => 1.0,
+        Outcome::Loss => 0.0,
+        Outcome::Draw => 0.5,
+    }
+}
+
+struct Node<S: GameOutcome> {
+    state: S,
+    parent: Option<usize>,
+    the_move: Option<S::Move>,
+    children: Vec<usize>,
+    unvisited_moves: Vec<S::Move>,
+    visits: u32,
+    /// Sum of playout results for the player who made the move to this node
+    wins: f64,
+    virtual_loss: u32,
+}
+
+impl<S: GameOutcome> Node<S> {
+    fn new(state: S, parent: Option<usize>, the_move: Option<S::Move>) -> Self {
+        let mut unvisited_moves = if state.is_over() {
+            Vec::new()
+        } else {
+            state.valid_moves()
+        };
+        // Moves are expanded from the end, keep the order of valid_moves
+        unvisited_moves.reverse();
+        Self {
+            state,
+            parent,
+            the_move,
+            children: Vec::new(),
+            unvisited_moves,
+            visits: 0,
+            wins: 0.0,
+            virtual_loss: 0,
+        }
+    }
+}
+
+/// A search tree stored as an arena of nodes, the root at index 0
+struct Tree<S: GameOutcome> {
+    nodes: Vec<Node<S>>,
+}
+
+impl<S: GameOutcome + Clone> Tree<S> {
+    fn new(root: S) -> Self {
+        Self {
+            nodes: vec![Node::new(root, None, None)],
+        }
+    }
+
+    /// Descend from the root choosing children by UCT, and expand one new
+    /// node at the end of the path. Adds virtual loss to the nodes on the path.
+    fn select_leaf(&mut self, exploration: f64, virtual_loss: u32) -> usize {
+        let mut index = 0;
+        loop {
+            self.nodes[index].virtual_loss += virtual_loss;
+            if let Some(the_move) = self.nodes[index].unvisited_moves.pop() {
+                let state = self.nodes[index].state.apply_move(&the_move);
+                let child = self.nodes.len();
+                self.nodes.push(Node::new(state, Some(index), Some(the_move)));
+                self.nodes[index].children.push(child);
+                self.nodes[child].virtual_loss += virtual_loss;
+                return child;
+            }
+            match self.best_uct_child(index, exploration) {
+                Some(child) => index = child,
+                None => return index,
+            }
+        }
+    }
+
+    fn best_uct_child(&self, index: usize, exploration: f64) -> Option<usize> {
+        let node = &self.nodes[index];
+        let parent_visits = (node.visits + node.virtual_loss).max(1) as f64;
+        node.children.iter().copied().max_by(|a, b| {
+            let a = self.uct_score(*a, parent_visits, exploration);
+            let b = self.uct_score(*b, parent_visits, exploration);
+            a.partial_cmp(&b).unwrap()
+        })
+    }
+
+    fn uct_score(&self, index: usize, parent_visits: f64, exploration: f64) -> f64 {
+        let node = &self.nodes[index];
+        // Virtual losses count as visits without wins
+        let visits = (node.visits + node.virtual_loss).max(1) as f64;
+        node.wins / visits + exploration * (parent_visits.ln() / visits).sqrt()
+    }
+
+    /// Add the result of a playout from the leaf, given for the player who
+    /// made the move to the leaf, to the nodes on the path from the root and
+    /// remove their virtual loss
+    fn backpropagate(&mut self, leaf: usize, mut value: f64, virtual_loss: u32) {
+        let mut index = Some(leaf);
+        while let Some(i) = index {
+            let node = &mut self.nodes[i];
+            node.visits += 1;
+            node.wins += value;
+            node.virtual_loss -= virtual_loss;
+            value = 1.0 - value;
+            index = node.parent;
+        }
+    }
+
+    fn child_for_move(&self, index: usize, the_move: &S::Move) -> Option<usize> {
+        self.nodes[index]
+            .children
+            .iter()
+            .copied()
+            .find(|child| self.nodes[*child].the_move.as_ref() == Some(the_move))
+    }
+
+    /// The subtree rooted at the node, as a tree of its own
+    fn into_subtree(mut self, index: usize) -> Self {
+        let mut nodes = Vec::new();
+        // Pairs of (index in self, index of parent in the subtree)
+        let mut unexplored = vec![(index, None)];
+        while let Some((old_index, parent)) = unexplored.pop() {
+            let new_index = nodes.len();
+            let old = &mut self.nodes[old_index];
+            let children = std::mem::take(&mut old.children);
+            nodes.push(Node {
+                state: old.state.clone(),
+                parent,
+                the_move: old.the_move,
+                children: Vec::new(),
+                unvisited_moves: std::mem::take(&mut old.unvisited_moves),
+                visits: old.visits,
+                wins: old.wins,
+                virtual_loss: 0,
+            });
+            if let Some(parent) = parent {
+                let parent: &mut Node<S> = &mut nodes[parent];
+                parent.children.push(new_index);
+            }
+            unexplored.extend(children.into_iter().rev().map(|child| (child, Some(new_index))));
+        }
+        Self { nodes }
+    }
+
+    /// Grow the tree with a single thread until the number of playouts is
+    /// reached or `stop` is set
+    fn grow<P: Agent<S>>(&mut self, rounds: u32, config: &MctsConfig, policy: &mut P, stop: Option<&AtomicBool>) {
+        for _ in 0..rounds {
+            if stop.is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+                break;
+            }
+            let leaf = self.select_leaf(config.exploration, 0);
+            let value = playout(&self.nodes[leaf].state, policy, config.max_playout_moves);
+            self.backpropagate(leaf, value, 0);
+        }
+    }
+
+    fn root_statistics(&self) -> Vec<MoveStats<S::Move>> {
+        self.nodes[0]
+            .children
+            .iter()
+            .map(|child| {
+                let node = &self.nodes[*child];
+                MoveStats {
+                    the_move: node.the_move.unwrap(),
+                    visits: node.visits,
+                    wins: node.wins,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Play the game from the state with the policy and return the result for
+/// the player who made the move to the state
+fn playout<S, P>(state: &S, policy: &mut P, max_moves: usize) -> f64
+where
+    S: GameOutcome + Clone,
+    P: Agent<S>,
+{
+    let mut state = state.clone();
+    let mut moves = 0;
+    while !state.is_over() && moves < max_moves {
+        let the_move = policy.select_move(&state);
+        state = state.apply_move(&the_move);
+        moves += 1;
+    }
+    // The outcome is for the player to move at the end of the playout
+    let outcome = if moves % 2 == 0 {
+        state.outcome().reverse()
+    } else {
+        state.outcome()
+    };
+    outcome_value(outcome)
+}
+
+/// Search running on a background thread until stopped
+struct Ponder<S: GameOutcome> {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Tree<S>>,
+}
+
+impl<S: GameOutcome> Ponder<S> {
+    fn finish(self) -> Tree<S> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().expect("Pondering thread panicked")
+    }
+}
+
+/// Monte Carlo tree search agent. Playouts are played by agents created with
+/// `playout_policy` from a seed, one for each thread, e.g.
+/// `MctsBot::new(config, FastRandomBot::with_seed)` for Go.
+///
+/// When told about the moves played, the bot keeps the subtree of the new
+/// position for its next search. After its own moves, it can keep searching
+/// on a background thread while the opponent thinks. With root
+/// parallelization, only the tree of the first thread is kept.
+///
+/// With a single thread or root parallelization and without pondering, a bot
+/// created with `with_seed` replays its searches identically.
+pub struct MctsBot<S: GameOutcome, F> {
+    config: MctsConfig,
+    playout_policy: Arc<F>,
+    rng: AgentRng,
+    tree: Option<Tree<S>>,
+    ponder: Option<Ponder<S>>,
+    /// State of the latest move selection, to tell the bot's own moves from
+    /// the opponent's
+    own_turn: Option<S>,
+}
+
+impl<S, F, P> MctsBot<S, F>
+where
+    S: GameOutcome + Clone + PartialEq + Send + Sync + 'static,
+    S::Move: Send + Sync,
+    F: Fn(u64) -> P + Send + Sync + 'static,
+    P: Agent<S>,
+{
+    pub fn new(config: MctsConfig, playout_policy: F) -> Self {
+        Self::with_rng(config, playout_policy, AgentRng::from_entropy())
+    }
+
+    pub fn with_seed(config: MctsConfig, playout_policy: F, seed: u64) -> Self {
+        Self::with_rng(config, playout_policy, AgentRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(config: MctsConfig, playout_policy: F, rng: AgentRng) -> Self {
+        Self {
+            config,
+            playout_policy: Arc::new(playout_policy),
+            rng,
+            tree: None,
+            ponder: None,
+            own_turn: None,
+        }
+    }
+
+    /// Search the state and return the statistics of the root moves
+    pub fn search(&mut self, game_state: &S) -> Vec<MoveStats<S::Move>> {
+        self.stop_pondering();
+        let tree = match self.tree.take() {
+            Some(tree) if self.config.reuse_tree && tree.nodes[0].state == *game_state => tree,
+            _ => Tree::new(game_state.clone()),
+        };
+
+        let threads = self.config.threads.max(1);
+        let seeds: Vec<u64> = (0..threads).map(|_| self.rng.gen()).collect();
+        let (stats, tree) = match self.config.parallelism {
+            Parallelism::Root => self.search_root_parallel(tree, &seeds),
+            Parallelism::Tree => self.search_tree_parallel(tree, &seeds),
+        };
+        if self.config.reuse_tree {
+            self.tree = Some(tree);
+        }
+        stats
+    }
+
+    fn search_root_parallel(&self, tree: Tree<S>, seeds: &[u64]) -> (Vec<MoveStats<S::Move>>, Tree<S>) {
+        let config = &self.config;
+        let playout_policy = &*self.playout_policy;
+        let threads = seeds.len() as u32;
+        let root = tree.nodes[0].state.clone();
+        let mut first_tree = Some(tree);
+        let mut trees: Vec<Tree<S>> = thread::scope(|scope| {
+            let handles: Vec<_> = seeds
+                .iter()
+                .enumerate()
+                .map(|(i, seed)| {
+                    // Spread the remainder of the rounds over the first threads
+                    let rounds = config.rounds / threads + u32::from((i as u32) < config.rounds % threads);
+                    let mut tree = first_tree.take().unwrap_or_else(|| Tree::new(root.clone()));
+                    scope.spawn(move || {
+                        let mut policy = playout_policy(*seed);
+                        tree.grow(rounds, config, &mut policy, None);
+                        tree
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut merged: Vec<MoveStats<S::Move>> = Vec::new();
+        for stats in trees.iter().flat_map(|tree| tree.root_statistics()) {
+            match merged.iter_mut().find(|m| m.the_move == stats.the_move) {
+                Some(m) => {
+                    m.visits += stats.visits;
+                    m.wins += stats.wins;
+                }
+                None => merged.push(stats),
+            }
+        }
+        (merged, trees.swap_remove(0))
+    }
+
+    fn search_tree_parallel(&self, tree: Tree<S>, seeds: &[u64]) -> (Vec<MoveStats<S::Move>>, Tree<S>) {
+        let config = &self.config;
+        let playout_policy = &*self.playout_policy;
+        let tree = Mutex::new(tree);
+        let rounds_started = AtomicU32::new(0);
+        thread::scope(|scope| {
+            for seed in seeds {
+                let tree = &tree;
+                let rounds_started = &rounds_started;
+                scope.spawn(move || {
+                    let mut policy = playout_policy(*seed);
+                    while rounds_started.fetch_add(1, Ordering::Relaxed) < config.rounds {
+                        let (leaf, state) = {
+                            let mut tree = tree.lock().unwrap();
+                            let leaf = tree.select_leaf(config.exploration, config.virtual_loss);
+                            (leaf, tree.nodes[leaf].state.clone())
+                        };
+                        let value = playout(&state, &mut policy, config.max_playout_moves);
+                        tree.lock().unwrap().backpropagate(leaf, value, config.virtual_loss);
+                    }
+                });
+            }
+        });
+        let tree = tree.into_inner().unwrap();
+        (tree.root_statistics(), tree)
+    }
+
+    fn start_pondering(&mut self, game_state: S) {
+        let mut tree = match self.tree.take() {
+            Some(tree) if tree.nodes[0].state == game_state => tree,
+            _ => Tree::new(game_state),
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let playout_policy = Arc::clone(&self.playout_policy);
+        let config = self.config.clone();
+        let seed = self.rng.gen();
+        let handle = thread::spawn(move || {
+            let mut policy = playout_policy(seed);
+            tree.grow(config.ponder_rounds, &config, &mut policy, Some(&thread_stop));
+            tree
+        });
+        self.ponder = Some(Ponder { stop, handle });
+    }
+
+    fn stop_pondering(&mut self) {
+        if let Some(ponder) = self.ponder.take() {
+            self.tree = Some(ponder.finish());
+        }
+    }
+}
+
+impl<S: GameOutcome, F> Drop for MctsBot<S, F> {
+    fn drop(&mut self) {
+        if let Some(ponder) = self.ponder.take() {
+            ponder.finish();
+        }
+    }
+}
+
+/// The most visited move, ties broken by win rate
+fn most_visited<M: Copy>(stats: &[MoveStats<M>]) -> Option<M> {
+    stats
+        .iter()
+        .max_by(|a, b| {
+            a.visits
+                .cmp(&b.visits)
+                .then(a.win_rate().partial_cmp(&b.win_rate()).unwrap())
+        })
+        .map(|m| m.the_move)
+}
+
+impl<S, F, P> Agent<S> for MctsBot<S, F>
+where
+    S: GameOutcome + Clone + PartialEq + Send + Sync + 'static,
+    S::Move: Send + Sync,
+    F: Fn(u64) -> P + Send + Sync + 'static,
+    P: Agent<S>,
+{
+    fn select_move(&mut self, game_state: &S) -> S::Move {
+        let stats = self.search(game_state);
+        self.own_turn = Some(game_state.clone());
+        most_visited(&stats).expect("No valid moves")
+    }
+
+    fn game_started(&mut self, _game_state: &S) {
+        self.stop_pondering();
+        self.tree = None;
+        self.own_turn = None;
+    }
+
+    fn move_played(&mut self, game_state: &S, the_move: &S::Move) {
+        self.stop_pondering();
+        let own_move = self.own_turn.take().is_some_and(|state| state == *game_state);
+        self.tree = self.tree.take().and_then(|tree| {
+            if tree.nodes[0].state != *game_state {
+                return None;
+            }
+            let child = tree.child_for_move(0, the_move)?;
+            Some(tree.into_subtree(child))
+        });
+
+        if own_move && self.config.ponder_rounds > 0 {
+            let next_state = game_state.apply_move(the_move);
+            if !next_state.is_over() {
+                self.start_pondering(next_state);
+            }
+        }
+    }
+
+    fn game_ended(&mut self, _game_state: &S) {
+        self.stop_pondering();
+        self.tree = None;
+        self.own_turn = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{FastRandomBot, RandomBot};
+    use crate::game::go::{Board, GoState, Move as GoMove, Player, Point};
+    use std::str::FromStr;
+    use crate::game::one_two_three::*;
+    use crate::game::GameState;
+
+    fn config(rounds: u32, threads: usize, parallelism: Parallelism) -> MctsConfig {
+        MctsConfig {
+            rounds,
+            threads,
+            parallelism,
+            ..MctsConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_mcts_finds_winning_move_in_one_two_three() {
+        let game = OneTwoThreeState::new();
+        for parallelism in [Parallelism::Root, Parallelism::Tree] {
+            let mut bot = MctsBot::with_seed(config(2000, 2, parallelism), RandomBot::with_seed, 1);
+            assert_eq!(bot.select_move(&game), Move::Three, "{:?}", parallelism);
+        }
+    }
+
+    #[test]
+    fn test_all_rounds_are_played() {
+        let game = OneTwoThreeState::new();
+        for parallelism in [Parallelism::Root, Parallelism::Tree] {
+            let mut bot = MctsBot::with_seed(config(101, 4, parallelism), RandomBot::with_seed, 1);
+            let visits: u32 = bot.search(&game).iter().map(|m| m.visits).sum();
+            assert_eq!(visits, 101, "{:?}", parallelism);
+        }
+    }
+
+    #[test]
+    fn test_seeded_root_parallel_search_is_reproducible() {
+        let game = GoState::new(5);
+        let search = || {
+            let mut bot = MctsBot::with_seed(config(200, 3, Parallelism::Root), FastRandomBot::with_seed, 7);
+            bot.search(&game)
+        };
+        assert_eq!(search(), search());
+    }
+
+    #[test]
+    fn test_mcts_captures_group_in_atari() {
+        // Both groups have a single liberty, the player to move wins by capturing
+        let board = r#"
+        oooo.
+        ooooo
+        ooooo
+        xxxxx
+        xxxx."#;
+        let board = Board::from_str(board).unwrap();
+        let game = GoState::from_board(board, Player::black());
+        let mut bot = MctsBot::with_seed(config(500, 2, Parallelism::Tree), FastRandomBot::with_seed, 3);
+        assert_eq!(bot.select_move(&game), GoMove::Play(Point::new(1, 5)));
+    }
+
+    #[test]
+    fn test_subtree_of_played_moves_is_reused() {
+        let game = OneTwoThreeState::new();
+        let mut bot = MctsBot::with_seed(config(300, 1, Parallelism::Root), RandomBot::with_seed, 1);
+        bot.game_started(&game);
+        let the_move = bot.select_move(&game);
+        bot.move_played(&game, &the_move);
+        let game = game.apply_move(&the_move);
+        bot.move_played(&game, &Move::One);
+        let game = game.apply_move(&Move::One);
+
+        let reused_visits = bot.tree.as_ref().unwrap().nodes[0].visits;
+        assert!(reused_visits > 0);
+        let visits: u32 = bot.search(&game).iter().map(|m| m.visits).sum();
+        assert_eq!(visits, reused_visits - 1 + 300);
+    }
+
+    #[test]
+    fn test_tree_of_another_position_is_not_reused() {
+        let game = OneTwoThreeState::new();
+        let mut bot = MctsBot::with_seed(config(100, 1, Parallelism::Tree), RandomBot::with_seed, 1);
+        bot.search(&game);
+        let other = game.apply_move(&Move::Two);
+        let visits: u32 = bot.search(&other).iter().map(|m| m.visits).sum();
+        assert_eq!(visits, 100);
+    }
+
+    #[test]
+    fn test_pondering_searches_while_the_opponent_thinks() {
+        let game = GoState::new(5);
+        let config = MctsConfig {
+            ponder_rounds: 50,
+            ..config(50, 1, Parallelism::Root)
+        };
+        let mut bot = MctsBot::with_seed(config, FastRandomBot::with_seed, 1);
+        bot.game_started(&game);
+        let the_move = bot.select_move(&game);
+        bot.move_played(&game, &the_move);
+        let game = game.apply_move(&the_move);
+        assert!(bot.ponder.is_some());
+
+        // Wait for the pondering to finish before the opponent's move
+        let tree = bot.ponder.take().unwrap().handle.join().unwrap();
+        assert_eq!(tree.nodes[0].state, game);
+        assert!(tree.nodes[0].visits >= 50);
+        bot.tree = Some(tree);
+
+        // No pondering on the bot's own turn
+        let reply = game.valid_moves()[0];
+        bot.move_played(&game, &reply);
+        assert!(bot.ponder.is_none());
+        bot.game_ended(&game.apply_move(&reply));
+        assert!(bot.tree.is_none());
+    }
+
+    #[test]
+    fn test_dropping_the_bot_stops_pondering() {
+        let game = GoState::new(5);
+        let config = MctsConfig {
+            ponder_rounds: u32::MAX,
+            ..config(10, 1, Parallelism::Root)
+        };
+        let mut bot = MctsBot::with_seed(config, FastRandomBot::with_seed, 1);
+        let the_move = bot.select_move(&game);
+        bot.move_played(&game, &the_move);
+        let stop = Arc::clone(&bot.ponder.as_ref().unwrap().stop);
+        drop(bot);
+
+        assert!(stop.load(Ordering::Relaxed));
+        // The pondering thread has released its handle of the flag
+        assert_eq!(Arc::strong_count(&stop), 1);
+    }
+}
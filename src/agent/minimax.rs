@@ -1,7 +1,18 @@
-use crate::game::GameState;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Instant;
+
+use crate::agent::ordering::{MoveOrderHint, MoveOrdering};
+use crate::agent::search::{NullObserver, SearchInfo, SearchObserver};
+use crate::agent::Evaluator;
+use crate::game::{GameState, StateHash};
 
 type MoveValue = i32;
 
+/// Bound of the search window, larger than any evaluation. Kept below
+/// `i32::MAX` so that null windows around it can't overflow.
+const INFINITY: MoveValue = i32::MAX - 1;
+
 #[derive(Clone, Copy, Debug)]
 pub struct OptimalMove<T> {
     pub best_move: Option<T>,
@@ -17,38 +28,513 @@ impl<T> OptimalMove<T> {
     }
 }
 
-pub fn minimax<S: GameState>(game: &S, ply: u32, eval_fn: fn(&S) -> MoveValue) -> OptimalMove<S::Move> {
-    // See PAIP 18.4 Searching ahead: Minimax
+pub fn minimax<S: GameState, E: Evaluator<S> + ?Sized>(game: &S, ply: u32, evaluator: &mut E) -> OptimalMove<S::Move> {
+    minimax_observed(game, ply, evaluator, &mut NullObserver)
+}
+
+/// Like `minimax`, but reports statistics of the search to the observer
+pub fn minimax_observed<S, E, O>(game: &S, ply: u32, evaluator: &mut E, observer: &mut O) -> OptimalMove<S::Move>
+where
+    S: GameState,
+    E: Evaluator<S> + ?Sized,
+    O: SearchObserver<S::Move> + ?Sized,
+{
+    let start = Instant::now();
+    let mut nodes = 1;
+
     if ply == 0 || game.is_over() {
-        println!("FOUND WINNING MOVE!");
-        return OptimalMove::new(None, eval_fn(game));
+        let value = evaluator.evaluate(game);
+        observer.on_search_info(&SearchInfo {
+            depth: ply,
+            nodes,
+            elapsed: start.elapsed(),
+            principal_variation: Vec::new(),
+            move_scores: Vec::new(),
+            cutoffs: 0,
+            first_move_cutoffs: 0,
+        });
+        return OptimalMove::new(None, value);
     }
 
-    let mut results: Vec<_> =  game.valid_moves()
+    let mut results: Vec<_> = game.valid_moves()
         .into_iter()
         .map(|the_move| {
             let next_gamestate = game.apply_move(&the_move);
-            let OptimalMove {value, ..} = minimax(&next_gamestate, ply - 1, eval_fn);
+            let (value, mut variation) = search(&next_gamestate, ply - 1, evaluator, &mut nodes);
+            variation.insert(0, the_move);
             // Negate because zero-sum game => worst for opponent is best for me
-            let result = OptimalMove::new(Some(the_move), -value);
-            result
+            (OptimalMove::new(Some(the_move), -value), variation)
         })
         .collect();
 
-    results.sort_by(|a, b| b.value.cmp(&a.value));
-    println!("{:?}", results);
-    results[0]
+    results.sort_by_key(|(r, _)| std::cmp::Reverse(r.value));
+    let (best, principal_variation) = results[0].clone();
+
+    observer.on_search_info(&SearchInfo {
+        depth: ply,
+        nodes,
+        elapsed: start.elapsed(),
+        principal_variation,
+        move_scores: results
+            .iter()
+            .map(|(r, _)| (r.best_move.unwrap(), r.value))
+            .collect(),
+        cutoffs: 0,
+        first_move_cutoffs: 0,
+    });
+    best
+}
+
+/// Return the minimax value of the game for the player to move, and the
+/// principal variation leading to it
+fn search<S, E>(game: &S, ply: u32, evaluator: &mut E, nodes: &mut u64) -> (MoveValue, Vec<S::Move>)
+where
+    S: GameState,
+    E: Evaluator<S> + ?Sized,
+{
+    // See PAIP 18.4 Searching ahead: Minimax
+    *nodes += 1;
+    if ply == 0 || game.is_over() {
+        return (evaluator.evaluate(game), Vec::new());
+    }
+
+    let mut best: Option<(MoveValue, Vec<S::Move>)> = None;
+    for the_move in game.valid_moves() {
+        let next_gamestate = game.apply_move(&the_move);
+        let (value, mut variation) = search(&next_gamestate, ply - 1, evaluator, nodes);
+        let value = -value;
+        if best.as_ref().is_none_or(|(best_value, _)| value > *best_value) {
+            variation.insert(0, the_move);
+            best = Some((value, variation));
+        }
+    }
+    best.expect("No valid moves")
+}
+
+/// State shared by the nodes of one alpha-beta style search
+struct SearchContext<'a, S: GameState, E: ?Sized, H> {
+    evaluator: &'a mut E,
+    ordering: &'a mut MoveOrdering<S, H>,
+    start: Instant,
+    nodes: u64,
+    cutoffs: u64,
+    first_move_cutoffs: u64,
+}
+
+impl<'a, S, E, H> SearchContext<'a, S, E, H>
+where
+    S: GameState,
+    S::Move: Hash + Eq,
+    E: Evaluator<S> + ?Sized,
+    H: MoveOrderHint<S>,
+{
+    fn new(evaluator: &'a mut E, ordering: &'a mut MoveOrdering<S, H>) -> Self {
+        Self {
+            evaluator,
+            ordering,
+            start: Instant::now(),
+            nodes: 0,
+            cutoffs: 0,
+            first_move_cutoffs: 0,
+        }
+    }
+
+    /// Record a cutoff caused by the `move_index`th move searched
+    fn cutoff(&mut self, the_move: S::Move, move_index: usize, ply: usize, depth: u32) {
+        self.cutoffs += 1;
+        if move_index == 0 {
+            self.first_move_cutoffs += 1;
+        }
+        self.ordering.record_cutoff(the_move, ply, depth);
+    }
+
+    fn search_info(&self, depth: u32, principal_variation: Vec<S::Move>, move_scores: Vec<(S::Move, MoveValue)>) -> SearchInfo<S::Move> {
+        SearchInfo {
+            depth,
+            nodes: self.nodes,
+            elapsed: self.start.elapsed(),
+            principal_variation,
+            move_scores,
+            cutoffs: self.cutoffs,
+            first_move_cutoffs: self.first_move_cutoffs,
+        }
+    }
+}
+
+/// Iterative deepening principal variation search (NegaScout). Each
+/// iteration searches with an aspiration window of `aspiration_window` around
+/// the value of the previous iteration, and re-searches with an open bound if
+/// the value falls outside of it. Moves are searched in the order given by
+/// `ordering`, with the principal variation of the previous iteration as the
+/// hash moves. The observer is notified after every iteration; the scores of
+/// root moves other than the best one are upper bounds only.
+///
+/// Returns the same value as `minimax`, but visits far fewer nodes.
+pub fn principal_variation_search<S, E, H, O>(
+    game: &S,
+    ply: u32,
+    aspiration_window: MoveValue,
+    evaluator: &mut E,
+    ordering: &mut MoveOrdering<S, H>,
+    observer: &mut O,
+) -> OptimalMove<S::Move>
+where
+    S: GameState,
+    S::Move: Hash + Eq,
+    E: Evaluator<S> + ?Sized,
+    H: MoveOrderHint<S>,
+    O: SearchObserver<S::Move> + ?Sized,
+{
+    let mut context = SearchContext::new(evaluator, ordering);
+    context.nodes = 1;
+
+    if ply == 0 || game.is_over() {
+        let value = context.evaluator.evaluate(game);
+        observer.on_search_info(&context.search_info(ply, Vec::new(), Vec::new()));
+        return OptimalMove::new(None, value);
+    }
+
+    let mut moves = game.valid_moves();
+    let mut best = OptimalMove::new(None, 0);
+    let mut principal_variation = Vec::new();
+    for depth in 1..=ply {
+        let (mut alpha, mut beta) = if depth > 1 && aspiration_window > 0 {
+            (
+                best.value.saturating_sub(aspiration_window).max(-INFINITY),
+                best.value.saturating_add(aspiration_window).min(INFINITY),
+            )
+        } else {
+            (-INFINITY, INFINITY)
+        };
+        context.ordering.order_moves(game, &mut moves, 0, principal_variation.first().copied());
+
+        let (value, variation, mut move_scores) = loop {
+            let (value, variation, move_scores) = pvs_root(game, &moves, depth, alpha, beta, &principal_variation, &mut context);
+            if value <= alpha && alpha > -INFINITY {
+                // Fail low, the true value is below the window
+                alpha = -INFINITY;
+            } else if value >= beta && beta < INFINITY {
+                // Fail high, the true value is above the window
+                beta = INFINITY;
+            } else {
+                break (value, variation, move_scores);
+            }
+        };
+
+        principal_variation = variation;
+        best = OptimalMove::new(Some(principal_variation[0]), value);
+        move_scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        observer.on_search_info(&context.search_info(depth, principal_variation.clone(), move_scores));
+    }
+    best
+}
+
+/// Search the root moves in the given order, returning the value, the
+/// principal variation and the score of each move
+#[allow(clippy::type_complexity)]
+fn pvs_root<S, E, H>(
+    game: &S,
+    moves: &[S::Move],
+    depth: u32,
+    mut alpha: MoveValue,
+    beta: MoveValue,
+    previous_variation: &[S::Move],
+    context: &mut SearchContext<S, E, H>,
+) -> (MoveValue, Vec<S::Move>, Vec<(S::Move, MoveValue)>)
+where
+    S: GameState,
+    S::Move: Hash + Eq,
+    E: Evaluator<S> + ?Sized,
+    H: MoveOrderHint<S>,
+{
+    let mut best_value = -INFINITY;
+    let mut best_variation = Vec::new();
+    let mut move_scores = Vec::with_capacity(moves.len());
+    for (i, the_move) in moves.iter().enumerate() {
+        let next_gamestate = game.apply_move(the_move);
+        let hint = variation_tail(previous_variation, the_move);
+        let (value, variation) = pvs_child(&next_gamestate, depth - 1, 1, alpha, beta, i == 0, hint, context);
+        move_scores.push((*the_move, value));
+        if value > best_value || best_variation.is_empty() {
+            best_value = value;
+            best_variation = variation;
+            best_variation.insert(0, *the_move);
+        }
+        alpha = alpha.max(value);
+        if alpha >= beta {
+            context.cutoff(*the_move, i, 0, depth);
+            break;
+        }
+    }
+    (best_value, best_variation, move_scores)
+}
+
+/// Search a child node from the parent's point of view. The first child is
+/// searched with the full window, the rest with a null window that is
+/// widened only if the move turns out to be better than the current best.
+#[allow(clippy::too_many_arguments)]
+fn pvs_child<S, E, H>(
+    child: &S,
+    depth: u32,
+    ply: usize,
+    alpha: MoveValue,
+    beta: MoveValue,
+    is_first: bool,
+    previous_variation: &[S::Move],
+    context: &mut SearchContext<S, E, H>,
+) -> (MoveValue, Vec<S::Move>)
+where
+    S: GameState,
+    S::Move: Hash + Eq,
+    E: Evaluator<S> + ?Sized,
+    H: MoveOrderHint<S>,
+{
+    if !is_first {
+        let (value, variation) = pvs(child, depth, ply, -alpha - 1, -alpha, previous_variation, context);
+        let value = -value;
+        if value <= alpha || value >= beta {
+            return (value, variation);
+        }
+    }
+    let (value, variation) = pvs(child, depth, ply, -beta, -alpha, previous_variation, context);
+    (-value, variation)
+}
+
+/// Fail-soft negamax principal variation search within the window
+/// (alpha, beta), `ply` plies from the root
+fn pvs<S, E, H>(
+    game: &S,
+    depth: u32,
+    ply: usize,
+    mut alpha: MoveValue,
+    beta: MoveValue,
+    previous_variation: &[S::Move],
+    context: &mut SearchContext<S, E, H>,
+) -> (MoveValue, Vec<S::Move>)
+where
+    S: GameState,
+    S::Move: Hash + Eq,
+    E: Evaluator<S> + ?Sized,
+    H: MoveOrderHint<S>,
+{
+    context.nodes += 1;
+    if depth == 0 || game.is_over() {
+        return (context.evaluator.evaluate(game), Vec::new());
+    }
+
+    let mut moves = game.valid_moves();
+    context.ordering.order_moves(game, &mut moves, ply, previous_variation.first().copied());
+
+    let mut best_value = -INFINITY;
+    let mut best_variation = Vec::new();
+    for (i, the_move) in moves.into_iter().enumerate() {
+        let next_gamestate = game.apply_move(&the_move);
+        let hint = variation_tail(previous_variation, &the_move);
+        let (value, mut variation) = pvs_child(&next_gamestate, depth - 1, ply + 1, alpha, beta, i == 0, hint, context);
+        if value > best_value {
+            best_value = value;
+            variation.insert(0, the_move);
+            best_variation = variation;
+        }
+        alpha = alpha.max(value);
+        if alpha >= beta {
+            context.cutoff(the_move, i, ply, depth);
+            break;
+        }
+    }
+    (best_value, best_variation)
+}
+
+/// The rest of the variation after the move, if the variation starts with it
+fn variation_tail<'v, T: PartialEq>(variation: &'v [T], the_move: &T) -> &'v [T] {
+    match variation.split_first() {
+        Some((first, tail)) if first == the_move => tail,
+        _ => &[],
+    }
+}
+
+/// Bounds of a state's value found by a search of the given depth
+#[derive(Clone, Copy, Debug)]
+struct MemoryEntry<T> {
+    depth: u32,
+    lower: MoveValue,
+    upper: MoveValue,
+    best_move: Option<T>,
+}
+
+type Memory<T> = HashMap<u64, MemoryEntry<T>>;
+
+/// Iterative deepening MTD(f) search (Plaat et al. 1996). Each iteration
+/// converges on the minimax value with repeated zero-window alpha-beta
+/// searches, starting from the value of the previous iteration. The searches
+/// share a memory table of value bounds keyed on `StateHash::state_hash`,
+/// whose best moves are searched first.
+///
+/// The observer is notified after every iteration. MTD(f) does not score
+/// root moves separately, so only the best move is reported.
+pub fn mtdf<S, E, H, O>(game: &S, ply: u32, evaluator: &mut E, ordering: &mut MoveOrdering<S, H>, observer: &mut O) -> OptimalMove<S::Move>
+where
+    S: GameState + StateHash,
+    S::Move: Hash + Eq,
+    E: Evaluator<S> + ?Sized,
+    H: MoveOrderHint<S>,
+    O: SearchObserver<S::Move> + ?Sized,
+{
+    let mut context = SearchContext::new(evaluator, ordering);
+    let mut memory = Memory::new();
+
+    if ply == 0 || game.is_over() {
+        context.nodes = 1;
+        let value = context.evaluator.evaluate(game);
+        observer.on_search_info(&context.search_info(ply, Vec::new(), Vec::new()));
+        return OptimalMove::new(None, value);
+    }
+
+    let mut best = OptimalMove::new(None, 0);
+    for depth in 1..=ply {
+        let mut value = best.value;
+        let mut lower = -INFINITY;
+        let mut upper = INFINITY;
+        let mut best_move = None;
+        while lower < upper {
+            let beta = value.max(lower + 1);
+            value = alpha_beta_with_memory(game, depth, 0, beta - 1, beta, &mut memory, &mut context);
+            if value < beta {
+                upper = value;
+            } else {
+                lower = value;
+                // A fail high proves the root's best move to be at least this good
+                best_move = memory[&game.state_hash()].best_move;
+            }
+        }
+        best = OptimalMove::new(best_move, value);
+
+        let principal_variation = principal_variation_from_memory(game, depth, &memory);
+        let move_scores = best_move.into_iter().map(|m| (m, value)).collect();
+        observer.on_search_info(&context.search_info(depth, principal_variation, move_scores));
+    }
+    best
+}
+
+/// Fail-soft negamax alpha-beta search that stores the bounds it finds in
+/// memory and uses them to narrow the window of later searches
+fn alpha_beta_with_memory<S, E, H>(
+    game: &S,
+    depth: u32,
+    ply: usize,
+    mut alpha: MoveValue,
+    mut beta: MoveValue,
+    memory: &mut Memory<S::Move>,
+    context: &mut SearchContext<S, E, H>,
+) -> MoveValue
+where
+    S: GameState + StateHash,
+    S::Move: Hash + Eq,
+    E: Evaluator<S> + ?Sized,
+    H: MoveOrderHint<S>,
+{
+    context.nodes += 1;
+    let hash = game.state_hash();
+    let stored = memory.get(&hash).copied();
+    if let Some(entry) = stored.filter(|entry| entry.depth == depth) {
+        if entry.lower >= beta {
+            return entry.lower;
+        }
+        if entry.upper <= alpha {
+            return entry.upper;
+        }
+        alpha = alpha.max(entry.lower);
+        beta = beta.min(entry.upper);
+    }
+
+    let mut best_move = None;
+    let value = if depth == 0 || game.is_over() {
+        context.evaluator.evaluate(game)
+    } else {
+        let mut moves = game.valid_moves();
+        context.ordering.order_moves(game, &mut moves, ply, stored.and_then(|entry| entry.best_move));
+
+        let mut value = -INFINITY;
+        let mut a = alpha;
+        for (i, the_move) in moves.into_iter().enumerate() {
+            let next_gamestate = game.apply_move(&the_move);
+            let child_value = -alpha_beta_with_memory(&next_gamestate, depth - 1, ply + 1, -beta, -a, memory, context);
+            if child_value > value || best_move.is_none() {
+                value = child_value;
+                best_move = Some(the_move);
+            }
+            a = a.max(value);
+            if value >= beta {
+                context.cutoff(the_move, i, ply, depth);
+                break;
+            }
+        }
+        value
+    };
+
+    let mut entry = match memory.get(&hash) {
+        Some(entry) if entry.depth == depth => *entry,
+        _ => MemoryEntry { depth, lower: -INFINITY, upper: INFINITY, best_move: None },
+    };
+    if value <= alpha {
+        entry.upper = value;
+    } else if value < beta {
+        entry.lower = value;
+        entry.upper = value;
+    } else {
+        entry.lower = value;
+    }
+    entry.best_move = best_move.or(entry.best_move);
+    memory.insert(hash, entry);
+
+    value
+}
+
+/// Follow the best moves stored in memory from the root
+fn principal_variation_from_memory<S>(game: &S, depth: u32, memory: &Memory<S::Move>) -> Vec<S::Move>
+where
+    S: GameState + StateHash,
+{
+    let mut variation = Vec::new();
+    let mut state: Option<S> = None;
+    for d in (1..=depth).rev() {
+        let current = state.as_ref().unwrap_or(game);
+        match memory.get(&current.state_hash()) {
+            Some(MemoryEntry { depth, best_move: Some(the_move), .. }) if *depth == d => {
+                variation.push(*the_move);
+                state = Some(current.apply_move(the_move));
+            }
+            _ => break,
+        }
+    }
+    variation
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::game::one_two_three::*;
+    use crate::game::go::{self, Board, GoState, Player, Point};
+    use crate::game::go::Move as GoMove;
+    use std::str::FromStr;
+
+    /// Small Go positions for validating searches against minimax
+    pub(crate) fn go_test_positions() -> Vec<GoState> {
+        let empty = GoState::new(3);
+        let opening = empty
+            .apply_move(&GoMove::Play(Point::new(2, 2)))
+            .apply_move(&GoMove::Play(Point::new(1, 2)));
+        let fight = opening
+            .apply_move(&GoMove::Play(Point::new(1, 1)))
+            .apply_move(&GoMove::Play(Point::new(2, 3)))
+            .apply_move(&GoMove::Play(Point::new(1, 3)));
+        vec![empty, opening, fight]
+    }
 
     #[test]
     fn test_one_ply_minimax_equals_selecting_move_with_best_evaluation_function_result() {
         let game = OneTwoThreeState::new();
-        let optimal_move = minimax(&game, 1, score_difference);
+        let optimal_move = minimax(&game, 1, &mut score_difference);
 
         assert_eq!(optimal_move.best_move, Some(Move::Three));
         assert_eq!(optimal_move.value, 3);
@@ -57,7 +543,7 @@ mod tests {
     #[test]
     fn test_two_ply_minimax_subtracts_the_opponents_best_move() {
         let game = OneTwoThreeState::new();
-        let optimal_move = minimax(&game, 2, score_difference);
+        let optimal_move = minimax(&game, 2, &mut score_difference);
 
         assert_eq!(optimal_move.best_move, Some(Move::Three));
         assert_eq!(optimal_move.value, 0);
@@ -66,9 +552,134 @@ mod tests {
     #[test]
     fn test_five_ply_minimax_with_one_two_three_game_finds_winning_move() {
         let game = OneTwoThreeState::new();
-        let optimal_move = minimax(&game, 5, score_difference);
+        let optimal_move = minimax(&game, 5, &mut score_difference);
 
         assert_eq!(optimal_move.best_move, Some(Move::Three));
         assert_eq!(optimal_move.value, 3);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_minimax_reports_search_info() {
+        let game = OneTwoThreeState::new();
+        let mut infos = Vec::new();
+        let optimal_move = minimax_observed(&game, 2, &mut score_difference, &mut |info: &SearchInfo<Move>| infos.push(info.clone()));
+
+        assert_eq!(infos.len(), 1);
+        let info = &infos[0];
+        assert_eq!(info.depth, 2);
+        assert_eq!(info.nodes, 1 + 3 + 9);
+        assert_eq!(info.principal_variation, vec![Move::Three, Move::Three]);
+        assert_eq!(info.move_scores.len(), 3);
+        assert_eq!(info.move_scores[0], (Move::Three, optimal_move.value));
+    }
+
+    #[test]
+    fn test_principal_variation_search_matches_minimax_on_one_two_three() {
+        let game = OneTwoThreeState::new();
+        for ply in 1..=6 {
+            for window in [0, 1, 5] {
+                let expected = minimax(&game, ply, &mut score_difference);
+                let result = principal_variation_search(&game, ply, window, &mut score_difference, &mut MoveOrdering::new(), &mut NullObserver);
+                assert_eq!(result.value, expected.value, "ply {} window {}", ply, window);
+                assert_eq!(result.best_move, Some(Move::Three));
+            }
+        }
+    }
+
+    #[test]
+    fn test_principal_variation_search_matches_minimax_on_go() {
+        for game in go_test_positions() {
+            for ply in 1..=3 {
+                let expected = minimax(&game, ply, &mut go::stone_difference);
+                let result = principal_variation_search(&game, ply, 2, &mut go::stone_difference, &mut MoveOrdering::new(), &mut NullObserver);
+                assert_eq!(result.value, expected.value, "ply {}", ply);
+                // Ties may be broken differently, but the move must be as good
+                let after_move = game.apply_move(&result.best_move.unwrap());
+                let move_value = -minimax(&after_move, ply - 1, &mut go::stone_difference).value;
+                assert_eq!(move_value, expected.value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_principal_variation_search_visits_fewer_nodes_than_minimax() {
+        let game = GoState::new(3);
+        let mut minimax_nodes = 0;
+        minimax_observed(&game, 3, &mut go::stone_difference, &mut |info: &SearchInfo<GoMove>| minimax_nodes = info.nodes);
+        let mut pvs_nodes = 0;
+        principal_variation_search(&game, 3, 2, &mut go::stone_difference, &mut MoveOrdering::new(), &mut |info: &SearchInfo<GoMove>| pvs_nodes = info.nodes);
+        assert!(pvs_nodes < minimax_nodes, "{} >= {}", pvs_nodes, minimax_nodes);
+    }
+
+    #[test]
+    fn test_mtdf_matches_minimax_on_one_two_three() {
+        let game = OneTwoThreeState::new();
+        for ply in 1..=6 {
+            let expected = minimax(&game, ply, &mut score_difference);
+            let result = mtdf(&game, ply, &mut score_difference, &mut MoveOrdering::new(), &mut NullObserver);
+            assert_eq!(result.value, expected.value, "ply {}", ply);
+            assert_eq!(result.best_move, Some(Move::Three));
+        }
+    }
+
+    #[test]
+    fn test_mtdf_matches_minimax_on_go() {
+        for game in go_test_positions() {
+            for ply in 1..=3 {
+                let expected = minimax(&game, ply, &mut go::stone_difference);
+                let result = mtdf(&game, ply, &mut go::stone_difference, &mut MoveOrdering::new(), &mut NullObserver);
+                assert_eq!(result.value, expected.value, "ply {}", ply);
+                let after_move = game.apply_move(&result.best_move.unwrap());
+                let move_value = -minimax(&after_move, ply - 1, &mut go::stone_difference).value;
+                assert_eq!(move_value, expected.value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mtdf_tells_apart_positions_that_differ_only_in_captures() {
+        let board = Board::from_str(".x.\nxo.\n...").unwrap();
+        let game = GoState::from_board(board.clone(), Player::white());
+        let mut captured = Player::white();
+        captured.captured = 2;
+        let game_with_captures = GoState::from_board(board, captured);
+        assert_ne!(game.state_hash(), game_with_captures.state_hash());
+
+        for ply in 1..=3 {
+            let search = |game: &GoState| {
+                mtdf(game, ply, &mut go::stone_difference, &mut MoveOrdering::new(), &mut NullObserver).value
+            };
+            let value = search(&game);
+            let value_with_captures = search(&game_with_captures);
+            assert_eq!(value, minimax(&game, ply, &mut go::stone_difference).value, "ply {}", ply);
+            assert_eq!(value_with_captures, value + 2, "ply {}", ply);
+        }
+    }
+
+    #[test]
+    fn test_mtdf_reports_principal_variation() {
+        let game = OneTwoThreeState::new();
+        let mut infos = Vec::new();
+        mtdf(&game, 3, &mut score_difference, &mut MoveOrdering::new(), &mut |info: &SearchInfo<Move>| infos.push(info.clone()));
+
+        assert_eq!(infos.len(), 3);
+        let info = infos.last().unwrap();
+        assert_eq!(info.depth, 3);
+        assert_eq!(info.principal_variation.first(), Some(&Move::Three));
+        assert!(!info.principal_variation.is_empty());
+    }
+
+    #[test]
+    fn test_search_with_move_ordering_reports_pruning_statistics() {
+        let game = GoState::new(3);
+        let mut ordering = MoveOrdering::with_hint(go::move_order_hint);
+        let mut last_info = None;
+        principal_variation_search(&game, 3, 2, &mut go::stone_difference, &mut ordering, &mut |info: &SearchInfo<GoMove>| last_info = Some(info.clone()));
+
+        let info = last_info.unwrap();
+        assert!(info.cutoffs > 0);
+        assert!(info.first_move_cutoffs <= info.cutoffs);
+        // The center is the natural first move on an empty board
+        assert_eq!(info.principal_variation[0], GoMove::Play(Point::new(2, 2)));
+    }
+}
@@ -1,25 +1,95 @@
 pub mod minimax;
+pub mod fast_random;
+pub mod evaluator;
+pub mod search;
+pub mod ordering;
+pub mod mcts;
+pub mod rave;
+pub mod puct;
+pub mod human;
+pub mod spec;
+pub mod policy;
+pub mod q;
+pub mod actor_critic;
 
-use crate::game::GameState;
-use rand::rngs::ThreadRng;
-use rand::Rng;
+pub use fast_random::FastRandomBot;
+pub use evaluator::Evaluator;
+pub use search::{NullObserver, SearchInfo, SearchObserver};
+pub use ordering::{MoveOrdering, MoveOrderHint, NoOrderHint};
+pub use mcts::{MctsBot, MctsConfig, Parallelism};
+pub use rave::{RaveBot, RaveConfig};
+pub use human::HumanAgent;
+pub use spec::AgentSpec;
+pub use policy::PolicyAgent;
+pub use q::QAgent;
+pub use actor_critic::ActorCriticAgent;
+pub use puct::{PolicyValue, PolicyValueEvaluator, PuctBot, PuctConfig, UniformEvaluator};
+
+use std::hash::Hash;
+
+use crate::game::{GameState, StateHash};
+use rand::{Rng, SeedableRng};
+
+/// Random number generator used by all stochastic agents. Seeding it makes
+/// the agent's choices reproducible.
+pub type AgentRng = rand_pcg::Pcg64;
 
 pub trait Agent<S: GameState> {
     fn select_move(&mut self, game_state: &S) -> S::Move;
+
+    /// Called when a new game starts from the state
+    fn game_started(&mut self, _game_state: &S) {}
+
+    /// Called after a move is played in the game, by either player. The
+    /// state is the one before the move.
+    fn move_played(&mut self, _game_state: &S, _the_move: &S::Move) {}
+
+    /// Called when the game has ended in the state
+    fn game_ended(&mut self, _game_state: &S) {}
+}
+
+impl<S: GameState, A: Agent<S> + ?Sized> Agent<S> for Box<A> {
+    fn select_move(&mut self, game_state: &S) -> S::Move {
+        (**self).select_move(game_state)
+    }
+
+    fn game_started(&mut self, game_state: &S) {
+        (**self).game_started(game_state)
+    }
+
+    fn move_played(&mut self, game_state: &S, the_move: &S::Move) {
+        (**self).move_played(game_state, the_move)
+    }
+
+    fn game_ended(&mut self, game_state: &S) {
+        (**self).game_ended(game_state)
+    }
 }
 
 pub struct RandomBot {
-    rng: ThreadRng,
+    rng: AgentRng,
 }
 
 impl RandomBot {
     pub fn new() -> Self {
         Self {
-            rng: rand::thread_rng(),
+            rng: AgentRng::from_entropy(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            rng: AgentRng::seed_from_u64(seed),
         }
     }
 }
 
+impl Default for RandomBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<S: GameState> Agent<S> for RandomBot {
     fn select_move(&mut self, game_state: &S) -> S::Move {
         // Generate valid candidates
@@ -29,22 +99,211 @@ impl<S: GameState> Agent<S> for RandomBot {
     }
 }
 
-pub struct MinimaxBot<S: GameState> {
+pub struct MinimaxBot<E, O = NullObserver> {
     plies: u32,
-    eval_fn: fn(&S) -> i32,
+    evaluator: E,
+    observer: O,
 }
 
-impl<S: GameState> MinimaxBot<S> {
-    pub fn new(plies: u32, eval_fn: fn(&S) -> i32) -> Self {
-        Self { plies, eval_fn }
+impl<E> MinimaxBot<E> {
+    pub fn new(plies: u32, evaluator: E) -> Self {
+        Self { plies, evaluator, observer: NullObserver }
     }
 }
 
-impl<S: GameState> Agent<S> for MinimaxBot<S> {
+impl<E, O> MinimaxBot<E, O> {
+    /// Report information about each search to the observer
+    pub fn with_observer<P>(self, observer: P) -> MinimaxBot<E, P> {
+        MinimaxBot { plies: self.plies, evaluator: self.evaluator, observer }
+    }
+}
+
+impl<S: GameState, E: Evaluator<S>, O: SearchObserver<S::Move>> Agent<S> for MinimaxBot<E, O> {
     fn select_move(&mut self, game_state: &S) -> S::Move {
-        let minimax::OptimalMove { best_move, value }  = minimax::minimax(game_state, self.plies, self.eval_fn);
-        println!("Selected {:?} {:?}", best_move, value);
+        let minimax::OptimalMove { best_move, .. } = minimax::minimax_observed(game_state, self.plies, &mut self.evaluator, &mut self.observer);
         best_move.expect("Not a valid move")
     }
 }
 
+/// Agent using iterative deepening principal variation search. The move
+/// ordering's history is kept from one move to the next.
+pub struct PvsBot<S: GameState, E, O = NullObserver, H = NoOrderHint> {
+    plies: u32,
+    aspiration_window: i32,
+    evaluator: E,
+    observer: O,
+    ordering: MoveOrdering<S, H>,
+}
+
+impl<S: GameState, E> PvsBot<S, E>
+where
+    S::Move: Hash + Eq,
+{
+    pub fn new(plies: u32, evaluator: E) -> Self {
+        Self { plies, aspiration_window: 10, evaluator, observer: NullObserver, ordering: MoveOrdering::new() }
+    }
+}
+
+impl<S: GameState, E, O, H> PvsBot<S, E, O, H>
+where
+    S::Move: Hash + Eq,
+{
+    /// Half-width of the window searched around the previous iteration's
+    /// value. Zero always searches with the full window.
+    pub fn with_aspiration_window(mut self, aspiration_window: i32) -> Self {
+        self.aspiration_window = aspiration_window;
+        self
+    }
+
+    /// Report information about each search iteration to the observer
+    pub fn with_observer<P>(self, observer: P) -> PvsBot<S, E, P, H> {
+        PvsBot {
+            plies: self.plies,
+            aspiration_window: self.aspiration_window,
+            evaluator: self.evaluator,
+            observer,
+            ordering: self.ordering,
+        }
+    }
+
+    /// Order otherwise equal moves by the game specific hint
+    pub fn with_order_hint<I: MoveOrderHint<S>>(self, hint: I) -> PvsBot<S, E, O, I> {
+        PvsBot {
+            plies: self.plies,
+            aspiration_window: self.aspiration_window,
+            evaluator: self.evaluator,
+            observer: self.observer,
+            ordering: MoveOrdering::with_hint(hint),
+        }
+    }
+}
+
+impl<S, E, O, H> Agent<S> for PvsBot<S, E, O, H>
+where
+    S: GameState,
+    S::Move: Hash + Eq,
+    E: Evaluator<S>,
+    O: SearchObserver<S::Move>,
+    H: MoveOrderHint<S>,
+{
+    fn select_move(&mut self, game_state: &S) -> S::Move {
+        // Killers are per ply from the root, which has moved since
+        self.ordering.clear_killers();
+        let minimax::OptimalMove { best_move, .. } = minimax::principal_variation_search(
+            game_state,
+            self.plies,
+            self.aspiration_window,
+            &mut self.evaluator,
+            &mut self.ordering,
+            &mut self.observer,
+        );
+        best_move.expect("Not a valid move")
+    }
+}
+
+/// Agent using iterative deepening MTD(f) search. The move ordering's
+/// history is kept from one move to the next.
+pub struct MtdfBot<S: GameState, E, O = NullObserver, H = NoOrderHint> {
+    plies: u32,
+    evaluator: E,
+    observer: O,
+    ordering: MoveOrdering<S, H>,
+}
+
+impl<S: GameState, E> MtdfBot<S, E>
+where
+    S::Move: Hash + Eq,
+{
+    pub fn new(plies: u32, evaluator: E) -> Self {
+        Self { plies, evaluator, observer: NullObserver, ordering: MoveOrdering::new() }
+    }
+}
+
+impl<S: GameState, E, O, H> MtdfBot<S, E, O, H>
+where
+    S::Move: Hash + Eq,
+{
+    /// Report information about each search iteration to the observer
+    pub fn with_observer<P>(self, observer: P) -> MtdfBot<S, E, P, H> {
+        MtdfBot { plies: self.plies, evaluator: self.evaluator, observer, ordering: self.ordering }
+    }
+
+    /// Order otherwise equal moves by the game specific hint
+    pub fn with_order_hint<I: MoveOrderHint<S>>(self, hint: I) -> MtdfBot<S, E, O, I> {
+        MtdfBot { plies: self.plies, evaluator: self.evaluator, observer: self.observer, ordering: MoveOrdering::with_hint(hint) }
+    }
+}
+
+impl<S, E, O, H> Agent<S> for MtdfBot<S, E, O, H>
+where
+    S: GameState + StateHash,
+    S::Move: Hash + Eq,
+    E: Evaluator<S>,
+    O: SearchObserver<S::Move>,
+    H: MoveOrderHint<S>,
+{
+    fn select_move(&mut self, game_state: &S) -> S::Move {
+        // Killers are per ply from the root, which has moved since
+        self.ordering.clear_killers();
+        let minimax::OptimalMove { best_move, .. } = minimax::mtdf(
+            game_state,
+            self.plies,
+            &mut self.evaluator,
+            &mut self.ordering,
+            &mut self.observer,
+        );
+        best_move.expect("Not a valid move")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::{self, GoState, Color};
+
+    fn play_seeded_game(black_seed: u64, white_seed: u64) -> GoState {
+        let mut black = RandomBot::with_seed(black_seed);
+        let mut white = FastRandomBot::with_seed(white_seed);
+        let mut game = GoState::new(5);
+        while !game.is_over() && game.moves.len() < 200 {
+            let the_move = match game.next_player.color {
+                Color::Black => black.select_move(&game),
+                Color::White => white.select_move(&game),
+            };
+            game = game.apply_move(&the_move);
+        }
+        game
+    }
+
+    #[test]
+    fn test_seeded_match_replays_identically() {
+        let first = play_seeded_game(1, 2);
+        let second = play_seeded_game(1, 2);
+        assert_eq!(first.moves, second.moves);
+        assert_eq!(first.board, second.board);
+    }
+
+    #[test]
+    fn test_different_seeds_give_different_games() {
+        let first = play_seeded_game(1, 2);
+        let second = play_seeded_game(3, 4);
+        assert_ne!(first.moves, second.moves);
+    }
+
+    #[test]
+    fn test_search_bots_keep_move_history_between_moves() {
+        let game = GoState::new(3);
+        let mut pvs = PvsBot::new(3, go::stone_difference);
+        let mut mtdf = MtdfBot::new(3, go::stone_difference);
+        let played = [pvs.select_move(&game), mtdf.select_move(&game)];
+        // Without history the moves would keep their valid_moves order
+        for ordering in [&pvs.ordering, &mtdf.ordering] {
+            let mut moves = game.valid_moves();
+            ordering.order_moves(&game, &mut moves, 0, None);
+            assert_ne!(moves, game.valid_moves());
+        }
+        let game = game.apply_move(&played[0]);
+        assert!(game.is_valid_move(&pvs.select_move(&game)));
+        assert!(game.is_valid_move(&mtdf.select_move(&game)));
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::game::GameState;
+
+/// Game-specific static estimate of how promising a move is, used to order
+/// moves that the search knows nothing else about. Larger is searched first.
+///
+/// Implemented for functions and closures, e.g. `go::move_order_hint`.
+pub trait MoveOrderHint<S: GameState> {
+    fn move_order_score(&self, game_state: &S, the_move: &S::Move) -> i32;
+}
+
+impl<S, F> MoveOrderHint<S> for F
+where
+    S: GameState,
+    F: Fn(&S, &S::Move) -> i32,
+{
+    fn move_order_score(&self, game_state: &S, the_move: &S::Move) -> i32 {
+        self(game_state, the_move)
+    }
+}
+
+/// Hint keeping the order of `GameState::valid_moves`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoOrderHint;
+
+impl<S: GameState> MoveOrderHint<S> for NoOrderHint {
+    fn move_order_score(&self, _game_state: &S, _the_move: &S::Move) -> i32 {
+        0
+    }
+}
+
+const KILLERS_PER_PLY: usize = 2;
+
+/// Move ordering for alpha-beta style searches. Moves are searched in the
+/// order
+/// 1. the hash move, i.e. the best move found earlier for the same state
+/// 2. killer moves, which recently caused a cutoff at the same ply
+/// 3. the rest by their history score, the sum of squared depths of the
+///    cutoffs they have caused anywhere in the tree
+/// 4. ties by the static order hint
+pub struct MoveOrdering<S: GameState, H = NoOrderHint> {
+    killers: Vec<[Option<S::Move>; KILLERS_PER_PLY]>,
+    history: HashMap<S::Move, u64>,
+    hint: H,
+}
+
+impl<S: GameState> MoveOrdering<S>
+where
+    S::Move: Hash + Eq,
+{
+    pub fn new() -> Self {
+        Self::with_hint(NoOrderHint)
+    }
+}
+
+impl<S, H> MoveOrdering<S, H>
+where
+    S: GameState,
+    S::Move: Hash + Eq,
+    H: MoveOrderHint<S>,
+{
+    pub fn with_hint(hint: H) -> Self {
+        Self {
+            killers: Vec::new(),
+            history: HashMap::new(),
+            hint,
+        }
+    }
+
+    /// Sort moves of the state at `ply` plies from the root, best first
+    pub fn order_moves(&self, game_state: &S, moves: &mut [S::Move], ply: usize, hash_move: Option<S::Move>) {
+        let killers = self.killers.get(ply);
+        moves.sort_by_cached_key(|the_move| {
+            let tier = if Some(*the_move) == hash_move {
+                0
+            } else if killers.is_some_and(|k| k.contains(&Some(*the_move))) {
+                1
+            } else {
+                2
+            };
+            let history = self.history.get(the_move).copied().unwrap_or(0);
+            let hint = self.hint.move_order_score(game_state, the_move);
+            (tier, std::cmp::Reverse(history), std::cmp::Reverse(hint))
+        });
+    }
+
+    /// Record that the move caused a cutoff at `ply` plies from the root,
+    /// with `depth` plies left to search
+    pub fn record_cutoff(&mut self, the_move: S::Move, ply: usize, depth: u32) {
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; KILLERS_PER_PLY]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(the_move) {
+            killers[1] = killers[0];
+            killers[0] = Some(the_move);
+        }
+        *self.history.entry(the_move).or_insert(0) += depth as u64 * depth as u64;
+    }
+
+    /// Forget the killer moves, e.g. when the root of the search changes.
+    /// History is kept, since it applies anywhere in the tree.
+    pub fn clear_killers(&mut self) {
+        self.killers.clear();
+    }
+}
+
+impl<S: GameState> Default for MoveOrdering<S>
+where
+    S::Move: Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::one_two_three::*;
+
+    #[test]
+    fn test_hash_move_is_ordered_first_and_killers_next() {
+        let game = OneTwoThreeState::new();
+        let mut ordering = MoveOrdering::new();
+        ordering.record_cutoff(Move::Two, 1, 1);
+
+        let mut moves = game.valid_moves();
+        ordering.order_moves(&game, &mut moves, 1, Some(Move::Three));
+        assert_eq!(moves, vec![Move::Three, Move::Two, Move::One]);
+
+        // Killers apply only at their own ply, but history everywhere
+        let mut moves = game.valid_moves();
+        ordering.order_moves(&game, &mut moves, 0, None);
+        assert_eq!(moves, vec![Move::Two, Move::One, Move::Three]);
+    }
+
+    #[test]
+    fn test_deeper_cutoffs_weigh_more_in_history() {
+        let game = OneTwoThreeState::new();
+        let mut ordering = MoveOrdering::new();
+        ordering.record_cutoff(Move::One, 5, 1);
+        ordering.record_cutoff(Move::One, 5, 1);
+        ordering.record_cutoff(Move::Two, 6, 2);
+
+        let mut moves = game.valid_moves();
+        ordering.order_moves(&game, &mut moves, 0, None);
+        assert_eq!(moves, vec![Move::Two, Move::One, Move::Three]);
+    }
+
+    #[test]
+    fn test_hint_breaks_ties() {
+        let game = OneTwoThreeState::new();
+        let hint = |_: &OneTwoThreeState, m: &Move| match m {
+            Move::One => 0,
+            Move::Two => 1,
+            Move::Three => 2,
+        };
+        let ordering = MoveOrdering::with_hint(hint);
+
+        let mut moves = game.valid_moves();
+        ordering.order_moves(&game, &mut moves, 0, None);
+        assert_eq!(moves, vec![Move::Three, Move::Two, Move::One]);
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{ensure, Context, Result};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::SeedableRng;
+
+use crate::agent::{Agent, AgentRng};
+use crate::game::go::encoders::Encoder;
+use crate::game::go::{GoState, Move};
+use crate::game::GameState;
+use crate::nn::{Network, DEFAULT_OUTPUT, POLICY_HEAD, VALUE_HEAD};
+
+/// Plays the moves a policy network predicts, without search. Invalid moves
+/// and points in the agent's own eyes are never played; when nothing else
+/// is left, the agent passes.
+pub struct PolicyAgent {
+    network: Network,
+    encoder: Box<dyn Encoder + Send + Sync>,
+    head: String,
+    /// Zero plays the most likely move, higher values sample more evenly
+    temperature: f64,
+    resign_threshold: Option<f32>,
+    rng: AgentRng,
+}
+
+impl PolicyAgent {
+    /// Agent for a network whose input is the encoder's, and whose policy
+    /// output has one probability per point of the board
+    pub fn new(network: Network, encoder: Box<dyn Encoder + Send + Sync>) -> Result<Self> {
+        Self::with_seed(network, encoder, rand::random())
+    }
+
+    pub fn with_seed(network: Network, encoder: Box<dyn Encoder + Send + Sync>, seed: u64) -> Result<Self> {
+        let head = network
+            .policy_head()
+            .with_context(|| format!("The network has no {} or {} output", POLICY_HEAD, DEFAULT_OUTPUT))?
+            .to_string();
+        let (_, rows, _) = encoder.shape();
+        let outputs = network.evaluate(encoder.as_ref(), &[GoState::new(rows)])?;
+        ensure!(
+            outputs[&head].data().len() == encoder.num_points(),
+            "The network's {} output has {} values for {} points",
+            head,
+            outputs[&head].data().len(),
+            encoder.num_points()
+        );
+        Ok(Self { network, encoder, head, temperature: 0.0, resign_threshold: None, rng: AgentRng::seed_from_u64(seed) })
+    }
+
+    /// Sample moves with probabilities proportional to the predicted ones
+    /// raised to `1 / temperature`, instead of playing the most likely move
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Resign when the network's value of the position is below the
+    /// threshold. Networks without a value head never resign.
+    pub fn with_resign_threshold(mut self, threshold: f32) -> Self {
+        self.resign_threshold = Some(threshold);
+        self
+    }
+}
+
+impl Agent<GoState> for PolicyAgent {
+    fn select_move(&mut self, game_state: &GoState) -> Move {
+        let outputs = self
+            .network
+            .evaluate(self.encoder.as_ref(), std::slice::from_ref(game_state))
+            .expect("The network was checked to fit the encoder");
+        if let (Some(threshold), Some(value)) = (self.resign_threshold, outputs.get(VALUE_HEAD)) {
+            if value.data()[0] < threshold {
+                return Move::Resign;
+            }
+        }
+
+        let candidates = scored_moves(self.encoder.as_ref(), game_state, outputs[&self.head].data());
+        if candidates.is_empty() {
+            return Move::Pass;
+        }
+
+        if self.temperature > 0.0 {
+            // Relative to the most likely move so that low temperatures
+            // don't underflow
+            let max = candidates.iter().map(|(_, p)| *p as f64).fold(0.0, f64::max);
+            let weights = candidates.iter().map(|(_, p)| (*p as f64 / max).powf(1.0 / self.temperature));
+            if let Ok(distribution) = WeightedIndex::new(weights) {
+                return candidates[distribution.sample(&mut self.rng)].0;
+            }
+        }
+        // The first of the most likely moves
+        candidates.iter().fold(candidates[0], |best, c| if c.1 > best.1 { *c } else { best }).0
+    }
+}
+
+/// Valid moves on the board, other than into the player's own eyes, with
+/// their scores from a network output of one score per point
+pub(crate) fn scored_moves(encoder: &dyn Encoder, game_state: &GoState, scores: &[f32]) -> Vec<(Move, f32)> {
+    let color = game_state.next_player.color;
+    let board = &game_state.board;
+    scores
+        .iter()
+        .enumerate()
+        .map(|(index, score)| (encoder.decode_point_index(index), *score))
+        .filter(|(point, _)| board.get(point).is_none() && !board.is_eye(point, color))
+        .map(|(point, score)| (Move::Play(point), score))
+        .filter(|(the_move, _)| game_state.is_valid_move(the_move))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::OnePlaneEncoder;
+    use crate::game::go::{Board, Player, Point};
+    use crate::nn::{Layer, Tensor};
+    use std::str::FromStr;
+
+    /// Network on 3x3 boards preferring points in the order of `scores`,
+    /// independent of the position
+    fn fixed_policy(scores: &[f32], value: Option<f32>) -> Network {
+        let policy = vec![
+            Layer::Dense { weight: Tensor::zeros(vec![9, 9]), bias: Some(scores.to_vec()) },
+            Layer::Softmax,
+        ];
+        let mut heads = vec![(POLICY_HEAD.to_string(), policy)];
+        if let Some(value) = value {
+            let value = Layer::Dense { weight: Tensor::zeros(vec![1, 9]), bias: Some(vec![value]) };
+            heads.push((VALUE_HEAD.to_string(), vec![value]));
+        }
+        Network::new((1, 3, 3), Vec::new(), heads).unwrap()
+    }
+
+    fn agent(scores: &[f32], value: Option<f32>) -> PolicyAgent {
+        PolicyAgent::with_seed(fixed_policy(scores, value), Box::new(OnePlaneEncoder::new(3)), 1).unwrap()
+    }
+
+    #[test]
+    fn test_plays_most_likely_valid_move() {
+        let mut agent = agent(&[0.0, 0.0, 0.0, 0.0, 5.0, 0.0, 0.0, 0.0, 1.0], None);
+        let game = GoState::new(3);
+        assert_eq!(agent.select_move(&game), Move::Play(Point::new(2, 2)));
+        // The center is taken, so the next best point
+        let game = game.apply_move(&Move::Play(Point::new(2, 2)));
+        assert_eq!(agent.select_move(&game), Move::Play(Point::new(3, 3)));
+    }
+
+    #[test]
+    fn test_never_fills_own_eyes() {
+        let board = r#"
+        x.x
+        xxx
+        .x."#;
+        let game = GoState::from_board(Board::from_str(board).unwrap(), Player::black());
+        let mut agent = agent(&[0.0, 9.0, 0.0, 0.0, 0.0, 0.0, 9.0, 0.0, 9.0], None).with_temperature(1.0);
+        for _ in 0..10 {
+            assert_eq!(agent.select_move(&game), Move::Pass);
+        }
+    }
+
+    #[test]
+    fn test_temperature_samples_moves() {
+        let mut agent = agent(&[1.0; 9], None).with_temperature(1.0);
+        let game = GoState::new(3);
+        let mut moves = Vec::new();
+        for _ in 0..50 {
+            let the_move = agent.select_move(&game);
+            assert!(game.is_valid_move(&the_move));
+            if !moves.contains(&the_move) {
+                moves.push(the_move);
+            }
+        }
+        assert!(moves.len() > 5);
+    }
+
+    #[test]
+    fn test_resigns_lost_positions() {
+        let game = GoState::new(3);
+        assert_eq!(agent(&[0.0; 9], Some(-0.95)).with_resign_threshold(-0.9).select_move(&game), Move::Resign);
+        assert_ne!(agent(&[0.0; 9], Some(-0.5)).with_resign_threshold(-0.9).select_move(&game), Move::Resign);
+        assert_ne!(agent(&[0.0; 9], None).with_resign_threshold(-0.9).select_move(&game), Move::Resign);
+    }
+
+    #[test]
+    fn test_network_must_fit_encoder() {
+        let network = fixed_policy(&[0.0; 9], None);
+        assert!(PolicyAgent::new(network, Box::new(OnePlaneEncoder::new(5))).is_err());
+    }
+}
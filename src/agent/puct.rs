@@ -0,0 +1,441 @@
+//! AlphaZero-style tree search. Instead of playing random games, the search
+//! asks a `PolicyValueEvaluator` for prior probabilities of the moves of each
+//! new node and for the value of its position, and chooses moves to explore
+//! by the PUCT rule
+//!
+//! Q(s, a) + c * P(s, a) * sqrt(N(s)) / (1 + N(s, a))
+
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use rand_distr::Gamma;
+
+use crate::agent::{Agent, AgentRng};
+use crate::game::{GameOutcome, Outcome};
+
+/// Output of a `PolicyValueEvaluator` for one position
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyValue {
+    /// Prior probability of each move, in the order the moves were given
+    pub priors: Vec<f64>,
+    /// Value of the position for the player to move, between -1 and 1
+    pub value: f64,
+}
+
+/// Evaluates positions for tree search, e.g. with a neural network
+pub trait PolicyValueEvaluator<S: GameOutcome> {
+    fn evaluate(&mut self, game_state: &S, moves: &[S::Move]) -> PolicyValue;
+}
+
+/// Evaluator without any knowledge: all moves are equally likely and all
+/// positions even. The search then relies on finished games only.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UniformEvaluator;
+
+impl<S: GameOutcome> PolicyValueEvaluator<S> for UniformEvaluator {
+    fn evaluate(&mut self, _game_state: &S, moves: &[S::Move]) -> PolicyValue {
+        PolicyValue {
+            priors: vec![1.0 / moves.len().max(1) as f64; moves.len()],
+            value: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PuctConfig {
+    /// Number of evaluated positions per move
+    pub rounds: u32,
+    /// Weight of the prior in the PUCT exploration term
+    pub c_puct: f64,
+    /// Concentration of the Dirichlet noise added to the root priors
+    pub dirichlet_alpha: f64,
+    /// Share of noise in the root priors, zero for no noise
+    pub dirichlet_weight: f64,
+    /// Moves are selected with probability proportional to
+    /// visits^(1 / temperature). Zero selects the most visited move.
+    pub temperature: f64,
+    /// Keep the subtree of the moves played between searches
+    pub reuse_tree: bool,
+}
+
+impl Default for PuctConfig {
+    fn default() -> Self {
+        Self {
+            rounds: 800,
+            c_puct: 1.5,
+            dirichlet_alpha: 0.3,
+            dirichlet_weight: 0.0,
+            temperature: 0.0,
+            reuse_tree: true,
+        }
+    }
+}
+
+struct Node<S: GameOutcome> {
+    /// Computed when the node is visited the first time
+    state: Option<S>,
+    the_move: Option<S::Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    expanded: bool,
+    prior: f64,
+    visits: u32,
+    /// Sum of values for the player who made the move to this node
+    value_sum: f64,
+}
+
+impl<S: GameOutcome> Node<S> {
+    fn new(state: Option<S>, the_move: Option<S::Move>, parent: Option<usize>, prior: f64) -> Self {
+        Self {
+            state,
+            the_move,
+            parent,
+            children: Vec::new(),
+            expanded: false,
+            prior,
+            visits: 0,
+            value_sum: 0.0,
+        }
+    }
+
+    fn mean_value(&self) -> f64 {
+        if self.visits > 0 {
+            self.value_sum / self.visits as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Tree search agent driven by a `PolicyValueEvaluator`. When told about the
+/// moves played, the bot keeps the subtree of the new position for its next
+/// search.
+pub struct PuctBot<S: GameOutcome, E> {
+    config: PuctConfig,
+    evaluator: E,
+    rng: AgentRng,
+    /// Tree of the previous search, the root at index 0
+    nodes: Vec<Node<S>>,
+}
+
+impl<S, E> PuctBot<S, E>
+where
+    S: GameOutcome + Clone + PartialEq,
+    E: PolicyValueEvaluator<S>,
+{
+    pub fn new(config: PuctConfig, evaluator: E) -> Self {
+        Self::with_rng(config, evaluator, AgentRng::from_entropy())
+    }
+
+    pub fn with_seed(config: PuctConfig, evaluator: E, seed: u64) -> Self {
+        Self::with_rng(config, evaluator, AgentRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(config: PuctConfig, evaluator: E, rng: AgentRng) -> Self {
+        Self {
+            config,
+            evaluator,
+            rng,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Search the state and return the visit count of each root move
+    pub fn search(&mut self, game_state: &S) -> Vec<(S::Move, u32)> {
+        if game_state.is_over() {
+            self.nodes.clear();
+            return Vec::new();
+        }
+        let mut nodes = std::mem::take(&mut self.nodes);
+        let reuse = self.config.reuse_tree && nodes.first().and_then(|root| root.state.as_ref()) == Some(game_state);
+        if !reuse {
+            nodes = vec![Node::new(Some(game_state.clone()), None, None, 1.0)];
+        }
+        if !nodes[0].expanded {
+            self.expand(&mut nodes, 0);
+        }
+        // The noise is for this search only, so that it doesn't compound on a
+        // reused root
+        let clean_priors: Vec<f64> = nodes[0].children.iter().map(|c| nodes[*c].prior).collect();
+        if self.config.dirichlet_weight > 0.0 {
+            self.add_dirichlet_noise(&mut nodes);
+        }
+
+        for _ in 0..self.config.rounds {
+            // Descend to an unexpanded or finished node
+            let mut index = 0;
+            while nodes[index].expanded && !nodes[index].children.is_empty() {
+                index = self.select_child(&mut nodes, index);
+            }
+
+            let state = nodes[index].state.as_ref().unwrap();
+            let value = if state.is_over() {
+                match state.outcome() {
+                    Outcome::Win => 1.0,
+                    Outcome::Loss => -1.0,
+                    Outcome::Draw => 0.0,
+                }
+            } else {
+                self.expand(&mut nodes, index)
+            };
+
+            // The value is for the player to move at the leaf
+            let mut value = -value;
+            let mut node = Some(index);
+            while let Some(i) = node {
+                nodes[i].visits += 1;
+                nodes[i].value_sum += value;
+                value = -value;
+                node = nodes[i].parent;
+            }
+        }
+
+        for (i, prior) in clean_priors.into_iter().enumerate() {
+            let child = nodes[0].children[i];
+            nodes[child].prior = prior;
+        }
+        let visits = nodes[0]
+            .children
+            .iter()
+            .map(|c| (nodes[*c].the_move.unwrap(), nodes[*c].visits))
+            .collect();
+        self.nodes = nodes;
+        visits
+    }
+
+    /// Keep only the subtree of the node, with the node as the new root
+    fn keep_subtree(&mut self, index: usize) {
+        let mut old_nodes: Vec<Option<Node<S>>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        // Pairs of (index in the old nodes, index of parent in the subtree)
+        let mut unexplored: Vec<(usize, Option<usize>)> = vec![(index, None)];
+        while let Some((old_index, parent)) = unexplored.pop() {
+            let new_index = self.nodes.len();
+            let mut node = old_nodes[old_index].take().unwrap();
+            let children = std::mem::take(&mut node.children);
+            node.parent = parent;
+            self.nodes.push(node);
+            if let Some(parent) = parent {
+                self.nodes[parent].children.push(new_index);
+            }
+            unexplored.extend(children.into_iter().rev().map(|child| (child, Some(new_index))));
+        }
+    }
+
+    /// Evaluate the node, create its children with the priors and return
+    /// the value of the node for the player to move
+    fn expand(&mut self, nodes: &mut Vec<Node<S>>, index: usize) -> f64 {
+        let state = nodes[index].state.as_ref().unwrap();
+        let moves = state.valid_moves();
+        let PolicyValue { priors, value } = self.evaluator.evaluate(state, &moves);
+        assert_eq!(priors.len(), moves.len(), "Evaluator must give a prior for every move");
+
+        for (the_move, prior) in moves.into_iter().zip(priors) {
+            let child = nodes.len();
+            nodes.push(Node::new(None, Some(the_move), Some(index), prior));
+            nodes[index].children.push(child);
+        }
+        nodes[index].expanded = true;
+        value
+    }
+
+    fn add_dirichlet_noise(&mut self, nodes: &mut [Node<S>]) {
+        let gamma = Gamma::new(self.config.dirichlet_alpha, 1.0).expect("Invalid Dirichlet alpha");
+        let children = nodes[0].children.clone();
+        let samples: Vec<f64> = children.iter().map(|_| gamma.sample(&mut self.rng)).collect();
+        let sum: f64 = samples.iter().sum();
+        if sum <= 0.0 {
+            return;
+        }
+        let weight = self.config.dirichlet_weight;
+        for (child, sample) in children.iter().zip(samples) {
+            let node = &mut nodes[*child];
+            node.prior = (1.0 - weight) * node.prior + weight * sample / sum;
+        }
+    }
+
+    fn select_child(&self, nodes: &mut [Node<S>], index: usize) -> usize {
+        let sqrt_visits = (nodes[index].visits as f64).sqrt();
+        let score = |node: &Node<S>| {
+            node.mean_value() + self.config.c_puct * node.prior * sqrt_visits / (1.0 + node.visits as f64)
+        };
+        let best = nodes[index]
+            .children
+            .iter()
+            .copied()
+            .max_by(|a, b| score(&nodes[*a]).partial_cmp(&score(&nodes[*b])).unwrap())
+            .unwrap();
+
+        if nodes[best].state.is_none() {
+            let state = nodes[index].state.as_ref().unwrap().apply_move(nodes[best].the_move.as_ref().unwrap());
+            nodes[best].state = Some(state);
+        }
+        best
+    }
+
+    /// Choose a move by its visit count according to the temperature
+    fn choose_move<M: Copy>(&mut self, visits: &[(M, u32)]) -> Option<M> {
+        if self.config.temperature > 0.0 {
+            // Relative to the most visited move so that low temperatures
+            // don't overflow
+            let max = visits.iter().map(|(_, n)| *n).max().unwrap_or(0).max(1) as f64;
+            let weights = visits.iter().map(|(_, n)| (*n as f64 / max).powf(1.0 / self.config.temperature));
+            if let Ok(distribution) = WeightedIndex::new(weights) {
+                return Some(visits[distribution.sample(&mut self.rng)].0);
+            }
+        }
+        visits.iter().max_by_key(|(_, n)| *n).map(|(m, _)| *m)
+    }
+}
+
+/// Visit counts normalized to a probability distribution, e.g. as a training
+/// target for the policy
+pub fn visit_distribution<M: Copy>(visits: &[(M, u32)]) -> Vec<(M, f64)> {
+    let total: u32 = visits.iter().map(|(_, n)| n).sum();
+    visits
+        .iter()
+        .map(|(m, n)| (*m, *n as f64 / total.max(1) as f64))
+        .collect()
+}
+
+impl<S, E> Agent<S> for PuctBot<S, E>
+where
+    S: GameOutcome + Clone + PartialEq,
+    E: PolicyValueEvaluator<S>,
+{
+    fn select_move(&mut self, game_state: &S) -> S::Move {
+        let visits = self.search(game_state);
+        self.choose_move(&visits).expect("No valid moves")
+    }
+
+    fn game_started(&mut self, _game_state: &S) {
+        self.nodes.clear();
+    }
+
+    fn move_played(&mut self, game_state: &S, the_move: &S::Move) {
+        let child = match self.nodes.first() {
+            Some(root) if root.state.as_ref() == Some(game_state) => {
+                // Only searched moves have a subtree worth keeping
+                let nodes = &self.nodes;
+                root.children
+                    .iter()
+                    .copied()
+                    .find(|c| nodes[*c].the_move.as_ref() == Some(the_move) && nodes[*c].state.is_some())
+            }
+            _ => None,
+        };
+        match child {
+            Some(child) => self.keep_subtree(child),
+            None => self.nodes.clear(),
+        }
+    }
+
+    fn game_ended(&mut self, _game_state: &S) {
+        self.nodes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::one_two_three::*;
+    use crate::game::GameState;
+
+    fn config(rounds: u32) -> PuctConfig {
+        PuctConfig {
+            rounds,
+            ..PuctConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_uniform_evaluator_finds_winning_move_in_one_two_three() {
+        // 6 - 2 for the first player, who wins immediately with Three
+        let game = OneTwoThreeState::new()
+            .apply_move(&Move::Three)
+            .apply_move(&Move::One)
+            .apply_move(&Move::Three)
+            .apply_move(&Move::One);
+        let mut bot = PuctBot::with_seed(config(400), UniformEvaluator, 1);
+        assert_eq!(bot.select_move(&game), Move::Three);
+    }
+
+    #[test]
+    fn test_visits_sum_to_rounds() {
+        let game = OneTwoThreeState::new();
+        let mut bot = PuctBot::with_seed(config(100), UniformEvaluator, 1);
+        let visits = bot.search(&game);
+        assert_eq!(visits.len(), 3);
+        assert_eq!(visits.iter().map(|(_, n)| n).sum::<u32>(), 100);
+
+        let distribution = visit_distribution(&visits);
+        let total: f64 = distribution.iter().map(|(_, p)| p).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_subtree_of_played_moves_is_reused() {
+        let game = OneTwoThreeState::new();
+        let mut bot = PuctBot::with_seed(config(100), UniformEvaluator, 1);
+        bot.game_started(&game);
+        let the_move = bot.select_move(&game);
+        bot.move_played(&game, &the_move);
+        let game = game.apply_move(&the_move);
+
+        let reused_visits = bot.nodes[0].visits;
+        assert!(reused_visits > 0);
+        let visits: u32 = bot.search(&game).iter().map(|(_, n)| n).sum();
+        assert_eq!(visits, reused_visits - 1 + 100);
+
+        bot.move_played(&game, &Move::One);
+        bot.game_ended(&game.apply_move(&Move::One));
+        assert!(bot.nodes.is_empty());
+    }
+
+    #[test]
+    fn test_dirichlet_noise_keeps_priors_normalized() {
+        let game = OneTwoThreeState::new();
+        let mut bot = PuctBot::with_seed(PuctConfig { dirichlet_weight: 0.25, ..config(1) }, UniformEvaluator, 1);
+        let mut nodes = vec![Node::new(Some(game), None, None, 1.0)];
+        bot.expand(&mut nodes, 0);
+        bot.add_dirichlet_noise(&mut nodes);
+
+        let priors: Vec<f64> = nodes[0].children.iter().map(|c| nodes[*c].prior).collect();
+        assert!((priors.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(priors.iter().any(|p| (p - 1.0 / 3.0).abs() > 1e-6));
+    }
+
+    #[test]
+    fn test_dirichlet_noise_does_not_compound_on_reused_root() {
+        let game = OneTwoThreeState::new();
+        let mut bot = PuctBot::with_seed(PuctConfig { dirichlet_weight: 0.25, ..config(10) }, UniformEvaluator, 1);
+        for _ in 0..2 {
+            bot.search(&game);
+            assert!(bot.nodes[0].children.iter().all(|c| (bot.nodes[*c].prior - 1.0 / 3.0).abs() < 1e-9));
+        }
+        assert_eq!(bot.nodes[0].visits, 20);
+    }
+
+    #[test]
+    fn test_low_temperature_chooses_the_most_visited_move() {
+        let config = PuctConfig { temperature: 0.001, ..config(1) };
+        let mut bot = PuctBot::<OneTwoThreeState, _>::with_seed(config, UniformEvaluator, 1);
+        let visits = [(Move::One, 50), (Move::Two, 400), (Move::Three, 300)];
+        assert_eq!(bot.choose_move(&visits), Some(Move::Two));
+        // Without visits to sample from, a move is still chosen
+        assert!(bot.choose_move(&[(Move::One, 0), (Move::Two, 0)]).is_some());
+    }
+
+    #[test]
+    fn test_seeded_temperature_sampling_is_reproducible() {
+        let game = OneTwoThreeState::new();
+        let config = PuctConfig {
+            temperature: 1.0,
+            dirichlet_weight: 0.25,
+            ..config(50)
+        };
+        let moves = |seed| {
+            let mut bot = PuctBot::with_seed(config.clone(), UniformEvaluator, seed);
+            (0..10).map(|_| bot.select_move(&game)).collect::<Vec<_>>()
+        };
+        assert_eq!(moves(5), moves(5));
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{ensure, Context, Result};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::agent::policy::scored_moves;
+use crate::agent::{Agent, AgentRng};
+use crate::game::go::encoders::Encoder;
+use crate::game::go::{GoState, Move};
+use crate::nn::{Network, Q_HEAD};
+
+/// Plays the move a Q network values most for the player to move, without
+/// search, or with probability epsilon a random move to explore. Like the
+/// policy agent it never fills its own eyes and passes when nothing else is
+/// left.
+pub struct QAgent {
+    network: Network,
+    encoder: Box<dyn Encoder + Send + Sync>,
+    epsilon: f64,
+    rng: AgentRng,
+}
+
+impl QAgent {
+    /// Agent for a network whose input is the encoder's, and whose Q output
+    /// has one value per point of the board
+    pub fn new(network: Network, encoder: Box<dyn Encoder + Send + Sync>) -> Result<Self> {
+        Self::with_seed(network, encoder, rand::random())
+    }
+
+    pub fn with_seed(network: Network, encoder: Box<dyn Encoder + Send + Sync>, seed: u64) -> Result<Self> {
+        let (_, rows, _) = encoder.shape();
+        let outputs = network.evaluate(encoder.as_ref(), &[GoState::new(rows)])?;
+        let values = outputs.get(Q_HEAD).with_context(|| format!("The network has no {} output", Q_HEAD))?;
+        ensure!(
+            values.data().len() == encoder.num_points(),
+            "The network's {} output has {} values for {} points",
+            Q_HEAD,
+            values.data().len(),
+            encoder.num_points()
+        );
+        Ok(Self { network, encoder, epsilon: 0.0, rng: AgentRng::seed_from_u64(seed) })
+    }
+
+    /// Probability of playing a random move instead of the best valued one.
+    /// Panics unless it is from 0 to 1.
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        assert!((0.0..=1.0).contains(&epsilon), "Invalid epsilon {}, expected 0 to 1", epsilon);
+        self.epsilon = epsilon;
+        self
+    }
+}
+
+impl Agent<GoState> for QAgent {
+    fn select_move(&mut self, game_state: &GoState) -> Move {
+        let outputs = self
+            .network
+            .evaluate(self.encoder.as_ref(), std::slice::from_ref(game_state))
+            .expect("The network was checked to fit the encoder");
+        let candidates = scored_moves(self.encoder.as_ref(), game_state, outputs[Q_HEAD].data());
+        if candidates.is_empty() {
+            return Move::Pass;
+        }
+        if self.rng.gen_bool(self.epsilon) {
+            return candidates.choose(&mut self.rng).unwrap().0;
+        }
+        // The first of the best valued moves
+        candidates.iter().fold(candidates[0], |best, c| if c.1 > best.1 { *c } else { best }).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::OnePlaneEncoder;
+    use crate::game::go::Point;
+    use crate::game::GameState;
+    use crate::nn::{Layer, Tensor, POLICY_HEAD};
+
+    /// Network on 3x3 boards valuing the points as given, independent of the
+    /// position
+    fn fixed_values(values: &[f32]) -> Network {
+        let q = vec![Layer::Dense { weight: Tensor::zeros(vec![9, 9]), bias: Some(values.to_vec()) }];
+        Network::new((1, 3, 3), Vec::new(), vec![(Q_HEAD.to_string(), q)]).unwrap()
+    }
+
+    #[test]
+    fn test_plays_best_valued_valid_move() {
+        let values = [0.1, 0.0, 0.0, 0.0, 0.9, 0.0, 0.0, 0.0, 0.5];
+        let mut agent = QAgent::with_seed(fixed_values(&values), Box::new(OnePlaneEncoder::new(3)), 1).unwrap();
+        let game = GoState::new(3);
+        assert_eq!(agent.select_move(&game), Move::Play(Point::new(2, 2)));
+        let game = game.apply_move(&Move::Play(Point::new(2, 2)));
+        assert_eq!(agent.select_move(&game), Move::Play(Point::new(3, 3)));
+    }
+
+    #[test]
+    fn test_epsilon_explores() {
+        let values = [0.0, 0.0, 0.0, 0.0, 0.9, 0.0, 0.0, 0.0, 0.0];
+        let agent = QAgent::with_seed(fixed_values(&values), Box::new(OnePlaneEncoder::new(3)), 2).unwrap();
+        let mut agent = agent.with_epsilon(0.5);
+        let game = GoState::new(3);
+        let best = (0..100).filter(|_| agent.select_move(&game) == Move::Play(Point::new(2, 2))).count();
+        // Half of the moves are random, and one in nine of those is the best
+        assert!((40..75).contains(&best), "{}", best);
+    }
+
+    #[test]
+    fn test_network_needs_q_head() {
+        let policy = vec![Layer::Dense { weight: Tensor::zeros(vec![9, 9]), bias: None }, Layer::Softmax];
+        let network = Network::new((1, 3, 3), Vec::new(), vec![(POLICY_HEAD.to_string(), policy)]).unwrap();
+        assert!(QAgent::new(network, Box::new(OnePlaneEncoder::new(3))).is_err());
+        assert!(QAgent::new(fixed_values(&[0.0; 9]), Box::new(OnePlaneEncoder::new(5))).is_err());
+    }
+}
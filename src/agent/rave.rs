@@ -0,0 +1,400 @@
+//! Monte Carlo tree search for Go with rapid action value estimation
+//! (MC-RAVE, Gelly & Silver 2011).
+//!
+//! Besides the usual statistics of a move, every node records
+//! all-moves-as-first (AMAF) statistics: the results of all simulations in
+//! which the move was played by the same player later on, in the tree or in
+//! the random playout. These are available after far fewer simulations, and
+//! are blended with the move's own win rate with a weight that decreases as
+//! the move gets visits of its own.
+
+use rand::{Rng, SeedableRng};
+
+use crate::agent::{Agent, AgentRng, FastRandomBot};
+use crate::game::go::{Color, GoState, Move, Point};
+use crate::game::{GameOutcome, GameState, Outcome};
+
+#[derive(Clone, Debug)]
+pub struct RaveConfig {
+    /// Number of simulations per move
+    pub rounds: u32,
+    /// Number of visits at which the win rate and the AMAF value of a move
+    /// are weighted equally. The larger, the longer AMAF values are trusted.
+    pub equivalence: f64,
+    /// Weight of the UCT exploration term, often zero with RAVE
+    pub exploration: f64,
+    /// Value of moves without any statistics, high to try every move once
+    pub first_play_urgency: f64,
+    /// Keep the subtree of the moves played between searches
+    pub reuse_tree: bool,
+}
+
+impl Default for RaveConfig {
+    fn default() -> Self {
+        Self {
+            rounds: 1000,
+            equivalence: 1000.0,
+            exploration: 0.0,
+            first_play_urgency: 1.1,
+            reuse_tree: true,
+        }
+    }
+}
+
+struct Node {
+    /// Computed when the node is visited the first time
+    state: Option<GoState>,
+    the_move: Move,
+    /// Player who made the move to this node
+    color: Color,
+    /// None until the node is expanded
+    children: Option<Vec<usize>>,
+    visits: u32,
+    /// Sum of results for the player who made the move to this node
+    wins: f64,
+    amaf_visits: u32,
+    amaf_wins: f64,
+}
+
+impl Node {
+    fn new(state: Option<GoState>, the_move: Move, color: Color) -> Self {
+        Self {
+            state,
+            the_move,
+            color,
+            children: None,
+            visits: 0,
+            wins: 0.0,
+            amaf_visits: 0,
+            amaf_wins: 0.0,
+        }
+    }
+
+    /// Blend of the win rate and the AMAF value, weighted by
+    /// beta = sqrt(k / (3n + k)) where k is the equivalence parameter
+    fn rave_value(&self, config: &RaveConfig) -> f64 {
+        if self.visits == 0 && self.amaf_visits == 0 {
+            return config.first_play_urgency;
+        }
+        let amaf_value = if self.amaf_visits > 0 {
+            self.amaf_wins / self.amaf_visits as f64
+        } else {
+            0.5
+        };
+        if self.visits == 0 {
+            return amaf_value;
+        }
+        let n = self.visits as f64;
+        let beta = (config.equivalence / (3.0 * n + config.equivalence)).sqrt();
+        (1.0 - beta) * (self.wins / n) + beta * amaf_value
+    }
+}
+
+/// Go tree search agent with MC-RAVE. Tree moves never fill the player's own
+/// eyes and never resign, and playouts are played by `FastRandomBot`.
+///
+/// When told about the moves played, the bot keeps the subtree of the new
+/// position for its next search.
+pub struct RaveBot {
+    config: RaveConfig,
+    playout_policy: FastRandomBot,
+    nodes: Vec<Node>,
+}
+
+impl RaveBot {
+    pub fn new(config: RaveConfig) -> Self {
+        Self::with_rng(config, AgentRng::from_entropy())
+    }
+
+    pub fn with_seed(config: RaveConfig, seed: u64) -> Self {
+        Self::with_rng(config, AgentRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(config: RaveConfig, mut rng: AgentRng) -> Self {
+        Self {
+            config,
+            playout_policy: FastRandomBot::with_seed(rng.gen()),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Search the state and return each candidate move with its number of
+    /// visits and RAVE value
+    pub fn search(&mut self, game_state: &GoState) -> Vec<(Move, u32, f64)> {
+        let reuse = self.config.reuse_tree && self.root_state() == Some(game_state);
+        if !reuse {
+            self.nodes.clear();
+            self.nodes.push(Node::new(Some(game_state.clone()), Move::Pass, game_state.previous_player.color));
+        }
+        for _ in 0..self.config.rounds {
+            self.simulate();
+        }
+        let children = self.nodes[0].children.clone().unwrap_or_default();
+        children
+            .iter()
+            .map(|c| {
+                let node = &self.nodes[*c];
+                (node.the_move, node.visits, node.rave_value(&self.config))
+            })
+            .collect()
+    }
+
+    fn root_state(&self) -> Option<&GoState> {
+        self.nodes.first().and_then(|root| root.state.as_ref())
+    }
+
+    /// Keep only the subtree of the node, with the node as the new root
+    fn keep_subtree(&mut self, index: usize) {
+        let mut old_nodes: Vec<Option<Node>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        // Pairs of (index in the old nodes, index of parent in the subtree)
+        let mut unexplored: Vec<(usize, Option<usize>)> = vec![(index, None)];
+        while let Some((old_index, parent)) = unexplored.pop() {
+            let new_index = self.nodes.len();
+            let mut node = old_nodes[old_index].take().unwrap();
+            let children = node.children.take();
+            node.children = children.as_ref().map(|_| Vec::new());
+            self.nodes.push(node);
+            if let Some(parent) = parent {
+                self.nodes[parent].children.as_mut().unwrap().push(new_index);
+            }
+            for child in children.unwrap_or_default().into_iter().rev() {
+                unexplored.push((child, Some(new_index)));
+            }
+        }
+    }
+
+    fn simulate(&mut self) {
+        // Descend the tree, expanding the first unexpanded node
+        let mut path = vec![0];
+        let mut index = 0;
+        loop {
+            let state = self.nodes[index].state.as_ref().unwrap();
+            if state.is_over() {
+                break;
+            }
+            let expanded = self.nodes[index].children.is_some();
+            if !expanded {
+                self.expand(index);
+            }
+            index = self.select_child(index);
+            path.push(index);
+            if !expanded {
+                break;
+            }
+        }
+
+        // Play out from the leaf, recording who played where
+        let mut state = self.nodes[index].state.clone().unwrap();
+        let mut sequence: Vec<(Color, Move)> = path[1..]
+            .iter()
+            .map(|i| (self.nodes[*i].color, self.nodes[*i].the_move))
+            .collect();
+        let max_moves = 3 * state.board.rows * state.board.cols;
+        while !state.is_over() && sequence.len() < max_moves {
+            let the_move = self.playout_policy.select_move(&state);
+            sequence.push((state.next_player.color, the_move));
+            state = state.apply_move(&the_move);
+        }
+        let winner = match state.outcome() {
+            Outcome::Win => Some(state.next_player.color),
+            Outcome::Loss => Some(state.previous_player.color),
+            Outcome::Draw => None,
+        };
+
+        self.update(&path, &sequence, winner, &state);
+    }
+
+    fn expand(&mut self, index: usize) {
+        let state = self.nodes[index].state.as_ref().unwrap();
+        let color = state.next_player.color;
+        let mut moves: Vec<Move> = state
+            .board
+            .empty_points()
+            .filter(|p| !state.board.is_eye(p, color))
+            .map(Move::Play)
+            .filter(|m| state.is_valid_move(m))
+            .collect();
+        moves.push(Move::Pass);
+
+        let mut children = Vec::with_capacity(moves.len());
+        for the_move in moves {
+            children.push(self.nodes.len());
+            self.nodes.push(Node::new(None, the_move, color));
+        }
+        self.nodes[index].children = Some(children);
+    }
+
+    fn select_child(&mut self, index: usize) -> usize {
+        let parent_visits = self.nodes[index].visits.max(1) as f64;
+        let config = &self.config;
+        let nodes = &self.nodes;
+        let score = |c: usize| {
+            let node = &nodes[c];
+            let exploration = config.exploration * (parent_visits.ln() / node.visits.max(1) as f64).sqrt();
+            node.rave_value(config) + exploration
+        };
+        let best = nodes[index]
+            .children
+            .as_ref()
+            .unwrap()
+            .iter()
+            .copied()
+            .max_by(|a, b| score(*a).partial_cmp(&score(*b)).unwrap())
+            .unwrap();
+
+        if self.nodes[best].state.is_none() {
+            let state = self.nodes[index].state.as_ref().unwrap().apply_move(&self.nodes[best].the_move);
+            self.nodes[best].state = Some(state);
+        }
+        best
+    }
+
+    /// Update the statistics of the nodes on the path, and the AMAF
+    /// statistics of their children
+    fn update(&mut self, path: &[usize], sequence: &[(Color, Move)], winner: Option<Color>, final_state: &GoState) {
+        let value_for = |color: Color| match winner {
+            Some(winner) if winner == color => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+
+        // Index of the first play on each point by each color
+        let board = &final_state.board;
+        let point_index = |p: &Point| (p.row - 1) * board.cols + (p.col - 1);
+        let mut first_play = [vec![usize::MAX; board.rows * board.cols], vec![usize::MAX; board.rows * board.cols]];
+        for (i, (color, the_move)) in sequence.iter().enumerate() {
+            if let Move::Play(p) = the_move {
+                let first = &mut first_play[*color as usize][point_index(p)];
+                *first = (*first).min(i);
+            }
+        }
+
+        for (depth, index) in path.iter().enumerate() {
+            let node = &mut self.nodes[*index];
+            node.visits += 1;
+            node.wins += value_for(node.color);
+
+            // The move from this node is sequence[depth]; AMAF counts moves
+            // first played by the same player from here on
+            let children = match &self.nodes[*index].children {
+                Some(children) => children.clone(),
+                None => continue,
+            };
+            for child in children {
+                let node = &mut self.nodes[child];
+                if let Move::Play(p) = node.the_move {
+                    let first = first_play[node.color as usize][point_index(&p)];
+                    if first != usize::MAX && first >= depth {
+                        node.amaf_visits += 1;
+                        node.amaf_wins += value_for(node.color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Agent<GoState> for RaveBot {
+    fn select_move(&mut self, game_state: &GoState) -> Move {
+        self.search(game_state)
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(a.2.partial_cmp(&b.2).unwrap()))
+            .map(|(the_move, _, _)| the_move)
+            .unwrap_or(Move::Pass)
+    }
+
+    fn game_started(&mut self, _game_state: &GoState) {
+        self.nodes.clear();
+    }
+
+    fn move_played(&mut self, game_state: &GoState, the_move: &Move) {
+        let child = if self.root_state() == Some(game_state) {
+            let mut children = self.nodes[0].children.iter().flatten().copied();
+            // Only searched moves have a subtree worth keeping
+            children.find(|c| self.nodes[*c].the_move == *the_move && self.nodes[*c].state.is_some())
+        } else {
+            None
+        };
+        match child {
+            Some(child) => self.keep_subtree(child),
+            None => self.nodes.clear(),
+        }
+    }
+
+    fn game_ended(&mut self, _game_state: &GoState) {
+        self.nodes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::{Board, Player};
+    use std::str::FromStr;
+
+    fn config(rounds: u32) -> RaveConfig {
+        RaveConfig {
+            rounds,
+            ..RaveConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_rave_captures_group_in_atari() {
+        let board = r#"
+        oooo.
+        ooooo
+        ooooo
+        xxxxx
+        xxxx."#;
+        let board = Board::from_str(board).unwrap();
+        let game = GoState::from_board(board, Player::black());
+        let mut bot = RaveBot::with_seed(config(300), 1);
+        assert_eq!(bot.select_move(&game), Move::Play(Point::new(1, 5)));
+    }
+
+    #[test]
+    fn test_amaf_statistics_cover_more_simulations_than_visits() {
+        let game = GoState::new(5);
+        let mut bot = RaveBot::with_seed(config(200), 2);
+        bot.search(&game);
+
+        let root_children = bot.nodes[0].children.clone().unwrap();
+        let visits: u32 = root_children.iter().map(|c| bot.nodes[*c].visits).sum();
+        let amaf_visits: u32 = root_children.iter().map(|c| bot.nodes[*c].amaf_visits).sum();
+        assert_eq!(visits, 200);
+        assert!(amaf_visits > visits);
+        for c in root_children {
+            assert!(bot.nodes[c].amaf_visits >= bot.nodes[c].visits || bot.nodes[c].the_move == Move::Pass);
+        }
+    }
+
+    #[test]
+    fn test_subtree_of_played_moves_is_reused() {
+        let game = GoState::new(5);
+        let mut bot = RaveBot::with_seed(config(200), 4);
+        bot.game_started(&game);
+        let the_move = bot.select_move(&game);
+        bot.move_played(&game, &the_move);
+        let game = game.apply_move(&the_move);
+
+        let reused_visits = bot.nodes[0].visits;
+        assert!(reused_visits > 0);
+        assert_eq!(bot.root_state(), Some(&game));
+        bot.search(&game);
+        assert_eq!(bot.nodes[0].visits, reused_visits + 200);
+
+        let reply = Move::Play(Point::new(1, 1));
+        bot.move_played(&game, &reply);
+        bot.game_ended(&game.apply_move(&reply));
+        assert!(bot.nodes.is_empty());
+    }
+
+    #[test]
+    fn test_seeded_search_is_reproducible() {
+        let game = GoState::new(5);
+        let first = RaveBot::with_seed(config(100), 3).search(&game);
+        let second = RaveBot::with_seed(config(100), 3).search(&game);
+        assert_eq!(first, second);
+    }
+}
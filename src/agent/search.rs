@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+/// Information about a finished search, passed to a `SearchObserver`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchInfo<M> {
+    /// Search depth in plies
+    pub depth: u32,
+    /// Number of game states visited, including the root
+    pub nodes: u64,
+    pub elapsed: Duration,
+    /// Expected line of play from the root, best move first
+    pub principal_variation: Vec<M>,
+    /// Every root move with its score, best first
+    pub move_scores: Vec<(M, i32)>,
+    /// Number of nodes whose search was cut off by alpha-beta pruning
+    pub cutoffs: u64,
+    /// Number of cutoffs caused by the first move searched. The closer this
+    /// is to `cutoffs`, the better the move ordering.
+    pub first_move_cutoffs: u64,
+}
+
+impl<M> SearchInfo<M> {
+    pub fn nodes_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.nodes as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Share of cutoffs caused by the first move searched, between 0 and 1
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        if self.cutoffs > 0 {
+            self.first_move_cutoffs as f64 / self.cutoffs as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Receives information about searches as they finish. Implemented for
+/// closures, so a caller can e.g. log the information with
+/// `|info: &SearchInfo<Move>| println!("{:?}", info)`.
+pub trait SearchObserver<M> {
+    fn on_search_info(&mut self, info: &SearchInfo<M>);
+}
+
+impl<M, F> SearchObserver<M> for F
+where
+    F: FnMut(&SearchInfo<M>),
+{
+    fn on_search_info(&mut self, info: &SearchInfo<M>) {
+        self(info)
+    }
+}
+
+/// Observer ignoring all search information, used by default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullObserver;
+
+impl<M> SearchObserver<M> for NullObserver {
+    fn on_search_info(&mut self, _info: &SearchInfo<M>) {}
+}
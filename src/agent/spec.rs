@@ -0,0 +1,354 @@
+//! Agents described by strings such as `minimax:depth=4` or
+//! `mcts:rounds=2000,threads=4`, e.g. for choosing agents on the command line
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::agent::{
+    ActorCriticAgent, Agent, FastRandomBot, HumanAgent, MctsBot, MctsConfig, MinimaxBot, MtdfBot, NullObserver,
+    Parallelism, PolicyAgent, PuctBot, PuctConfig, PvsBot, QAgent, RandomBot, RaveBot, RaveConfig, SearchObserver, UniformEvaluator,
+};
+use crate::game::go::encoders::{get_encoder_by_name, Encoder};
+use crate::game::go::{self, GoState};
+use crate::game::one_two_three::{self, OneTwoThreeState};
+use crate::nn::Network;
+
+/// Names of the agents that can be built from a spec
+pub const AGENT_NAMES: [&str; 12] = [
+    "human", "random", "fast-random", "minimax", "pvs", "mtdf", "mcts", "rave", "puct", "policy", "q", "actor-critic",
+];
+
+/// Name of an agent and its parameters
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgentSpec {
+    pub name: String,
+    params: Vec<(String, String)>,
+}
+
+impl AgentSpec {
+    /// Value of the parameter, or the default if it was not given
+    pub fn param<T>(&self, key: &str, default: T) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.params.iter().find(|(k, _)| k == key) {
+            Some((_, value)) => value
+                .parse()
+                .map_err(|e| anyhow!("Invalid value {} for {} of {}: {}", value, key, self.name, e)),
+            None => Ok(default),
+        }
+    }
+
+    fn check_params(&self, allowed: &[&str]) -> Result<()> {
+        for (key, _) in &self.params {
+            if key != "seed" && !allowed.contains(&key.as_str()) {
+                bail!("Unknown parameter {} for {}, expected one of: seed {}", key, self.name, allowed.join(" "));
+            }
+        }
+        Ok(())
+    }
+
+    /// Seed given in the spec, or the default
+    pub fn seed(&self, default: u64) -> Result<u64> {
+        self.param("seed", default)
+    }
+
+    /// Search depth of the minimax family of agents
+    pub fn depth(&self) -> Result<u32> {
+        self.check_params(&["depth", "window"])?;
+        let depth = self.param("depth", 3)?;
+        ensure!(depth > 0, "{} needs a depth of at least one", self.name);
+        Ok(depth)
+    }
+
+    pub fn mcts_config(&self) -> Result<MctsConfig> {
+        self.check_params(&["rounds", "threads", "parallelism", "exploration", "reuse", "ponder"])?;
+        let default = MctsConfig::default();
+        let parallelism = match self.param("parallelism", "root".to_string())?.as_str() {
+            "root" => Parallelism::Root,
+            "tree" => Parallelism::Tree,
+            other => bail!("Unknown parallelism {}, expected root or tree", other),
+        };
+        let config = MctsConfig {
+            rounds: self.param("rounds", default.rounds)?,
+            threads: self.param("threads", default.threads)?,
+            parallelism,
+            exploration: self.param("exploration", default.exploration)?,
+            reuse_tree: self.param("reuse", default.reuse_tree)?,
+            ponder_rounds: self.param("ponder", default.ponder_rounds)?,
+            ..default
+        };
+        ensure!(config.rounds > 0, "{} needs at least one round", self.name);
+        Ok(config)
+    }
+
+    pub fn rave_config(&self) -> Result<RaveConfig> {
+        self.check_params(&["rounds", "equivalence", "exploration", "reuse"])?;
+        let default = RaveConfig::default();
+        let config = RaveConfig {
+            rounds: self.param("rounds", default.rounds)?,
+            equivalence: self.param("equivalence", default.equivalence)?,
+            exploration: self.param("exploration", default.exploration)?,
+            reuse_tree: self.param("reuse", default.reuse_tree)?,
+            ..default
+        };
+        ensure!(config.rounds > 0, "{} needs at least one round", self.name);
+        Ok(config)
+    }
+
+    pub fn puct_config(&self) -> Result<PuctConfig> {
+        self.check_params(&["rounds", "c", "noise", "temperature", "reuse"])?;
+        let default = PuctConfig::default();
+        let config = PuctConfig {
+            rounds: self.param("rounds", default.rounds)?,
+            c_puct: self.param("c", default.c_puct)?,
+            dirichlet_weight: self.param("noise", default.dirichlet_weight)?,
+            temperature: self.param("temperature", default.temperature)?,
+            reuse_tree: self.param("reuse", default.reuse_tree)?,
+            ..default
+        };
+        ensure!(config.rounds > 0, "{} needs at least one round", self.name);
+        ensure!(config.temperature >= 0.0, "Invalid temperature {} of {}", config.temperature, self.name);
+        Ok(config)
+    }
+
+    /// Policy network agent of a network spec file (`model`) and the name of
+    /// its encoder
+    pub fn policy_agent(&self, seed: u64) -> Result<PolicyAgent> {
+        self.check_params(&["model", "encoder", "temperature", "resign"])?;
+        let (network, encoder) = self.network()?;
+        let mut agent = PolicyAgent::with_seed(network, encoder, seed)?.with_temperature(self.param("temperature", 0.0)?);
+        if self.params.iter().any(|(key, _)| key == "resign") {
+            agent = agent.with_resign_threshold(self.param("resign", 0.0)?);
+        }
+        Ok(agent)
+    }
+
+    /// Q network agent, with the parameters of `policy_agent` and the
+    /// exploration rate
+    pub fn q_agent(&self, seed: u64) -> Result<QAgent> {
+        self.check_params(&["model", "encoder", "epsilon"])?;
+        let epsilon = self.param("epsilon", 0.0)?;
+        ensure!((0.0..=1.0).contains(&epsilon), "Invalid epsilon {} of {}, expected 0 to 1", epsilon, self.name);
+        let (network, encoder) = self.network()?;
+        Ok(QAgent::with_seed(network, encoder, seed)?.with_epsilon(epsilon))
+    }
+
+    /// Actor-critic agent, with the parameters of `policy_agent`
+    pub fn actor_critic_agent(&self, seed: u64) -> Result<ActorCriticAgent> {
+        self.check_params(&["model", "encoder", "temperature", "resign"])?;
+        let (network, encoder) = self.network()?;
+        let mut agent = ActorCriticAgent::with_seed(network, encoder, seed)?.with_temperature(self.param("temperature", 1.0)?);
+        if self.params.iter().any(|(key, _)| key == "resign") {
+            agent = agent.with_resign_threshold(self.param("resign", 0.0)?);
+        }
+        Ok(agent)
+    }
+
+    /// Network of the spec file given as `model`, and its encoder, by
+    /// default the seven plane one for the network's board size
+    fn network(&self) -> Result<(Network, Box<dyn Encoder + Send + Sync>)> {
+        let model: String = self.param("model", String::new())?;
+        if model.is_empty() {
+            bail!("{} needs the model parameter, the path of a network spec", self.name);
+        }
+        let network = Network::load(model.as_ref())?;
+        let encoder = get_encoder_by_name(&self.param("encoder", "sevenplane".to_string())?, network.input_shape().1)?;
+        Ok((network, encoder))
+    }
+
+    /// Build a Go agent. Searching agents report to the observer, and
+    /// stochastic ones are seeded with the seed unless the spec has its own.
+    pub fn build_go<O>(&self, seed: u64, observer: O) -> Result<Box<dyn Agent<GoState>>>
+    where
+        O: SearchObserver<go::Move> + 'static,
+    {
+        let seed = self.seed(seed)?;
+        let agent: Box<dyn Agent<GoState>> = match self.name.as_str() {
+            "human" => {
+                self.check_params(&[])?;
+                Box::new(HumanAgent::new())
+            }
+            "random" => {
+                self.check_params(&[])?;
+                Box::new(RandomBot::with_seed(seed))
+            }
+            "fast-random" => {
+                self.check_params(&[])?;
+                Box::new(FastRandomBot::with_seed(seed))
+            }
+            "minimax" => Box::new(MinimaxBot::new(self.depth()?, go::stone_difference).with_observer(observer)),
+            "pvs" => Box::new(
+                PvsBot::new(self.depth()?, go::stone_difference)
+                    .with_aspiration_window(self.param("window", 10)?)
+                    .with_order_hint(go::move_order_hint)
+                    .with_observer(observer),
+            ),
+            "mtdf" => Box::new(
+                MtdfBot::new(self.depth()?, go::stone_difference)
+                    .with_order_hint(go::move_order_hint)
+                    .with_observer(observer),
+            ),
+            "mcts" => Box::new(MctsBot::with_seed(self.mcts_config()?, FastRandomBot::with_seed, seed)),
+            "rave" => Box::new(RaveBot::with_seed(self.rave_config()?, seed)),
+            "puct" => Box::new(PuctBot::with_seed(self.puct_config()?, UniformEvaluator, seed)),
+            "policy" => Box::new(self.policy_agent(seed)?),
+            "q" => Box::new(self.q_agent(seed)?),
+            "actor-critic" => Box::new(self.actor_critic_agent(seed)?),
+            other => bail!("Unknown agent {}, expected one of: {}", other, AGENT_NAMES.join(" ")),
+        };
+        Ok(agent)
+    }
+
+    /// Check that the spec describes a valid Go agent. Human agents are only
+    /// checked for parameters, as they would take over the terminal.
+    pub fn check_go(&self) -> Result<()> {
+        match self.name.as_str() {
+            "human" => self.check_params(&[]),
+            _ => self.build_go(0, NullObserver).map(drop),
+        }
+    }
+
+    /// Check that the spec describes a valid One-Two-Three agent, like
+    /// `check_go`
+    pub fn check_one_two_three(&self) -> Result<()> {
+        match self.name.as_str() {
+            "human" => self.check_params(&[]),
+            _ => self.build_one_two_three(0, NullObserver).map(drop),
+        }
+    }
+
+    /// Build a One-Two-Three agent, like `build_go`
+    pub fn build_one_two_three<O>(&self, seed: u64, observer: O) -> Result<Box<dyn Agent<OneTwoThreeState>>>
+    where
+        O: SearchObserver<one_two_three::Move> + 'static,
+    {
+        let seed = self.seed(seed)?;
+        let evaluator = one_two_three::score_difference;
+        let agent: Box<dyn Agent<OneTwoThreeState>> = match self.name.as_str() {
+            "human" => {
+                self.check_params(&[])?;
+                Box::new(HumanAgent::new())
+            }
+            "random" => {
+                self.check_params(&[])?;
+                Box::new(RandomBot::with_seed(seed))
+            }
+            "minimax" => Box::new(MinimaxBot::new(self.depth()?, evaluator).with_observer(observer)),
+            "pvs" => Box::new(
+                PvsBot::new(self.depth()?, evaluator)
+                    .with_aspiration_window(self.param("window", 10)?)
+                    .with_observer(observer),
+            ),
+            "mtdf" => Box::new(MtdfBot::new(self.depth()?, evaluator).with_observer(observer)),
+            "mcts" => Box::new(MctsBot::with_seed(self.mcts_config()?, RandomBot::with_seed, seed)),
+            "puct" => Box::new(PuctBot::with_seed(self.puct_config()?, UniformEvaluator, seed)),
+            "fast-random" | "rave" | "policy" | "q" | "actor-critic" => bail!("{} only plays Go", self.name),
+            other => bail!("Unknown agent {}, expected one of: {}", other, AGENT_NAMES.join(" ")),
+        };
+        Ok(agent)
+    }
+}
+
+/// `name` or `name:key=value,key=value`
+impl FromStr for AgentSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, params) = match s.split_once(':') {
+            Some((name, params)) => (name, params),
+            None => (s, ""),
+        };
+        let name = name.trim().to_lowercase();
+        if !AGENT_NAMES.contains(&name.as_str()) {
+            bail!("Unknown agent {}, expected one of: {}", name, AGENT_NAMES.join(" "));
+        }
+        let params = params
+            .split(',')
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| {
+                let (key, value) = p.split_once('=').with_context(|| format!("Expected key=value, got {}", p))?;
+                Ok((key.trim().to_lowercase(), value.trim().to_string()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { name, params })
+    }
+}
+
+impl fmt::Display for AgentSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (i, (key, value)) in self.params.iter().enumerate() {
+            let separator = if i == 0 { ':' } else { ',' };
+            write!(f, "{}{}={}", separator, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameState;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec: AgentSpec = "MCTS:rounds=200, parallelism=tree".parse().unwrap();
+        assert_eq!(spec.name, "mcts");
+        assert_eq!(spec.to_string(), "mcts:rounds=200,parallelism=tree");
+        let config = spec.mcts_config().unwrap();
+        assert_eq!(config.rounds, 200);
+        assert_eq!(config.parallelism, Parallelism::Tree);
+
+        assert!("alphago".parse::<AgentSpec>().is_err());
+        assert!("minimax:depth".parse::<AgentSpec>().is_err());
+    }
+
+    #[test]
+    fn test_invalid_params_are_rejected() {
+        let spec: AgentSpec = "minimax:rounds=5".parse().unwrap();
+        assert!(spec.build_go(1, NullObserver).is_err());
+        let spec: AgentSpec = "minimax:depth=deep".parse().unwrap();
+        assert!(spec.build_go(1, NullObserver).is_err());
+        let spec: AgentSpec = "rave".parse().unwrap();
+        assert!(spec.build_one_two_three(1, NullObserver).is_err());
+        let spec: AgentSpec = "policy:encoder=sevenplane".parse().unwrap();
+        assert!(spec.build_go(1, NullObserver).is_err());
+        let spec: AgentSpec = "q:model=q.json,temperature=1".parse().unwrap();
+        assert!(spec.build_go(1, NullObserver).is_err());
+        let invalid = [
+            "minimax:depth=0",
+            "pvs:depth=0",
+            "mtdf:depth=0",
+            "mcts:rounds=0",
+            "rave:rounds=0",
+            "puct:rounds=0",
+            "puct:temperature=-1",
+            "puct:temperature=NaN",
+        ];
+        for spec in invalid {
+            assert!(spec.parse::<AgentSpec>().unwrap().build_go(1, NullObserver).is_err(), "{}", spec);
+        }
+        // Checked before the missing model is loaded
+        for spec in ["q:model=q.json,epsilon=1.5", "q:model=q.json,epsilon=NaN"] {
+            let error = spec.parse::<AgentSpec>().unwrap().build_go(1, NullObserver).err().unwrap();
+            assert!(error.to_string().contains("epsilon"), "{}", spec);
+        }
+        let spec: AgentSpec = "human:depth=2".parse().unwrap();
+        assert!(spec.check_go().is_err());
+        let spec: AgentSpec = "rave".parse().unwrap();
+        assert!(spec.check_one_two_three().is_err());
+    }
+
+    #[test]
+    fn test_built_agents_play_valid_moves() {
+        let game = GoState::new(5);
+        for spec in ["random", "fast-random", "minimax:depth=1", "pvs:depth=1", "mtdf:depth=1", "mcts:rounds=20", "rave:rounds=20", "puct:rounds=20"] {
+            let mut agent = spec.parse::<AgentSpec>().unwrap().build_go(1, NullObserver).unwrap();
+            assert!(game.is_valid_move(&agent.select_move(&game)), "{}", spec);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::{bail, Result};
-use std::rc::Rc;
+use std::sync::Arc;
 use std::str::FromStr;
 use std::fmt;
 use std::fmt::Formatter;
@@ -12,7 +12,7 @@ pub struct Board {
     pub rows: usize,
     pub cols: usize,
     grid: Vec<Option<Color>>,
-    hasher: Rc<ZobristHasher>,
+    hasher: Arc<ZobristHasher>,
     hash: ZobristHash,
 }
 
@@ -22,7 +22,7 @@ impl Board {
             rows: size,
             cols: size,
             grid: vec![None; size * size],
-            hasher: Rc::new(ZobristHasher::new(size)),
+            hasher: Arc::new(ZobristHasher::new(size)),
             hash: ZobristHasher::empty_board()
         }
     }
@@ -51,7 +51,7 @@ impl Board {
 
     fn remove_stone(&mut self, captured_point: &Point) {
         // Assume this is only called for point with stone
-        let player = self.get(captured_point).expect(&format!("Failed to remove stone at point {:?}", captured_point));
+        let player = self.get(captured_point).unwrap_or_else(|| panic!("Failed to remove stone at point {:?}", captured_point));
         self.apply_hash_for_play(player, captured_point);
         self.set(captured_point, None);
     }
@@ -68,7 +68,7 @@ impl Board {
                 explored.push(point);
             }
             for neighbor in point.neighbors().iter().filter(|p| self.is_on_grid(p)) {
-                match self.get(&neighbor) {
+                match self.get(neighbor) {
                     None => {
                         // The group has at least one liberty, return previously captured
                         return captured;
@@ -76,9 +76,9 @@ impl Board {
                     Some(neighbor_color) => {
                         // Ignore opponent's stones and stones that are already added to group
                         if neighbor_color == color
-                            && !captured.contains(&neighbor)
-                            && !explored.contains(&neighbor)
-                            && !unexplored.contains(&neighbor) {
+                            && !captured.contains(neighbor)
+                            && !explored.contains(neighbor)
+                            && !unexplored.contains(neighbor) {
                             unexplored.push(*neighbor);
                         }
                     }
@@ -92,17 +92,61 @@ impl Board {
 
     pub fn is_alive(&self, point: &Point) -> bool {
         assert!(self.get(point).is_some());
-        self.group_without_liberties(point, Vec::new()).len() == 0
+        self.group_without_liberties(point, Vec::new()).is_empty()
+    }
+
+    /// Stones of the group containing the stone at the point
+    pub fn group(&self, point: &Point) -> Vec<Point> {
+        let color = self.get(point).expect("No stone at point");
+        let mut group = vec![*point];
+        let mut unexplored = vec![*point];
+        while let Some(point) = unexplored.pop() {
+            for neighbor in point.neighbors().iter().filter(|p| self.is_on_grid(p)) {
+                if self.get(neighbor) == Some(color) && !group.contains(neighbor) {
+                    group.push(*neighbor);
+                    unexplored.push(*neighbor);
+                }
+            }
+        }
+        group
+    }
+
+    /// Liberties of the group containing the stone at the point
+    pub fn liberties(&self, point: &Point) -> Vec<Point> {
+        let mut liberties = Vec::new();
+        for stone in self.group(point) {
+            for neighbor in stone.neighbors().iter().filter(|p| self.is_on_grid(p)) {
+                if self.get(neighbor).is_none() && !liberties.contains(neighbor) {
+                    liberties.push(*neighbor);
+                }
+            }
+        }
+        liberties
+    }
+
+    /// Number of liberties of the group of each point in row-major order,
+    /// zero for empty points
+    pub fn liberty_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.rows * self.cols];
+        for point in self.points() {
+            let index = (point.row - 1) * self.cols + (point.col - 1);
+            if self.get(&point).is_none() || counts[index] > 0 {
+                continue;
+            }
+            let liberties = self.liberties(&point).len();
+            for stone in self.group(&point) {
+                counts[(stone.row - 1) * self.cols + (stone.col - 1)] = liberties;
+            }
+        }
+        counts
     }
 
     pub fn is_eye(&self, point: &Point, color: Color) -> bool {
         match self.get(point) {
             None => {
                 for neighbor in point.neighbors() {
-                    if self.is_on_grid(&neighbor) {
-                        if self.get(&neighbor) != Some(color) {
-                            return false;
-                        }
+                    if self.is_on_grid(&neighbor) && self.get(&neighbor) != Some(color) {
+                        return false;
                     }
                 }
                 let mut friendly_corners = 0;
@@ -128,7 +172,7 @@ impl Board {
         }
     }
 
-    fn is_on_grid(&self, point: &Point) -> bool {
+    pub(crate) fn is_on_grid(&self, point: &Point) -> bool {
         (1..=self.rows).contains(&point.row) && (1..=self.cols).contains(&point.col)
     }
 
@@ -137,11 +181,11 @@ impl Board {
         self.grid[(point.row - 1) * self.cols + (point.col - 1)]
     }
 
-    pub fn points(&self) -> BoardPoints {
+    pub fn points(&self) -> BoardPoints<'_> {
         BoardPoints::new(self)
     }
 
-    pub fn empty_points(&self) -> EmptyBoardPoints {
+    pub fn empty_points(&self) -> EmptyBoardPoints<'_> {
         EmptyBoardPoints::new(self)
     }
 
@@ -220,14 +264,9 @@ impl<'a> Iterator for EmptyBoardPoints<'a> {
     type Item = Point;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Return first empty point
-        while let Some(p) = self.points.next() {
-            if self.board.get(&p).is_none() {
-                return Some(p);
-            }
-        }
-        // Or return None
-        None
+        // Return first empty point, or None if there are no more
+        let board = self.board;
+        self.points.find(|p| board.get(p).is_none())
     }
 }
 
@@ -274,7 +313,7 @@ impl fmt::Debug for Board {
         for i in 1..=self.cols {
             write!(f, " {:2}", i)?;
         }
-        write!(f, "\n")?;
+        writeln!(f)?;
 
         for row in 1..=self.rows {
             write!(f, "{:2} ", row)?;
@@ -289,7 +328,7 @@ impl fmt::Debug for Board {
                 };
                 write!(f, " {} ", c)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -309,7 +348,7 @@ impl fmt::Display for Board {
                 };
                 let _ = write!(f, "{}", c);
             }
-            let _ = write!(f, "\n");
+            let _ = writeln!(f);
         }
         Ok(())
     }
@@ -354,6 +393,17 @@ mod tests {
         assert!(!board.is_alive(&Point::new(2, 2)));
     }
 
+    #[test]
+    fn test_group_liberties() {
+        let board = r#"xx.
+                             o..
+                             .o."#;
+        let board = Board::from_str(board).unwrap();
+        assert_eq!(board.group(&Point::new(1, 2)).len(), 2);
+        assert_eq!(board.liberties(&Point::new(1, 1)), vec![Point::new(2, 2), Point::new(1, 3)]);
+        assert_eq!(board.liberty_counts(), vec![2, 2, 0, 2, 0, 0, 0, 3, 0]);
+    }
+
     #[test]
     fn test_placing_and_removing_stone_preserves_hash() {
         let mut board = Board::new(19);
@@ -0,0 +1,364 @@
+//! Supervised training data from SGF game collections: every position of
+//! every game encoded as features, labeled with the index of the move played
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::GzDecoder;
+use rand::{Rng, SeedableRng};
+
+use crate::game::go::encoders::{transform_planes, transform_point, Encoder, NUM_SYMMETRIES};
+use crate::game::go::sgf::parse_sgf;
+use crate::game::go::Move;
+use crate::npy::NpyWriter;
+
+/// Part of the data a game's positions belong to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Split {
+    Train,
+    Validation,
+}
+
+/// Receives the samples as they are generated
+pub trait SampleSink {
+    fn add(&mut self, split: Split, features: &[f32], label: usize) -> Result<()>;
+}
+
+#[derive(Clone, Debug)]
+pub struct DatasetConfig {
+    /// Share of games whose positions go to the validation set
+    pub validation_fraction: f64,
+    /// Add all eight rotations and reflections of every position
+    pub augment: bool,
+    /// Rulesets (RU property) whose games are used, compared case
+    /// insensitively. Games without a ruleset are always used.
+    pub rulesets: Vec<String>,
+    /// Seed for choosing the validation games
+    pub seed: u64,
+}
+
+impl Default for DatasetConfig {
+    fn default() -> Self {
+        Self {
+            validation_fraction: 0.1,
+            augment: false,
+            // Rules with the same move legality as `GoState`: no suicide
+            rulesets: ["japanese", "chinese", "korean", "aga", "jp", "cn"].iter().map(|r| r.to_string()).collect(),
+            seed: 0,
+        }
+    }
+}
+
+/// A game that was left out, and why
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedGame {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatasetReport {
+    pub train_games: usize,
+    pub validation_games: usize,
+    pub train_samples: usize,
+    pub validation_samples: usize,
+    pub skipped: Vec<SkippedGame>,
+}
+
+/// Generates samples of games with the encoder. Positions before passes
+/// and resignations have no move label and are left out.
+pub struct DatasetGenerator<'e> {
+    encoder: &'e dyn Encoder,
+    config: DatasetConfig,
+    rng: rand_pcg::Pcg64,
+    report: DatasetReport,
+}
+
+impl<'e> DatasetGenerator<'e> {
+    pub fn new(encoder: &'e dyn Encoder, config: DatasetConfig) -> Result<Self> {
+        let fraction = config.validation_fraction;
+        ensure!((0.0..=1.0).contains(&fraction), "Validation fraction must be between 0 and 1, got {}", fraction);
+        let rng = rand_pcg::Pcg64::seed_from_u64(config.seed);
+        Ok(Self { encoder, config, rng, report: DatasetReport::default() })
+    }
+
+    pub fn report(&self) -> &DatasetReport {
+        &self.report
+    }
+
+    pub fn into_report(self) -> DatasetReport {
+        self.report
+    }
+
+    /// Add the games of an SGF file, a directory searched recursively for
+    /// SGF files, or a tar archive of them, optionally gzipped
+    pub fn add_path(&mut self, path: &Path, sink: &mut dyn SampleSink) -> Result<()> {
+        if path.is_dir() {
+            for file in sgf_files(path)? {
+                let text = fs::read_to_string(&file);
+                self.add_text(&file.display().to_string(), text.map_err(Into::into), sink)?;
+            }
+            return Ok(());
+        }
+
+        let name = path.to_string_lossy();
+        if name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            let file = File::open(path).with_context(|| format!("Failed to open {}", name))?;
+            let reader: Box<dyn Read> = if name.ends_with(".tar") { Box::new(file) } else { Box::new(GzDecoder::new(file)) };
+            let mut archive = tar::Archive::new(reader);
+            for entry in archive.entries().with_context(|| format!("Failed to read {}", name))? {
+                let mut entry = entry.with_context(|| format!("Failed to read {}", name))?;
+                let entry_name = format!("{}:{}", name, entry.path()?.display());
+                if !entry.header().entry_type().is_file() || !entry_name.to_lowercase().ends_with(".sgf") {
+                    continue;
+                }
+                let mut text = String::new();
+                let read = entry.read_to_string(&mut text).map(|_| text);
+                self.add_text(&entry_name, read.map_err(Into::into), sink)?;
+            }
+            return Ok(());
+        }
+
+        let text = fs::read_to_string(path);
+        self.add_text(&name, text.map_err(Into::into), sink)
+    }
+
+    fn add_text(&mut self, name: &str, text: Result<String>, sink: &mut dyn SampleSink) -> Result<()> {
+        let result = text.and_then(|text| self.add_game(&text, sink));
+        if let Err(e) = result {
+            self.report.skipped.push(SkippedGame { name: name.to_string(), reason: format!("{:#}", e) });
+        }
+        Ok(())
+    }
+
+    /// Add the positions of the game in SGF. Games that can't be used are
+    /// rejected before any of their samples are added.
+    pub fn add_game(&mut self, sgf: &str, sink: &mut dyn SampleSink) -> Result<()> {
+        let game = parse_sgf(sgf)?;
+        if let Some(ruleset) = &game.ruleset {
+            if !self.config.rulesets.iter().any(|r| r.eq_ignore_ascii_case(ruleset)) {
+                bail!("Unsupported ruleset {}", ruleset);
+            }
+        }
+        let (_, rows, cols) = self.encoder.shape();
+        if game.board_size != rows || game.board_size != cols {
+            bail!("Board size {} does not match the encoder's {}", game.board_size, rows);
+        }
+
+        // Replay the whole game before adding samples, so that illegal games
+        // leave no trace
+        let mut samples = Vec::new();
+        game.replay(|state, the_move| {
+            if let Move::Play(point) = the_move {
+                samples.push((self.encoder.encode(state), *point));
+            }
+        })?;
+
+        let split = if self.rng.gen_bool(self.config.validation_fraction) { Split::Validation } else { Split::Train };
+        let symmetries = if self.config.augment { NUM_SYMMETRIES } else { 1 };
+        for (features, point) in &samples {
+            for symmetry in 0..symmetries {
+                let label = self.encoder.encode_point(&transform_point(point, game.board_size, symmetry));
+                if symmetry == 0 {
+                    sink.add(split, features, label)?;
+                } else {
+                    sink.add(split, &transform_planes(self.encoder, features, symmetry), label)?;
+                }
+            }
+        }
+
+        let count = samples.len() * symmetries;
+        match split {
+            Split::Train => {
+                self.report.train_games += 1;
+                self.report.train_samples += count;
+            }
+            Split::Validation => {
+                self.report.validation_games += 1;
+                self.report.validation_samples += count;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SGF files under the directory, in a stable order
+fn sgf_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("sgf")) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Writes the samples of each split to `.npy` files in a directory:
+/// `{split}_features.npy` of f32 with shape (samples, planes, rows,
+/// columns) and `{split}_labels.npy` of i64 with shape (samples,)
+pub struct NpySampleSink {
+    files: [(NpyFile<f32>, NpyFile<i64>); 2],
+}
+
+type NpyFile<T> = NpyWriter<BufWriter<File>, T>;
+
+impl NpySampleSink {
+    pub fn create(dir: &Path, shape: (usize, usize, usize)) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let features = [shape.0, shape.1, shape.2];
+        Ok(Self {
+            files: [
+                (
+                    NpyWriter::create(&dir.join("train_features.npy"), &features)?,
+                    NpyWriter::create(&dir.join("train_labels.npy"), &[])?,
+                ),
+                (
+                    NpyWriter::create(&dir.join("validation_features.npy"), &features)?,
+                    NpyWriter::create(&dir.join("validation_labels.npy"), &[])?,
+                ),
+            ],
+        })
+    }
+
+    /// Write the final numbers of samples to the files
+    pub fn finish(self) -> Result<()> {
+        for (features, labels) in self.files {
+            features.finish()?;
+            labels.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl SampleSink for NpySampleSink {
+    fn add(&mut self, split: Split, features: &[f32], label: usize) -> Result<()> {
+        let (feature_file, label_file) = &mut self.files[split as usize];
+        feature_file.write(features)?;
+        label_file.write(&[label as i64])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::OnePlaneEncoder;
+
+    #[derive(Default)]
+    struct VecSink {
+        samples: Vec<(Split, Vec<f32>, usize)>,
+    }
+
+    impl SampleSink for VecSink {
+        fn add(&mut self, split: Split, features: &[f32], label: usize) -> Result<()> {
+            self.samples.push((split, features.to_vec(), label));
+            Ok(())
+        }
+    }
+
+    const GAME: &str = "(;GM[1]SZ[5]RU[Chinese];B[cc];W[bb];B[];W[dd])";
+
+    fn config(augment: bool) -> DatasetConfig {
+        DatasetConfig { validation_fraction: 0.0, augment, ..DatasetConfig::default() }
+    }
+
+    #[test]
+    fn test_samples_of_game() {
+        let encoder = OnePlaneEncoder::new(5);
+        let mut generator = DatasetGenerator::new(&encoder, config(false)).unwrap();
+        let mut sink = VecSink::default();
+        generator.add_game(GAME, &mut sink).unwrap();
+
+        // The pass has no sample
+        let labels: Vec<usize> = sink.samples.iter().map(|s| s.2).collect();
+        assert_eq!(labels, vec![12, 6, 18]);
+        assert!(sink.samples[0].1.iter().all(|v| *v == 0.0));
+        assert_eq!(generator.report().train_samples, 3);
+    }
+
+    #[test]
+    fn test_augmentation_adds_every_symmetry() {
+        let encoder = OnePlaneEncoder::new(5);
+        let mut generator = DatasetGenerator::new(&encoder, config(true)).unwrap();
+        let mut sink = VecSink::default();
+        generator.add_game(GAME, &mut sink).unwrap();
+        assert_eq!(sink.samples.len(), 24);
+
+        // The second move B2 maps to the points diagonally next to each
+        // corner, and black's first stone to the center
+        let mut labels: Vec<usize> = sink.samples[8..16].iter().map(|s| s.2).collect();
+        labels.sort_unstable();
+        labels.dedup();
+        assert_eq!(labels, vec![6, 8, 16, 18]);
+        for (_, features, _) in &sink.samples[8..16] {
+            assert_eq!(features[12], -1.0);
+            assert_eq!(features.iter().sum::<f32>(), -1.0);
+        }
+    }
+
+    #[test]
+    fn test_bad_games_are_skipped_and_reported() {
+        let dir = std::env::temp_dir().join(format!("bgai-dataset-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("good.sgf"), GAME).unwrap();
+        fs::write(dir.join("nested/illegal.sgf"), "(;SZ[5];B[cc];W[cc])").unwrap();
+        fs::write(dir.join("nested/rules.SGF"), "(;SZ[5]RU[Tromp-Taylor];B[cc])").unwrap();
+        fs::write(dir.join("big.sgf"), "(;SZ[9];B[cc])").unwrap();
+        fs::write(dir.join("notes.txt"), "not a game").unwrap();
+
+        let encoder = OnePlaneEncoder::new(5);
+        let mut generator = DatasetGenerator::new(&encoder, config(false)).unwrap();
+        let mut sink = VecSink::default();
+        generator.add_path(&dir, &mut sink).unwrap();
+        let report = generator.into_report();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.train_games, 1);
+        assert_eq!(sink.samples.len(), 3);
+        let reasons: Vec<&str> = report.skipped.iter().map(|s| s.reason.as_str()).collect();
+        assert_eq!(reasons.len(), 3);
+        assert!(reasons.iter().any(|r| r.contains("Illegal move")));
+        assert!(reasons.iter().any(|r| r.contains("Unsupported ruleset Tromp-Taylor")));
+        assert!(reasons.iter().any(|r| r.contains("Board size 9")));
+    }
+
+    #[test]
+    fn test_tar_archive() {
+        let path = std::env::temp_dir().join(format!("bgai-dataset-{}.tar", std::process::id()));
+        {
+            let mut builder = tar::Builder::new(File::create(&path).unwrap());
+            for (name, game) in [("a.sgf", GAME), ("b.sgf", GAME), ("readme", "text")] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(game.len() as u64);
+                header.set_cksum();
+                builder.append_data(&mut header, name, game.as_bytes()).unwrap();
+            }
+            builder.finish().unwrap();
+        }
+
+        let encoder = OnePlaneEncoder::new(5);
+        let mut generator = DatasetGenerator::new(&encoder, DatasetConfig { validation_fraction: 1.0, ..config(false) }).unwrap();
+        let mut sink = VecSink::default();
+        generator.add_path(&path, &mut sink).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(generator.report().validation_games, 2);
+        assert!(sink.samples.iter().all(|s| s.0 == Split::Validation));
+    }
+
+    #[test]
+    fn test_validation_fraction_out_of_range_is_rejected() {
+        let encoder = OnePlaneEncoder::new(5);
+        for fraction in [-0.1, 1.5, f64::NAN] {
+            assert!(DatasetGenerator::new(&encoder, DatasetConfig { validation_fraction: fraction, ..config(false) }).is_err());
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! The 48 feature planes of the AlphaGo policy network (Silver et al. 2016),
+//! relative to the player to move:
+//!
+//! - 0-2. stones of the player, of the opponent, and empty points
+//! - 3. ones
+//! - 4-11. turns since a stone was played, 1 to 8 or more
+//! - 12-19. liberties of the group of a stone, 1 to 8 or more
+//! - 20-27. liberties of the group after playing a legal move, 1 to 8 or more
+//! - 28-35. opponent stones a legal move would capture, 0 to 7 or more
+//! - 36-43. stones a legal move would leave in atari, 1 to 8 or more
+//! - 44. legal moves that capture an opponent group in a ladder
+//! - 45. legal moves that escape a ladder
+//! - 46. legal moves that don't fill the player's own eye
+//! - 47. zeros
+
+use crate::game::go::encoders::{index_point, point_index, Encoder};
+use crate::game::go::ladder::{is_ladder_capture, is_ladder_escape};
+use crate::game::go::{GoState, Move, Point};
+use crate::game::GameState;
+
+const STONES: usize = 0;
+const ONES: usize = 3;
+const TURNS_SINCE: usize = 4;
+const LIBERTIES: usize = 12;
+const LIBERTIES_AFTER: usize = 20;
+const CAPTURE_SIZE: usize = 28;
+const SELF_ATARI_SIZE: usize = 36;
+const LADDER_CAPTURE: usize = 44;
+const LADDER_ESCAPE: usize = 45;
+const SENSIBLENESS: usize = 46;
+const NUM_PLANES: usize = 48;
+
+pub struct AlphaGoEncoder {
+    board_size: usize,
+}
+
+impl AlphaGoEncoder {
+    pub fn new(board_size: usize) -> Self {
+        Self { board_size }
+    }
+
+    /// Number of moves since the stone at each point was played, or None
+    /// for stones placed before the recorded moves
+    fn turns_since(&self, game: &GoState) -> Vec<Option<usize>> {
+        let mut turns = vec![None; self.num_points()];
+        for (i, the_move) in game.moves.iter().enumerate() {
+            if let Move::Play(point) = the_move {
+                turns[self.encode_point(point)] = Some(game.moves.len() - i);
+            }
+        }
+        turns
+    }
+}
+
+/// Index of the plane for the count, counts from `first` up to seven more
+/// sharing the last plane
+fn bucket(plane: usize, count: usize, first: usize) -> usize {
+    plane + count.clamp(first, first + 7) - first
+}
+
+impl Encoder for AlphaGoEncoder {
+    fn name(&self) -> &'static str {
+        "alphago"
+    }
+
+    fn encode(&self, game: &GoState) -> Vec<f32> {
+        let num_points = self.num_points();
+        let mut planes = vec![0.0; NUM_PLANES * num_points];
+        planes[ONES * num_points..(ONES + 1) * num_points].fill(1.0);
+
+        let board = &game.board;
+        let color = game.next_player.color;
+        let liberties = board.liberty_counts();
+        let turns_since = self.turns_since(game);
+
+        for point in board.points() {
+            let index = self.encode_point(&point);
+            let mut set = |plane: usize| planes[plane * num_points + index] = 1.0;
+
+            match board.get(&point) {
+                Some(stone) => {
+                    set(STONES + if stone == color { 0 } else { 1 });
+                    set(bucket(TURNS_SINCE, turns_since[index].unwrap_or(8), 1));
+                    set(bucket(LIBERTIES, liberties[index], 1));
+                }
+                None => {
+                    set(STONES + 2);
+                    let the_move = Move::Play(point);
+                    if !game.is_valid_move(&the_move) {
+                        continue;
+                    }
+                    let mut next = board.clone();
+                    let captured = next.place_stone(color, &point).expect("Valid move");
+                    let liberties_after = next.liberties(&point).len();
+                    set(bucket(LIBERTIES_AFTER, liberties_after, 1));
+                    set(bucket(CAPTURE_SIZE, captured, 0));
+                    if liberties_after == 1 {
+                        set(bucket(SELF_ATARI_SIZE, next.group(&point).len(), 1));
+                    }
+                    if is_ladder_capture(board, color, &point) {
+                        set(LADDER_CAPTURE);
+                    }
+                    if is_ladder_escape(board, color, &point) {
+                        set(LADDER_ESCAPE);
+                    }
+                    if !board.is_eye(&point, color) {
+                        set(SENSIBLENESS);
+                    }
+                }
+            }
+        }
+        planes
+    }
+
+    fn encode_point(&self, point: &Point) -> usize {
+        point_index(point, self.board_size)
+    }
+
+    fn decode_point_index(&self, index: usize) -> Point {
+        index_point(index, self.board_size)
+    }
+
+    fn num_points(&self) -> usize {
+        self.board_size * self.board_size
+    }
+
+    fn shape(&self) -> (usize, usize, usize) {
+        (NUM_PLANES, self.board_size, self.board_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::tests::assert_point_round_trip;
+    use crate::game::go::{Board, Player};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_point_round_trip() {
+        for size in [9, 19] {
+            assert_point_round_trip(&AlphaGoEncoder::new(size), size);
+        }
+    }
+
+    #[test]
+    fn test_constant_and_stone_planes() {
+        let encoder = AlphaGoEncoder::new(9);
+        let game = GoState::new(9)
+            .apply_move(&Move::Play(Point::new(3, 3)))
+            .apply_move(&Move::Play(Point::new(7, 7)))
+            .apply_move(&Move::Play(Point::new(3, 7)));
+        let planes = encoder.encode(&game);
+        assert_eq!(planes.len(), 48 * 81);
+        let plane = |p: usize| &planes[p * 81..(p + 1) * 81];
+        let value = |p: usize, row, col| plane(p)[encoder.encode_point(&Point::new(row, col))];
+
+        assert!(plane(ONES).iter().all(|v| *v == 1.0));
+        assert!(plane(47).iter().all(|v| *v == 0.0));
+        // Every point is one of the player's, the opponent's or empty
+        let stone_planes: f32 = planes[..3 * 81].iter().sum();
+        assert_eq!(stone_planes, 81.0);
+        // White to move
+        assert_eq!(value(STONES, 7, 7), 1.0);
+        assert_eq!(value(STONES + 1, 3, 3), 1.0);
+
+        // C3 was played three turns ago, C7 one turn ago
+        assert_eq!(value(TURNS_SINCE + 2, 3, 3), 1.0);
+        assert_eq!(value(TURNS_SINCE, 3, 7), 1.0);
+        assert_eq!(value(LIBERTIES + 3, 3, 3), 1.0);
+        // Every empty point is a legal and sensible move with no captures
+        assert_eq!(plane(SENSIBLENESS).iter().sum::<f32>(), 78.0);
+        assert_eq!(plane(CAPTURE_SIZE).iter().sum::<f32>(), 78.0);
+    }
+
+    #[test]
+    fn test_capture_self_atari_and_ladder_planes() {
+        let board = r#"
+        ......o..
+        .......o.
+        .........
+        ....x....
+        ...xo....
+        .....x...
+        .........
+        .x.......
+        xo......."#;
+        let game = GoState::from_board(Board::from_str(board).unwrap(), Player::black());
+        let encoder = AlphaGoEncoder::new(9);
+        let planes = encoder.encode(&game);
+        let value = |p: usize, row, col| planes[p * 81 + encoder.encode_point(&Point::new(row, col))];
+
+        assert_eq!(value(LADDER_CAPTURE, 5, 6), 1.0);
+        assert_eq!(value(LADDER_CAPTURE, 1, 1), 0.0);
+        // Black A9 is in atari, and A8 connects it to B8 with three liberties
+        assert_eq!(value(LIBERTIES, 9, 1), 1.0);
+        assert_eq!(value(LIBERTIES_AFTER + 2, 8, 1), 1.0);
+        // C9 captures white B9
+        assert_eq!(value(CAPTURE_SIZE + 1, 9, 3), 1.0);
+        assert_eq!(value(CAPTURE_SIZE, 9, 3), 0.0);
+        assert_eq!(value(CAPTURE_SIZE, 8, 1), 1.0);
+        // H1 between the white stones is self-atari
+        assert_eq!(value(SELF_ATARI_SIZE, 1, 8), 1.0);
+        assert_eq!(value(SELF_ATARI_SIZE, 8, 1), 0.0);
+    }
+}
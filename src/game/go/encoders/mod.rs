@@ -0,0 +1,183 @@
+//! Encoders turning Go positions into feature planes for neural networks,
+//! and points into indices of the network's move outputs
+
+pub mod alphago;
+pub mod oneplane;
+pub mod sevenplane;
+pub mod simple;
+
+pub use alphago::AlphaGoEncoder;
+pub use oneplane::OnePlaneEncoder;
+pub use sevenplane::SevenPlaneEncoder;
+pub use simple::SimpleEncoder;
+
+use crate::game::go::{GoState, Move, Point};
+
+pub trait Encoder {
+    fn name(&self) -> &'static str;
+
+    /// Feature planes of the position, flattened in plane, row, column order
+    fn encode(&self, game: &GoState) -> Vec<f32>;
+
+    /// Index of the point on a plane, in row-major order
+    fn encode_point(&self, point: &Point) -> usize;
+
+    /// Inverse of `encode_point`
+    fn decode_point_index(&self, index: usize) -> Point;
+
+    /// Number of points on the board, i.e. of move outputs
+    fn num_points(&self) -> usize;
+
+    /// Number of planes, rows and columns of the encoding
+    fn shape(&self) -> (usize, usize, usize);
+}
+
+/// Encoder by its name and the board size
+pub fn get_encoder_by_name(name: &str, board_size: usize) -> anyhow::Result<Box<dyn Encoder + Send + Sync>> {
+    Ok(match name {
+        "oneplane" => Box::new(OnePlaneEncoder::new(board_size)),
+        "sevenplane" => Box::new(SevenPlaneEncoder::new(board_size)),
+        "simple" => Box::new(SimpleEncoder::new(board_size)),
+        "alphago" => Box::new(AlphaGoEncoder::new(board_size)),
+        other => anyhow::bail!("Unknown encoder {}", other),
+    })
+}
+
+/// Number of rotations and reflections of the board
+pub const NUM_SYMMETRIES: usize = 8;
+
+/// The point under one of the symmetries of a square board: `symmetry % 4`
+/// quarter turns, reflected across the vertical axis if `symmetry >= 4`.
+/// Symmetry 0 is the identity.
+pub fn transform_point(point: &Point, board_size: usize, symmetry: usize) -> Point {
+    let (mut row, mut col) = (point.row, point.col);
+    for _ in 0..symmetry % 4 {
+        // Quarter turn clockwise
+        let turned = (col, board_size + 1 - row);
+        row = turned.0;
+        col = turned.1;
+    }
+    if symmetry >= 4 {
+        col = board_size + 1 - col;
+    }
+    Point::new(row, col)
+}
+
+/// Encoded planes under the symmetry, consistent with `transform_point` for
+/// the move labels
+pub fn transform_planes(encoder: &dyn Encoder, planes: &[f32], symmetry: usize) -> Vec<f32> {
+    let (_, rows, _) = encoder.shape();
+    let num_points = encoder.num_points();
+    let mut transformed = vec![0.0; planes.len()];
+    for index in 0..num_points {
+        let point = transform_point(&encoder.decode_point_index(index), rows, symmetry);
+        let target = encoder.encode_point(&point);
+        for plane in 0..planes.len() / num_points {
+            transformed[plane * num_points + target] = planes[plane * num_points + index];
+        }
+    }
+    transformed
+}
+
+/// Row-major index of the point on a board with the number of columns
+fn point_index(point: &Point, cols: usize) -> usize {
+    (point.row - 1) * cols + (point.col - 1)
+}
+
+fn index_point(index: usize, cols: usize) -> Point {
+    Point::new(index / cols + 1, index % cols + 1)
+}
+
+/// Whether playing the empty point would retake a ko for the player to move
+fn is_ko_point(game: &GoState, point: &Point) -> bool {
+    let color = game.next_player.color;
+    let the_move = Move::Play(*point);
+    game.board.get(point).is_none()
+        && !game.is_move_self_capture(color, &the_move)
+        && game.does_move_violate_ko(color, &the_move)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::game::go::{Board, Player};
+    use crate::game::GameState;
+    use std::str::FromStr;
+
+    /// Every point survives encoding and decoding, and indices cover the
+    /// move outputs exactly once
+    pub(crate) fn assert_point_round_trip(encoder: &dyn Encoder, board_size: usize) {
+        assert_eq!(encoder.num_points(), board_size * board_size);
+        let board = Board::new(board_size);
+        let mut seen = vec![false; encoder.num_points()];
+        for point in board.points() {
+            let index = encoder.encode_point(&point);
+            assert_eq!(encoder.decode_point_index(index), point);
+            assert!(!seen[index]);
+            seen[index] = true;
+        }
+        for index in 0..encoder.num_points() {
+            assert_eq!(encoder.encode_point(&encoder.decode_point_index(index)), index);
+        }
+    }
+
+    /// Position where white can't immediately retake the ko at B2, with
+    /// white to move
+    pub(crate) fn ko_position() -> (GoState, Point) {
+        let board = r#"
+        .x...
+        x.x..
+        o.o..
+        .o...
+        ....."#;
+        let game = GoState::from_board(Board::from_str(board).unwrap(), Player::white());
+        let game = game
+            .apply_move(&Move::Play(Point::new(2, 2)))
+            .apply_move(&Move::Play(Point::new(3, 2)));
+        (game, Point::new(2, 2))
+    }
+
+    #[test]
+    fn test_ko_point() {
+        let (game, ko) = ko_position();
+        assert!(is_ko_point(&game, &ko));
+        assert!(!is_ko_point(&game, &Point::new(5, 5)));
+    }
+
+    #[test]
+    fn test_symmetries_are_distinct_permutations() {
+        let board = Board::new(5);
+        let corner = Point::new(1, 2);
+        let mut images = Vec::new();
+        for symmetry in 0..NUM_SYMMETRIES {
+            let mut seen: Vec<Point> = board.points().map(|p| transform_point(&p, 5, symmetry)).collect();
+            seen.sort_by_key(|p| (p.row, p.col));
+            seen.dedup();
+            assert_eq!(seen.len(), 25);
+            images.push(transform_point(&corner, 5, symmetry));
+        }
+        images.sort_by_key(|p| (p.row, p.col));
+        images.dedup();
+        assert_eq!(images.len(), 8);
+        assert_eq!(transform_point(&corner, 5, 0), corner);
+    }
+
+    #[test]
+    fn test_transformed_planes_follow_points() {
+        let encoder = OnePlaneEncoder::new(5);
+        let game = GoState::new(5).apply_move(&Move::Play(Point::new(1, 2)));
+        let planes = encoder.encode(&game);
+        for symmetry in 0..NUM_SYMMETRIES {
+            let transformed = transform_planes(&encoder, &planes, symmetry);
+            let stone = transform_point(&Point::new(1, 2), 5, symmetry);
+            assert_eq!(transformed[encoder.encode_point(&stone)], -1.0);
+            assert_eq!(transformed.iter().sum::<f32>(), -1.0);
+        }
+    }
+
+    #[test]
+    fn test_get_encoder_by_name() {
+        assert_eq!(get_encoder_by_name("sevenplane", 9).unwrap().shape(), (7, 9, 9));
+        assert!(get_encoder_by_name("fourplane", 9).is_err());
+    }
+}
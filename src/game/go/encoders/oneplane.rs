@@ -0,0 +1,77 @@
+//! The simplest encoding: a single plane with 1 for the stones of the player
+//! to move, -1 for the opponent's stones and 0 for empty points
+
+use crate::game::go::encoders::{index_point, point_index, Encoder};
+use crate::game::go::{GoState, Point};
+
+pub struct OnePlaneEncoder {
+    board_size: usize,
+}
+
+impl OnePlaneEncoder {
+    pub fn new(board_size: usize) -> Self {
+        Self { board_size }
+    }
+}
+
+impl Encoder for OnePlaneEncoder {
+    fn name(&self) -> &'static str {
+        "oneplane"
+    }
+
+    fn encode(&self, game: &GoState) -> Vec<f32> {
+        let mut planes = vec![0.0; self.num_points()];
+        let next_color = game.next_player.color;
+        for point in game.board.points() {
+            if let Some(color) = game.board.get(&point) {
+                planes[self.encode_point(&point)] = if color == next_color { 1.0 } else { -1.0 };
+            }
+        }
+        planes
+    }
+
+    fn encode_point(&self, point: &Point) -> usize {
+        point_index(point, self.board_size)
+    }
+
+    fn decode_point_index(&self, index: usize) -> Point {
+        index_point(index, self.board_size)
+    }
+
+    fn num_points(&self) -> usize {
+        self.board_size * self.board_size
+    }
+
+    fn shape(&self) -> (usize, usize, usize) {
+        (1, self.board_size, self.board_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::tests::assert_point_round_trip;
+    use crate::game::go::Move;
+    use crate::game::GameState;
+
+    #[test]
+    fn test_point_round_trip() {
+        for size in [5, 9, 19] {
+            assert_point_round_trip(&OnePlaneEncoder::new(size), size);
+        }
+    }
+
+    #[test]
+    fn test_stones_are_relative_to_player_to_move() {
+        let encoder = OnePlaneEncoder::new(5);
+        let game = GoState::new(5)
+            .apply_move(&Move::Play(Point::new(1, 1)))
+            .apply_move(&Move::Play(Point::new(2, 3)));
+        let planes = encoder.encode(&game);
+        assert_eq!(planes.len(), 25);
+        // Black to move
+        assert_eq!(planes[encoder.encode_point(&Point::new(1, 1))], 1.0);
+        assert_eq!(planes[encoder.encode_point(&Point::new(2, 3))], -1.0);
+        assert_eq!(planes.iter().filter(|v| **v == 0.0).count(), 23);
+    }
+}
@@ -0,0 +1,96 @@
+//! Stones bucketed by the liberties of their group, plus ko:
+//!
+//! 0-2. stones of the player to move with 1, 2 and 3 or more liberties
+//! 3-5. the opponent's stones with 1, 2 and 3 or more liberties
+//! 6. points where the player to move can't play because of ko
+
+use crate::game::go::encoders::{index_point, is_ko_point, point_index, Encoder};
+use crate::game::go::{GoState, Point};
+
+pub struct SevenPlaneEncoder {
+    board_size: usize,
+}
+
+impl SevenPlaneEncoder {
+    pub fn new(board_size: usize) -> Self {
+        Self { board_size }
+    }
+}
+
+impl Encoder for SevenPlaneEncoder {
+    fn name(&self) -> &'static str {
+        "sevenplane"
+    }
+
+    fn encode(&self, game: &GoState) -> Vec<f32> {
+        let num_points = self.num_points();
+        let mut planes = vec![0.0; 7 * num_points];
+        let liberties = game.board.liberty_counts();
+        for point in game.board.points() {
+            let index = self.encode_point(&point);
+            match game.board.get(&point) {
+                Some(color) => {
+                    let offset = if color == game.next_player.color { 0 } else { 3 };
+                    let bucket = liberties[index].min(3) - 1;
+                    planes[(offset + bucket) * num_points + index] = 1.0;
+                }
+                None => {
+                    if is_ko_point(game, &point) {
+                        planes[6 * num_points + index] = 1.0;
+                    }
+                }
+            }
+        }
+        planes
+    }
+
+    fn encode_point(&self, point: &Point) -> usize {
+        point_index(point, self.board_size)
+    }
+
+    fn decode_point_index(&self, index: usize) -> Point {
+        index_point(index, self.board_size)
+    }
+
+    fn num_points(&self) -> usize {
+        self.board_size * self.board_size
+    }
+
+    fn shape(&self) -> (usize, usize, usize) {
+        (7, self.board_size, self.board_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::tests::{assert_point_round_trip, ko_position};
+
+    #[test]
+    fn test_point_round_trip() {
+        for size in [5, 9, 19] {
+            assert_point_round_trip(&SevenPlaneEncoder::new(size), size);
+        }
+    }
+
+    #[test]
+    fn test_liberty_planes_and_ko() {
+        let encoder = SevenPlaneEncoder::new(5);
+        let (game, ko) = ko_position();
+        let planes = encoder.encode(&game);
+        assert_eq!(planes.len(), 7 * 25);
+        let value = |plane: usize, row, col| planes[plane * 25 + encoder.encode_point(&Point::new(row, col))];
+
+        // White to move. The black stone that captured is in atari.
+        assert_eq!(value(3, 3, 2), 1.0);
+        // The black stone at A2 has two liberties
+        assert_eq!(value(4, 2, 1), 1.0);
+        // White's A3 stone is in atari too
+        assert_eq!(value(0, 3, 1), 1.0);
+        assert_eq!(value(6, ko.row, ko.col), 1.0);
+        assert_eq!(planes[6 * 25..].iter().sum::<f32>(), 1.0);
+        // Every stone is on exactly one plane
+        let stones = game.board.points().filter(|p| game.board.get(p).is_some()).count();
+        assert_eq!(planes[..6 * 25].iter().sum::<f32>(), stones as f32);
+    }
+}
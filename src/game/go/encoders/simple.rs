@@ -0,0 +1,105 @@
+//! Stones by color and liberties, whose turn it is, and ko:
+//!
+//! 0-3. black stones with 1, 2, 3 and 4 or more liberties
+//! 4-7. white stones with 1, 2, 3 and 4 or more liberties
+//! 8. ones if black is to move
+//! 9. ones if white is to move
+//! 10. points where the player to move can't play because of ko
+
+use crate::game::go::encoders::{index_point, is_ko_point, point_index, Encoder};
+use crate::game::go::{Color, GoState, Point};
+
+pub struct SimpleEncoder {
+    board_size: usize,
+}
+
+impl SimpleEncoder {
+    pub fn new(board_size: usize) -> Self {
+        Self { board_size }
+    }
+}
+
+impl Encoder for SimpleEncoder {
+    fn name(&self) -> &'static str {
+        "simple"
+    }
+
+    fn encode(&self, game: &GoState) -> Vec<f32> {
+        let num_points = self.num_points();
+        let mut planes = vec![0.0; 11 * num_points];
+        let turn_plane = match game.next_player.color {
+            Color::Black => 8,
+            Color::White => 9,
+        };
+        planes[turn_plane * num_points..(turn_plane + 1) * num_points].fill(1.0);
+
+        let liberties = game.board.liberty_counts();
+        for point in game.board.points() {
+            let index = self.encode_point(&point);
+            match game.board.get(&point) {
+                Some(color) => {
+                    let offset = match color {
+                        Color::Black => 0,
+                        Color::White => 4,
+                    };
+                    let bucket = liberties[index].min(4) - 1;
+                    planes[(offset + bucket) * num_points + index] = 1.0;
+                }
+                None => {
+                    if is_ko_point(game, &point) {
+                        planes[10 * num_points + index] = 1.0;
+                    }
+                }
+            }
+        }
+        planes
+    }
+
+    fn encode_point(&self, point: &Point) -> usize {
+        point_index(point, self.board_size)
+    }
+
+    fn decode_point_index(&self, index: usize) -> Point {
+        index_point(index, self.board_size)
+    }
+
+    fn num_points(&self) -> usize {
+        self.board_size * self.board_size
+    }
+
+    fn shape(&self) -> (usize, usize, usize) {
+        (11, self.board_size, self.board_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::tests::{assert_point_round_trip, ko_position};
+
+    #[test]
+    fn test_point_round_trip() {
+        for size in [5, 9, 19] {
+            assert_point_round_trip(&SimpleEncoder::new(size), size);
+        }
+    }
+
+    #[test]
+    fn test_color_liberty_turn_and_ko_planes() {
+        let encoder = SimpleEncoder::new(5);
+        let (game, ko) = ko_position();
+        let planes = encoder.encode(&game);
+        assert_eq!(planes.len(), 11 * 25);
+        let value = |plane: usize, row, col| planes[plane * 25 + encoder.encode_point(&Point::new(row, col))];
+
+        // The capturing black stone and white A3 are in atari, and white
+        // C3 has 2 liberties
+        assert_eq!(value(0, 3, 2), 1.0);
+        assert_eq!(value(4, 3, 1), 1.0);
+        assert_eq!(value(5, 3, 3), 1.0);
+        // White to move
+        assert!(planes[8 * 25..9 * 25].iter().all(|v| *v == 0.0));
+        assert!(planes[9 * 25..10 * 25].iter().all(|v| *v == 1.0));
+        assert_eq!(value(10, ko.row, ko.col), 1.0);
+    }
+}
@@ -0,0 +1,166 @@
+//! Ladder reading: whether a group in atari can be chased to capture by
+//! repeated ataris. Ko and the attacker's own weaknesses beyond being
+//! captured are not considered.
+
+use crate::game::go::board::Board;
+use crate::game::go::types::{Color, Point};
+
+/// Reading stops at this many moves, and the prey is considered escaped
+const MAX_LADDER_DEPTH: usize = 200;
+
+/// Play the stone on a copy of the board, or None if the point is taken or
+/// the move would be suicide
+fn try_play(board: &Board, color: Color, point: &Point) -> Option<Board> {
+    if board.get(point).is_some() {
+        return None;
+    }
+    let mut next = board.clone();
+    next.place_stone(color, point).ok()?;
+    if next.liberties(point).is_empty() {
+        return None;
+    }
+    Some(next)
+}
+
+/// Whether the group of the stone at `prey` is captured in a ladder with the
+/// prey to move. The group must be in atari.
+pub fn is_captured_in_ladder(board: &Board, prey: &Point) -> bool {
+    prey_is_captured(board, prey, 0)
+}
+
+fn prey_is_captured(board: &Board, prey: &Point, depth: usize) -> bool {
+    let liberties = board.liberties(prey);
+    if liberties.len() != 1 {
+        return liberties.is_empty();
+    }
+    if depth > MAX_LADDER_DEPTH {
+        return false;
+    }
+    let color = board.get(prey).unwrap();
+
+    // Capture an adjacent attacking group in atari
+    for stone in board.group(prey) {
+        for neighbor in stone.neighbors().iter().filter(|p| board.is_on_grid(p)) {
+            if board.get(neighbor) != Some(color.other()) {
+                continue;
+            }
+            let attacker_liberties = board.liberties(neighbor);
+            if attacker_liberties.len() != 1 {
+                continue;
+            }
+            if let Some(next) = try_play(board, color, &attacker_liberties[0]) {
+                if !attacker_captures(&next, prey, depth + 1) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    // Extend from the only liberty
+    match try_play(board, color, &liberties[0]) {
+        Some(next) => attacker_captures(&next, prey, depth + 1),
+        None => true,
+    }
+}
+
+/// Whether the attacker, to move, captures the group at `prey` by ladder
+fn attacker_captures(board: &Board, prey: &Point, depth: usize) -> bool {
+    let liberties = board.liberties(prey);
+    match liberties.len() {
+        0 | 1 => return true,
+        2 => {}
+        _ => return false,
+    }
+    if depth > MAX_LADDER_DEPTH {
+        return false;
+    }
+    let attacker = board.get(prey).unwrap().other();
+    liberties.iter().any(|liberty| match try_play(board, attacker, liberty) {
+        Some(next) => prey_is_captured(&next, prey, depth + 1),
+        None => false,
+    })
+}
+
+/// Whether playing the point puts an adjacent opponent group in atari that
+/// then can't escape the ladder
+pub fn is_ladder_capture(board: &Board, color: Color, point: &Point) -> bool {
+    let next = match try_play(board, color, point) {
+        Some(next) => next,
+        None => return false,
+    };
+    point
+        .neighbors()
+        .iter()
+        .filter(|p| next.is_on_grid(p) && next.get(p) == Some(color.other()))
+        .any(|prey| next.liberties(prey).len() == 1 && prey_is_captured(&next, prey, 0))
+}
+
+/// Whether playing the point saves an adjacent group of the player that is
+/// in atari, so that it can't be captured in a ladder
+pub fn is_ladder_escape(board: &Board, color: Color, point: &Point) -> bool {
+    let in_atari: Vec<Point> = point
+        .neighbors()
+        .into_iter()
+        .filter(|p| board.is_on_grid(p) && board.get(p) == Some(color) && board.liberties(p).len() == 1)
+        .collect();
+    if in_atari.is_empty() {
+        return false;
+    }
+    let next = match try_play(board, color, point) {
+        Some(next) => next,
+        None => return false,
+    };
+    in_atari.iter().any(|stone| !attacker_captures(&next, stone, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// White E5 can be laddered towards the lower left corner. Black has
+    /// just played F5, so white is in atari.
+    const LADDER: &str = r#"
+        .........
+        .........
+        .........
+        ....x....
+        ...xox...
+        .....x...
+        .........
+        .........
+        ........."#;
+
+    #[test]
+    fn test_ladder_works_without_breaker() {
+        let board = Board::from_str(LADDER).unwrap();
+        assert!(is_captured_in_ladder(&board, &Point::new(5, 5)));
+        assert!(!is_ladder_escape(&board, Color::White, &Point::new(6, 5)));
+    }
+
+    #[test]
+    fn test_ladder_breaker_lets_prey_escape() {
+        let mut board = Board::from_str(LADDER).unwrap();
+        board.place_stone(Color::White, &Point::new(8, 3)).unwrap();
+        assert!(!is_captured_in_ladder(&board, &Point::new(5, 5)));
+        assert!(is_ladder_escape(&board, Color::White, &Point::new(6, 5)));
+    }
+
+    #[test]
+    fn test_ladder_capture_move() {
+        // Without F5, playing there starts the ladder
+        let board = r#"
+        .........
+        .........
+        .........
+        ....x....
+        ...xo....
+        .....x...
+        .........
+        .........
+        ........."#;
+        let board = Board::from_str(board).unwrap();
+        assert!(is_ladder_capture(&board, Color::Black, &Point::new(5, 6)));
+        assert!(!is_ladder_capture(&board, Color::Black, &Point::new(1, 1)));
+    }
+}
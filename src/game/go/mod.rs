@@ -3,11 +3,17 @@ pub mod types;
 pub mod state;
 pub mod zobrist;
 pub mod player;
+pub mod scoring;
+pub mod sgf;
+pub mod encoders;
+pub mod ladder;
+pub mod dataset;
 
 pub use board::Board;
 pub use types::{Point, Color, Move};
 pub use state::GoState;
 pub use player::Player;
+pub use scoring::GameResult;
 
 
 /// A test evaluation function
@@ -29,10 +35,24 @@ pub fn stone_difference(game: &GoState) -> i32 {
     final_score + position_score
 }
 
+/// Static move ordering hint for search: central plays first, then plays
+/// towards the edge, then pass and finally resignation
+pub fn move_order_hint(game: &GoState, the_move: &Move) -> i32 {
+    match the_move {
+        Move::Play(p) => {
+            let board = &game.board;
+            let row_distance = (p.row - 1).min(board.rows - p.row);
+            let col_distance = (p.col - 1).min(board.cols - p.col);
+            row_distance.min(col_distance) as i32
+        }
+        Move::Pass => -1,
+        Move::Resign => -2,
+    }
+}
+
 /// A todo final score, (captured + own stones on board) of next player - (captured + own stones on board) for previous player
 fn final_score(game: &GoState) -> i32 {
     let previous_player_eval = (game.previous_player.captured + game.board.number_of_stones_of_color(game.previous_player.color)) as i32;
     let next_player_eval = (game.next_player.captured + game.board.number_of_stones_of_color(game.next_player.color)) as i32;
-    let result = next_player_eval - previous_player_eval;
-    result
+    next_player_eval - previous_player_eval
 }
@@ -0,0 +1,105 @@
+use crate::game::go::board::Board;
+use crate::game::go::types::{Color, Point};
+
+/// Area score of a position: stones on the board plus empty regions
+/// surrounded by only one color. Dead stones are not removed, so the score is
+/// accurate only for games played to the end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameResult {
+    pub black: usize,
+    pub white: usize,
+    pub komi: f32,
+}
+
+impl GameResult {
+    pub fn compute(board: &Board, komi: f32) -> Self {
+        let mut black = board.number_of_stones_of_color(Color::Black);
+        let mut white = board.number_of_stones_of_color(Color::White);
+
+        let mut visited = vec![false; board.rows * board.cols];
+        let index = |p: &Point| (p.row - 1) * board.cols + (p.col - 1);
+        for start in board.empty_points() {
+            if visited[index(&start)] {
+                continue;
+            }
+            visited[index(&start)] = true;
+            let mut region_size = 0;
+            let mut borders_black = false;
+            let mut borders_white = false;
+            let mut unexplored = vec![start];
+            while let Some(point) = unexplored.pop() {
+                region_size += 1;
+                for neighbor in point.neighbors().iter().filter(|p| board.is_on_grid(p)) {
+                    match board.get(neighbor) {
+                        Some(Color::Black) => borders_black = true,
+                        Some(Color::White) => borders_white = true,
+                        None => {
+                            if !visited[index(neighbor)] {
+                                visited[index(neighbor)] = true;
+                                unexplored.push(*neighbor);
+                            }
+                        }
+                    }
+                }
+            }
+            match (borders_black, borders_white) {
+                (true, false) => black += region_size,
+                (false, true) => white += region_size,
+                _ => {}
+            }
+        }
+
+        Self { black, white, komi }
+    }
+
+    /// Points by which black is ahead, negative if white is ahead
+    pub fn margin(&self) -> f32 {
+        self.black as f32 - (self.white as f32 + self.komi)
+    }
+
+    /// The winner, or None for a draw with an integer komi
+    pub fn winner(&self) -> Option<Color> {
+        let margin = self.margin();
+        if margin > 0.0 {
+            Some(Color::Black)
+        } else if margin < 0.0 {
+            Some(Color::White)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_territory_surrounded_by_one_color_is_counted() {
+        let board = r#"
+        .x.o.
+        xx.oo
+        ...o.
+        xx.o.
+        .x.o."#;
+        let board = Board::from_str(board).unwrap();
+        let result = GameResult::compute(&board, 0.5);
+
+        // Black: 6 stones and the two left corners, the middle column is neutral
+        assert_eq!(result.black, 6 + 2);
+        // White: 6 stones and 4 points on the right edge
+        assert_eq!(result.white, 6 + 4);
+        assert_eq!(result.winner(), Some(Color::White));
+        assert_eq!(result.margin(), -2.5);
+    }
+
+    #[test]
+    fn test_empty_board_is_neutral() {
+        let board = Board::new(5);
+        let result = GameResult::compute(&board, 0.0);
+        assert_eq!(result.black, 0);
+        assert_eq!(result.white, 0);
+        assert_eq!(result.winner(), None);
+    }
+}
@@ -0,0 +1,346 @@
+//! Smart Game Format (SGF) game records
+
+use anyhow::{bail, Context, Result};
+
+use crate::game::go::board::Board;
+use crate::game::go::player::Player;
+use crate::game::go::state::GoState;
+use crate::game::go::types::{Color, Move, Point};
+use crate::game::GameState;
+
+/// SGF coordinate letter, `a` for the first row or column
+fn sgf_coordinate(index: usize) -> char {
+    (b'a' + (index - 1) as u8) as char
+}
+
+fn sgf_point(point: &Point) -> String {
+    format!("{}{}", sgf_coordinate(point.col), sgf_coordinate(point.row))
+}
+
+/// SGF result property value of a finished game, such as `B+R` or `W+3.5`
+pub fn sgf_result(game: &GoState) -> String {
+    if game.moves.last() == Some(&Move::Resign) {
+        // The previous player resigned
+        return match game.next_player.color {
+            Color::Black => "B+R".to_string(),
+            Color::White => "W+R".to_string(),
+        };
+    }
+    let result = game.game_result();
+    match result.winner() {
+        Some(Color::Black) => format!("B+{}", result.margin()),
+        Some(Color::White) => format!("W+{}", -result.margin()),
+        None => "0".to_string(),
+    }
+}
+
+/// Record of the game from an empty board, with the result if it is over
+pub fn write_sgf(game: &GoState) -> String {
+    let mut sgf = format!("(;GM[1]FF[4]SZ[{}]KM[{}]", game.board.rows, game.komi);
+    if game.is_over() {
+        sgf += &format!("RE[{}]", sgf_result(game));
+    }
+    let mut color = Color::Black;
+    for the_move in &game.moves {
+        let tag = match color {
+            Color::Black => 'B',
+            Color::White => 'W',
+        };
+        match the_move {
+            Move::Play(point) => sgf += &format!(";{}[{}]", tag, sgf_point(point)),
+            Move::Pass => sgf += &format!(";{}[]", tag),
+            // Resignation is recorded in the result only
+            Move::Resign => {}
+        }
+        color = color.other();
+    }
+    sgf += ")\n";
+    sgf
+}
+
+/// Game read from an SGF record, following the main line of play
+#[derive(Clone, Debug, PartialEq)]
+pub struct SgfGame {
+    pub board_size: usize,
+    pub komi: f32,
+    /// Value of the RU property, if any
+    pub ruleset: Option<String>,
+    /// Stones placed before the first move, e.g. handicap stones
+    pub setup: Vec<(Color, Point)>,
+    pub first_player: Color,
+    pub moves: Vec<(Color, Move)>,
+}
+
+/// Properties of an SGF node, each with its values
+type Node = Vec<(String, Vec<String>)>;
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => bail!("Expected '{}', found '{}'", expected, c),
+            None => bail!("Expected '{}', found end of file", expected),
+        }
+    }
+
+    /// Nodes of the game tree, continuing with the first variation at every
+    /// branch
+    fn main_line(&mut self) -> Result<Vec<Node>> {
+        self.expect('(')?;
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some(';') => {
+                    self.chars.next();
+                    nodes.push(self.node()?);
+                }
+                Some('(') => {
+                    nodes.extend(self.main_line()?);
+                    // Skip the other variations
+                    self.skip_whitespace();
+                    while self.chars.peek() == Some(&'(') {
+                        self.main_line()?;
+                        self.skip_whitespace();
+                    }
+                }
+                Some(')') => {
+                    self.chars.next();
+                    return Ok(nodes);
+                }
+                Some(c) => bail!("Unexpected '{}'", c),
+                None => bail!("Unterminated game tree"),
+            }
+        }
+    }
+
+    fn node(&mut self) -> Result<Node> {
+        let mut properties = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let mut identifier = String::new();
+            while let Some(c) = self.chars.peek().copied().filter(|c| c.is_ascii_alphabetic()) {
+                identifier.push(c);
+                self.chars.next();
+            }
+            if identifier.is_empty() {
+                return Ok(properties);
+            }
+            let mut values = Vec::new();
+            self.skip_whitespace();
+            while self.chars.peek() == Some(&'[') {
+                self.chars.next();
+                values.push(self.value()?);
+                self.skip_whitespace();
+            }
+            if values.is_empty() {
+                bail!("Property {} has no value", identifier);
+            }
+            properties.push((identifier, values));
+        }
+    }
+
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some('\\') => value.extend(self.chars.next()),
+                Some(']') => return Ok(value),
+                Some(c) => value.push(c),
+                None => bail!("Unterminated property value"),
+            }
+        }
+    }
+}
+
+/// Point from SGF coordinates, or None for a pass
+fn parse_sgf_point(value: &str, board_size: usize) -> Result<Option<Point>> {
+    let coordinates: Vec<u8> = value.bytes().collect();
+    if coordinates.is_empty() || (value == "tt" && board_size <= 19) {
+        return Ok(None);
+    }
+    let index = |c: u8| -> Result<usize> {
+        let index = c.wrapping_sub(b'a') as usize + 1;
+        if !(1..=board_size).contains(&index) {
+            bail!("Invalid coordinate {}", value);
+        }
+        Ok(index)
+    };
+    if coordinates.len() != 2 {
+        bail!("Invalid point {}", value);
+    }
+    Ok(Some(Point::new(index(coordinates[1])?, index(coordinates[0])?)))
+}
+
+fn parse_color(value: &str) -> Result<Color> {
+    match value {
+        "B" | "b" => Ok(Color::Black),
+        "W" | "w" => Ok(Color::White),
+        other => bail!("Invalid color {}", other),
+    }
+}
+
+/// Read the first game of an SGF record
+pub fn parse_sgf(text: &str) -> Result<SgfGame> {
+    let start = text.find('(').context("No game tree")?;
+    let mut parser = Parser { chars: text[start..].chars().peekable() };
+    let nodes = parser.main_line()?;
+    let root = nodes.first().context("Empty game tree")?;
+    let property = |name: &str| root.iter().find(|(id, _)| id == name).map(|(_, values)| values[0].as_str());
+
+    if let Some(game) = property("GM") {
+        if game.trim() != "1" {
+            bail!("Not a game of Go: GM[{}]", game);
+        }
+    }
+    let board_size = match property("SZ") {
+        Some(size) => size.trim().parse().with_context(|| format!("Invalid board size {}", size))?,
+        None => 19,
+    };
+    if !(2..=25).contains(&board_size) {
+        bail!("Unsupported board size {}", board_size);
+    }
+    let komi = match property("KM") {
+        Some(komi) if !komi.trim().is_empty() => komi.trim().parse().with_context(|| format!("Invalid komi {}", komi))?,
+        _ => 0.0,
+    };
+
+    let mut game = SgfGame {
+        board_size,
+        komi,
+        ruleset: property("RU").map(|r| r.trim().to_string()),
+        setup: Vec::new(),
+        first_player: Color::Black,
+        moves: Vec::new(),
+    };
+    let mut first_player = property("PL").map(parse_color).transpose()?;
+    for node in &nodes {
+        for (id, values) in node {
+            match id.as_str() {
+                "AB" | "AW" => {
+                    let color = if id == "AB" { Color::Black } else { Color::White };
+                    for value in values {
+                        if let Some(point) = parse_sgf_point(value, board_size)? {
+                            game.setup.push((color, point));
+                        }
+                    }
+                }
+                "B" | "W" => {
+                    let color = parse_color(id)?;
+                    let the_move = match parse_sgf_point(&values[0], board_size)? {
+                        Some(point) => Move::Play(point),
+                        None => Move::Pass,
+                    };
+                    first_player.get_or_insert(color);
+                    game.moves.push((color, the_move));
+                }
+                _ => {}
+            }
+        }
+    }
+    game.first_player = first_player.unwrap_or(Color::Black);
+    Ok(game)
+}
+
+impl SgfGame {
+    /// Position after the setup stones, before the first move
+    pub fn initial_state(&self) -> Result<GoState> {
+        let mut board = Board::new(self.board_size);
+        for (color, point) in &self.setup {
+            board.place_stone(*color, point).with_context(|| format!("Setup stone on occupied point {}", point))?;
+        }
+        Ok(GoState::from_board(board, Player::new(self.first_player)).with_komi(self.komi))
+    }
+
+    /// Play the moves from the initial state, calling `on_move` with the
+    /// state before each move, and return the final state. Fails on moves by
+    /// the wrong player and on illegal moves.
+    pub fn replay<F>(&self, mut on_move: F) -> Result<GoState>
+    where
+        F: FnMut(&GoState, &Move),
+    {
+        let mut state = self.initial_state()?;
+        for (i, (color, the_move)) in self.moves.iter().enumerate() {
+            if *color != state.next_player.color {
+                bail!("Move {} is by {:?} out of turn", i + 1, color);
+            }
+            if !state.is_valid_move(the_move) {
+                bail!("Illegal move {} at move {}", the_move, i + 1);
+            }
+            on_move(&state, the_move);
+            state = state.apply_move(the_move);
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_sgf() {
+        let game = GoState::new(5)
+            .apply_move(&Move::Play(Point::new(1, 2)))
+            .apply_move(&Move::Play(Point::new(3, 3)))
+            .apply_move(&Move::Pass)
+            .apply_move(&Move::Resign);
+        assert_eq!(write_sgf(&game), "(;GM[1]FF[4]SZ[5]KM[7.5]RE[B+R];B[ba];W[cc];B[])\n");
+    }
+
+    #[test]
+    fn test_written_game_reads_back() {
+        let game = GoState::new(5)
+            .apply_move(&Move::Play(Point::new(1, 2)))
+            .apply_move(&Move::Play(Point::new(3, 3)))
+            .apply_move(&Move::Pass)
+            .apply_move(&Move::Play(Point::new(4, 5)));
+        let sgf = parse_sgf(&write_sgf(&game)).unwrap();
+        assert_eq!(sgf.board_size, 5);
+        assert_eq!(sgf.komi, 7.5);
+        let final_state = sgf.replay(|_, _| {}).unwrap();
+        assert_eq!(final_state.moves, game.moves);
+        assert_eq!(final_state.board, game.board);
+    }
+
+    #[test]
+    fn test_parse_main_line_with_handicap_and_comments() {
+        let sgf = r#"(;GM[1]SZ[9]HA[2]RU[Japanese]AB[cc][gg]C[Handicap \] game]
+            ;W[ee](;B[ge];W[tt];B[]) (;B[cg]))"#;
+        let game = parse_sgf(sgf).unwrap();
+        assert_eq!(game.ruleset.as_deref(), Some("Japanese"));
+        assert_eq!(game.setup, vec![(Color::Black, Point::new(3, 3)), (Color::Black, Point::new(7, 7))]);
+        assert_eq!(game.first_player, Color::White);
+        assert_eq!(
+            game.moves,
+            vec![
+                (Color::White, Move::Play(Point::new(5, 5))),
+                (Color::Black, Move::Play(Point::new(5, 7))),
+                (Color::White, Move::Pass),
+                (Color::Black, Move::Pass),
+            ]
+        );
+        assert!(game.replay(|_, _| {}).unwrap().is_over());
+    }
+
+    #[test]
+    fn test_illegal_moves_are_rejected() {
+        let occupied = parse_sgf("(;SZ[9];B[cc];W[cc])").unwrap();
+        assert!(occupied.replay(|_, _| {}).is_err());
+        let out_of_turn = parse_sgf("(;SZ[9];B[cc];B[dd])").unwrap();
+        assert!(out_of_turn.replay(|_, _| {}).is_err());
+        assert!(parse_sgf("(;SZ[9];B[zz])").is_err());
+        assert!(parse_sgf("(;GM[2];B[aa])").is_err());
+    }
+}
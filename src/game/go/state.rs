@@ -1,9 +1,18 @@
-use crate::game::GameState;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use std::fmt;
+use std::fmt::Formatter;
+
+use crate::game::{GameOutcome, GameState, Outcome, StateHash};
 use crate::game::go::board::{Board, EmptyBoardPoints};
 use crate::game::go::player::Player;
-use crate::game::go::types::{Color, Move};
+use crate::game::go::scoring::GameResult;
+use crate::game::go::types::{Color, Move, Point};
 use crate::game::go::zobrist::ZobristHash;
 
+const DEFAULT_KOMI: f32 = 7.5;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct GoState {
     pub board: Board,
@@ -13,6 +22,8 @@ pub struct GoState {
     /// Vec<(next player, Zobrist hash of current state)>
     previous_states: Vec<(Color, ZobristHash)>,
     pub moves: Vec<Move>,
+    /// Points added to white's score to compensate for black moving first
+    pub komi: f32,
 }
 
 impl GoState {
@@ -20,8 +31,13 @@ impl GoState {
         Self::from_board(Board::new(board_size), Player::black())
     }
 
-    // For testing
-    fn from_board(board: Board, next_player: Player) -> Self {
+    pub fn with_komi(mut self, komi: f32) -> Self {
+        self.komi = komi;
+        self
+    }
+
+    /// Position with the stones on the board and the player to move
+    pub(crate) fn from_board(board: Board, next_player: Player) -> Self {
         let other_color = next_player.color.other();
         Self {
             board,
@@ -29,6 +45,7 @@ impl GoState {
             previous_player: Player::new(other_color),
             previous_states: Vec::new(),
             moves: Vec::new(),
+            komi: DEFAULT_KOMI,
         }
     }
 
@@ -54,6 +71,27 @@ impl GoState {
             _ => false
         }
     }
+
+    /// Area score of the position
+    pub fn game_result(&self) -> GameResult {
+        GameResult::compute(&self.board, self.komi)
+    }
+
+    fn black(&self) -> &Player {
+        if self.next_player.color == Color::Black {
+            &self.next_player
+        } else {
+            &self.previous_player
+        }
+    }
+
+    fn white(&self) -> &Player {
+        if self.next_player.color == Color::White {
+            &self.next_player
+        } else {
+            &self.previous_player
+        }
+    }
 }
 
 impl GameState for GoState {
@@ -64,7 +102,7 @@ impl GameState for GoState {
         let mut captured_stones = 0;
         match m {
             Move::Play(point) => {
-                captured_stones = next_board.place_stone(self.next_player.color, &point).expect("Illegal play");
+                captured_stones = next_board.place_stone(self.next_player.color, point).expect("Illegal play");
 
             }
             Move::Pass => {}
@@ -86,21 +124,21 @@ impl GameState for GoState {
             next_player,
             previous_player,
             previous_states,
-            moves
+            moves,
+            komi: self.komi,
         }
     }
 
     fn valid_moves(&self) -> Vec<Self::Move> {
-        ValidMoves::new(self)
-            .into_iter()
-            .collect()
+        ValidMoves::new(self).collect()
     }
 
     fn is_valid_move(&self, the_move: &Move) -> bool {
         match the_move {
             Move::Play(point) => {
                 !self.is_over() &&
-                    self.board.get(&point).is_none() &&
+                    self.board.is_on_grid(point) &&
+                    self.board.get(point).is_none() &&
                     !self.is_move_self_capture(self.next_player.color, the_move) &&
                     !self.does_move_violate_ko(self.next_player.color, the_move)
             }
@@ -116,10 +154,7 @@ impl GameState for GoState {
                 // Over if two consecutive passes
                 Move::Pass => match self.moves.len() {
                     1 => false,
-                    _ => match self.moves.get(self.moves.len() - 2) {
-                        Some(Move::Pass) => true,
-                        _ => false,
-                    }
+                    _ => matches!(self.moves.get(self.moves.len() - 2), Some(Move::Pass)),
                 },
                 Move::Resign => true
             }
@@ -128,6 +163,63 @@ impl GameState for GoState {
 
 }
 
+/// The board with coordinates, and the player to move
+impl fmt::Display for GoState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "  ")?;
+        for col in 1..=self.board.cols {
+            write!(f, " {}", Point::column_letter(col))?;
+        }
+        writeln!(f)?;
+        for row in 1..=self.board.rows {
+            write!(f, "{:2}", row)?;
+            for col in 1..=self.board.cols {
+                let c = match self.board.get(&Point::new(row, col)) {
+                    None => '.',
+                    Some(Color::Black) => 'x',
+                    Some(Color::White) => 'o',
+                };
+                write!(f, " {}", c)?;
+            }
+            writeln!(f)?;
+        }
+        let player = match self.next_player.color {
+            Color::Black => "Black (x)",
+            Color::White => "White (o)",
+        };
+        writeln!(f, "{} to move, captures: black {}, white {}", player, self.black().captured, self.white().captured)
+    }
+}
+
+impl GameOutcome for GoState {
+    fn outcome(&self) -> Outcome {
+        if self.moves.last() == Some(&Move::Resign) {
+            // The previous player resigned
+            return Outcome::Win;
+        }
+        match self.game_result().winner() {
+            Some(color) if color == self.next_player.color => Outcome::Win,
+            Some(_) => Outcome::Loss,
+            None => Outcome::Draw,
+        }
+    }
+}
+
+impl StateHash for GoState {
+    /// Zobrist hash of the board combined with the rest of the state that
+    /// evaluation functions and the end of the game depend on: the player to
+    /// move, the last move, whether the game is over and the captures. The ko
+    /// history is not included.
+    fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.next_player.color.hash(&mut hasher);
+        self.next_player.captured.hash(&mut hasher);
+        self.previous_player.captured.hash(&mut hasher);
+        self.moves.last().hash(&mut hasher);
+        self.is_over().hash(&mut hasher);
+        self.board.hash() ^ hasher.finish()
+    }
+}
 
 pub struct ValidMoves<'a> {
     game: &'a GoState,
@@ -152,7 +244,7 @@ impl<'a> Iterator for ValidMoves<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         // Return first valid play
-        while let Some(p) = self.points.next() {
+        for p in self.points.by_ref() {
             let the_move = Move::Play(p);
             if self.game.is_valid_move(&the_move) {
                 return Some(the_move);
@@ -1,4 +1,8 @@
-/// Common types needed everywhere
+//! Common types needed everywhere
+
+use anyhow::{bail, Result};
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Color {
@@ -22,11 +26,19 @@ pub struct Point {
     pub col: usize,
 }
 
+/// Column letters of board coordinates, I is skipped as usual in Go
+const COLUMN_LETTERS: &str = "ABCDEFGHJKLMNOPQRSTUVWXYZ";
+
 impl Point {
     pub fn new(row: usize, col: usize) -> Self {
         Self { row, col }
     }
 
+    /// Letter of the column in coordinates such as D4
+    pub fn column_letter(col: usize) -> char {
+        COLUMN_LETTERS.chars().nth(col - 1).expect("Column out of range")
+    }
+
     pub(crate) fn neighbors(&self) -> Vec<Point> {
         vec![
             Self::new(self.row - 1, self.col),
@@ -54,3 +66,83 @@ pub enum Move {
     Pass,
     Resign,
 }
+
+/// Coordinates such as D4, the column as a letter and the row as a number
+impl FromStr for Point {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let mut chars = s.chars();
+        let letter = match chars.next() {
+            Some(c) => c.to_ascii_uppercase(),
+            None => bail!("Empty coordinates"),
+        };
+        let col = match COLUMN_LETTERS.find(letter) {
+            Some(index) => index + 1,
+            None => bail!("Invalid column: {}", letter),
+        };
+        let row: usize = match chars.as_str().parse() {
+            Ok(row) if row > 0 => row,
+            _ => bail!("Invalid row: {}", chars.as_str()),
+        };
+        Ok(Self::new(row, col))
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", Self::column_letter(self.col), self.row)
+    }
+}
+
+/// Coordinates of a play, `pass` or `resign`
+impl FromStr for Move {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "pass" => Ok(Move::Pass),
+            "resign" => Ok(Move::Resign),
+            coordinates => Ok(Move::Play(coordinates.parse()?)),
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Move::Play(point) => write!(f, "{}", point),
+            Move::Pass => write!(f, "pass"),
+            Move::Resign => write!(f, "resign"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coordinates_round_trip() {
+        for coordinates in ["A1", "D4", "H8", "J9", "T19"] {
+            let point: Point = coordinates.parse().unwrap();
+            assert_eq!(point.to_string(), coordinates);
+        }
+        assert_eq!("j9".parse::<Point>().unwrap(), Point::new(9, 9));
+    }
+
+    #[test]
+    fn test_invalid_coordinates_are_rejected() {
+        for coordinates in ["", "I3", "D", "D0", "4D", "D-1"] {
+            assert!(coordinates.parse::<Point>().is_err(), "{}", coordinates);
+        }
+    }
+
+    #[test]
+    fn test_parse_moves() {
+        assert_eq!("pass".parse::<Move>().unwrap(), Move::Pass);
+        assert_eq!(" Resign\n".parse::<Move>().unwrap(), Move::Resign);
+        assert_eq!("c2".parse::<Move>().unwrap(), Move::Play(Point::new(2, 3)));
+    }
+}
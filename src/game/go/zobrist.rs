@@ -37,7 +37,8 @@ impl ZobristHasher {
             Color::Black => 0,
             Color::White => 1,
         };
-        let index = (point.row - 1) * self.board_size + (point.col - 1) + offset;
+        // Two entries per point, one for each color
+        let index = 2 * ((point.row - 1) * self.board_size + (point.col - 1)) + offset;
 
         hash ^ self.lut[index]
     }
@@ -53,4 +54,18 @@ impl PartialEq for ZobristHasher {
     fn eq(&self, other: &Self) -> bool {
         self.board_size == other.board_size && self.seed == other.seed
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors_of_adjacent_points_have_distinct_keys() {
+        let hasher = ZobristHasher::new(3);
+        let empty = ZobristHasher::empty_board();
+        let white = hasher.hash_move(empty, Color::White, &Point::new(1, 1));
+        let black = hasher.hash_move(empty, Color::Black, &Point::new(1, 2));
+        assert_ne!(white, black);
+    }
+}
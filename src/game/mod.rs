@@ -12,4 +12,36 @@ pub trait GameState {
         self.valid_moves().contains(m)
     }
     fn is_over(&self) -> bool;
-}
\ No newline at end of file
+}
+
+/// Game states that can be identified by a hash of the position, e.g. for
+/// transposition tables in search. Equal positions must hash equally.
+pub trait StateHash {
+    fn state_hash(&self) -> u64;
+}
+
+/// Result of a game from the point of view of one player
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl Outcome {
+    /// The same result from the point of view of the opponent
+    pub fn reverse(&self) -> Outcome {
+        match self {
+            Outcome::Win => Outcome::Loss,
+            Outcome::Loss => Outcome::Win,
+            Outcome::Draw => Outcome::Draw,
+        }
+    }
+}
+
+/// Games that can be scored, needed by searches that play games to the end
+pub trait GameOutcome: GameState {
+    /// Outcome for the player to move. If the game is not over, the outcome
+    /// it would have if it ended now.
+    fn outcome(&self) -> Outcome;
+}
@@ -8,13 +8,23 @@
 //! and 2) the first player always wins if they play correctly.
 
 use crate::GameState;
+use crate::game::{GameOutcome, Outcome, StateHash};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct OneTwoThreeState {
     players: [Player; 2],
     current_player_index: usize,
 }
 
+impl Default for OneTwoThreeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OneTwoThreeState {
     pub fn new() -> Self {
         Self { players: [Player::new(), Player::new()], current_player_index: 0 }
@@ -73,7 +83,24 @@ impl GameState for OneTwoThreeState {
     }
 }
 
-#[derive(Clone, Debug)]
+impl StateHash for OneTwoThreeState {
+    fn state_hash(&self) -> u64 {
+        let points = (self.players[0].points as u64) << 32 | self.players[1].points as u64;
+        points << 1 | self.current_player_index as u64
+    }
+}
+
+impl GameOutcome for OneTwoThreeState {
+    fn outcome(&self) -> Outcome {
+        match score_difference(self).cmp(&0) {
+            Ordering::Greater => Outcome::Win,
+            Ordering::Less => Outcome::Loss,
+            Ordering::Equal => Outcome::Draw,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Player {
     points: u32
 }
@@ -84,13 +111,50 @@ impl Player {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Move {
     One,
     Two,
     Three
 }
 
+/// A number from 1 to 3, as a digit or a word
+impl FromStr for Move {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "1" | "one" => Ok(Move::One),
+            "2" | "two" => Ok(Move::Two),
+            "3" | "three" => Ok(Move::Three),
+            other => anyhow::bail!("Not one, two or three: {}", other),
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = match self {
+            Move::One => 1,
+            Move::Two => 2,
+            Move::Three => 3,
+        };
+        write!(f, "{}", number)
+    }
+}
+
+impl fmt::Display for OneTwoThreeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Player 1: {} points, player 2: {} points, player {} to move",
+            self.players[0].points,
+            self.players[1].points,
+            self.current_player_index + 1
+        )
+    }
+}
+
 /// Evaluation function calculating the difference between the scores of the
 /// current player and the other player
 pub fn score_difference(game: &OneTwoThreeState) -> i32 {
@@ -1,5 +1,10 @@
 pub mod agent;
 pub mod game;
+pub mod nn;
+pub mod npy;
+pub mod play;
+pub mod rl;
+pub mod tournament;
 
 pub use game::GameState;
 
@@ -1,48 +1,980 @@
-use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
 use std::io::{stdin, stdout, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
 use std::time::Duration;
-use bgai;
-use bgai::game::go::{self, Move, Color, GoState};
-use bgai::agent::{RandomBot, Agent, MinimaxBot};
+
+use anyhow::{bail, Context, Result};
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use bgai::agent::puct::visit_distribution;
+use bgai::agent::{Agent, AgentSpec, FastRandomBot, MctsBot, PuctBot, RandomBot, RaveBot, SearchInfo, UniformEvaluator};
+use bgai::game::go::dataset::{DatasetConfig, DatasetGenerator, NpySampleSink};
+use bgai::game::go::encoders::get_encoder_by_name;
+use bgai::game::go::{self, GoState};
+use bgai::game::one_two_three::{self, OneTwoThreeState};
+use bgai::nn::train::{Optimizer, Schedule};
+use bgai::nn::{Examples, Network, TrainConfig, Trainer};
+use bgai::game::GameOutcome;
+use bgai::play::{play_game, GameRecord};
+use bgai::rl::reinforce::{Baseline, Evaluation};
+use bgai::rl::{
+    ActorCriticConfig, ActorCriticTrainer, ExperienceBuffer, ExperienceCollector, QConfig, QTrainer, RecordingAgent,
+    ReinforceConfig, ReinforceTrainer,
+};
+use bgai::tournament::{self, round_robin, Entrant, Sprt, SprtDecision, TournamentConfig, TournamentResults};
 use bgai::GameState;
 
-fn main() {
-    go_game()
+#[derive(Parser)]
+#[command(name = "bgai", about = "Board game AI experiments")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play a game, showing the board after every move
+    Play(PlayArgs),
+    /// Play a series of games between two agents, alternating colors
+    Match(MatchArgs),
+    /// Play games between every pair of agents and rate them
+    Tournament(TournamentArgs),
+    /// Test whether a candidate agent is stronger than a baseline with a
+    /// sequential probability ratio test
+    Sprt(SprtArgs),
+    /// Show what an agent thinks of a position
+    Analyze(AnalyzeArgs),
+    /// Generate Go training data from SGF files
+    Dataset(DatasetArgs),
+    /// Train a policy network on the data of the dataset command
+    Train(TrainArgs),
+    /// Record the experience of Go agents in games against each other
+    SelfPlay(SelfPlayArgs),
+    /// Train a network on the experience of the self-play command with
+    /// policy gradients, Q-learning or actor-critic
+    Reinforce(ReinforceArgs),
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GameKind {
+    Go,
+    OneTwoThree,
+}
+
+#[derive(Args)]
+struct GameArgs {
+    #[arg(long, value_enum, default_value_t = GameKind::Go)]
+    game: GameKind,
+    /// Size of the Go board, from 2 to 25
+    #[arg(long, default_value_t = 5, value_parser = RangedU64ValueParser::<usize>::new().range(2..=25))]
+    size: usize,
+    /// Points added to white's score in Go
+    #[arg(long, default_value_t = 7.5)]
+    komi: f32,
+    /// Seed for the agents, random if not given. Each agent gets its own
+    /// seed derived from this one unless its spec has a seed.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// Agents are given as `name` or `name:key=value,...`, e.g.
+/// `mcts:rounds=2000,threads=4`. Agents: human, random, fast-random,
+/// minimax, pvs, mtdf (depth, window), mcts (rounds, threads, parallelism,
+/// exploration, reuse, ponder), rave (rounds, equivalence, exploration,
+/// reuse), puct (rounds, c, noise, temperature, reuse), policy (model, encoder,
+/// temperature, resign), q (model, encoder, epsilon) and actor-critic
+/// (model, encoder, temperature, resign).
+#[derive(Args)]
+struct PlayArgs {
+    #[command(flatten)]
+    game: GameArgs,
+    /// Agent moving first
+    #[arg(long, default_value = "minimax:depth=3")]
+    black: AgentSpec,
+    /// Agent moving second
+    #[arg(long, default_value = "random")]
+    white: AgentSpec,
+    /// Play without waiting for Enter between moves
+    #[arg(long)]
+    auto: bool,
+    /// Pause between moves with --auto, in milliseconds
+    #[arg(long, default_value_t = 100)]
+    delay_ms: u64,
+    /// Show search information of the searching agents
+    #[arg(long)]
+    verbose: bool,
+    /// Stop the game after this many moves
+    #[arg(long)]
+    max_moves: Option<usize>,
+    /// Write the game record to the file, SGF for Go
+    #[arg(long)]
+    output: Option<PathBuf>,
 }
 
-fn go_game() {
-    let mut game = GoState::new(5);
-    let mut bots: HashMap<Color, Box<dyn Agent<GoState>>> = HashMap::new();
-    bots.insert(Color::White, Box::new(RandomBot::new()));
-    bots.insert(Color::Black, Box::new(MinimaxBot::new(5, go::stone_difference)));
+#[derive(Args)]
+struct MatchArgs {
+    #[command(flatten)]
+    game: GameArgs,
+    /// Agent moving first in the first game
+    #[arg(long, default_value = "fast-random")]
+    agent: AgentSpec,
+    /// Agent moving second in the first game
+    #[arg(long, default_value = "random")]
+    opponent: AgentSpec,
+    #[command(flatten)]
+    options: TournamentOptions,
+}
+
+#[derive(Args)]
+struct TournamentArgs {
+    #[command(flatten)]
+    game: GameArgs,
+    /// Agents taking part, see `play --help`
+    #[arg(long, num_args = 2.., required = true)]
+    agents: Vec<AgentSpec>,
+    #[command(flatten)]
+    options: TournamentOptions,
+}
+
+#[derive(Args)]
+struct SprtArgs {
+    #[command(flatten)]
+    game: GameArgs,
+    #[arg(long)]
+    candidate: AgentSpec,
+    #[arg(long)]
+    baseline: AgentSpec,
+    /// Elo gain of the candidate under the null hypothesis
+    #[arg(long, default_value_t = 0.0)]
+    elo0: f64,
+    /// Elo gain of the candidate under the alternative hypothesis
+    #[arg(long, default_value_t = 10.0)]
+    elo1: f64,
+    /// Probability of accepting the alternative when the null hypothesis holds
+    #[arg(long, default_value_t = 0.05)]
+    alpha: f64,
+    /// Probability of accepting the null hypothesis when the alternative holds
+    #[arg(long, default_value_t = 0.05)]
+    beta: f64,
+    /// Stop without a decision after this many games
+    #[arg(long, default_value_t = 10000)]
+    max_games: u32,
+    /// Number of games played at the same time
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// Stop each game after this many moves and score it as a draw
+    #[arg(long)]
+    max_moves: Option<usize>,
+}
+
+#[derive(Args)]
+struct TournamentOptions {
+    /// Number of games between each pair of agents
+    #[arg(long, default_value_t = 10)]
+    games: u32,
+    /// Number of games played at the same time
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+    /// Stop each game after this many moves and score it as a draw
+    #[arg(long)]
+    max_moves: Option<usize>,
+    /// Write the standings to the file as CSV
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Write the result of every game to the file as CSV
+    #[arg(long)]
+    games_output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct AnalyzeArgs {
+    #[command(flatten)]
+    game: GameArgs,
+    #[arg(long, default_value = "pvs:depth=4")]
+    agent: AgentSpec,
+    /// Moves played from the start of the game, separated by spaces, e.g.
+    /// "D4 C3 pass"
+    #[arg(long, default_value = "")]
+    moves: String,
+}
+
+#[derive(Args)]
+struct DatasetArgs {
+    /// SGF files, directories of them or tar archives, optionally gzipped
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Directory for the NumPy files of features and labels
+    #[arg(long)]
+    output: PathBuf,
+    /// Feature encoder: oneplane, sevenplane, simple or alphago
+    #[arg(long, default_value = "sevenplane")]
+    encoder: String,
+    #[arg(long, default_value_t = 19)]
+    size: usize,
+    /// Share of games used for validation
+    #[arg(long, default_value_t = 0.1)]
+    validation: f64,
+    /// Add the rotations and reflections of every position
+    #[arg(long)]
+    augment: bool,
+    /// Rulesets whose games are used, instead of the default ones
+    #[arg(long, num_args = 1..)]
+    rulesets: Option<Vec<String>>,
+    /// Seed for choosing the validation games
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(Args)]
+struct TrainArgs {
+    /// Directory of the dataset command's output
+    #[arg(long)]
+    data: PathBuf,
+    /// Network spec to continue training from, instead of a new network
+    #[arg(long)]
+    model: Option<PathBuf>,
+    /// Filters of the 3x3 convolutions of a new network
+    #[arg(long, value_delimiter = ',', default_value = "32,32")]
+    filters: Vec<usize>,
+    /// Path of the trained network's spec, saved next to its weights
+    #[arg(long)]
+    output: PathBuf,
+    #[arg(long, default_value_t = 10)]
+    epochs: usize,
+    #[arg(long, default_value_t = 32)]
+    batch_size: usize,
+    #[arg(long, default_value_t = 0.001)]
+    learning_rate: f32,
+    #[arg(long, value_enum, default_value_t = OptimizerKind::Adam)]
+    optimizer: OptimizerKind,
+    /// Momentum of SGD
+    #[arg(long, default_value_t = 0.9)]
+    momentum: f32,
+    #[arg(long, value_enum, default_value_t = ScheduleKind::Constant)]
+    schedule: ScheduleKind,
+    /// Epochs between halvings of the learning rate with the step schedule
+    #[arg(long, default_value_t = 5)]
+    step_epochs: usize,
+    /// Directory for a checkpoint after every epoch
+    #[arg(long)]
+    checkpoint_dir: Option<PathBuf>,
+    /// Seed for the initial weights and the order of the examples
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(Args)]
+struct SelfPlayArgs {
+    /// Agent whose experience is recorded, see `play --help`
+    #[arg(long, default_value = "fast-random")]
+    agent: AgentSpec,
+    /// Agent playing against it, the agent itself if not given
+    #[arg(long)]
+    opponent: Option<AgentSpec>,
+    /// Record the experience of the opponent too
+    #[arg(long)]
+    record_opponent: bool,
+    #[arg(long, default_value_t = 10)]
+    games: usize,
+    #[arg(long, default_value_t = 9)]
+    size: usize,
+    #[arg(long, default_value_t = 7.5)]
+    komi: f32,
+    /// Feature encoder of the recorded positions
+    #[arg(long, default_value = "sevenplane")]
+    encoder: String,
+    /// Stop each game after this many moves and score it as a draw
+    #[arg(long)]
+    max_moves: Option<usize>,
+    /// File for the experience, see `bgai::rl::experience` for the format
+    #[arg(long)]
+    output: PathBuf,
+    /// Add the experience to the one already in the output file
+    #[arg(long)]
+    append: bool,
+    /// Seed for the agents, random if not given
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Args)]
+struct ReinforceArgs {
+    /// Experience files of the self-play command, combined
+    #[arg(required = true)]
+    experience: Vec<PathBuf>,
+    #[arg(long, value_enum, default_value_t = RlMethod::Reinforce)]
+    method: RlMethod,
+    /// Network spec to continue training from, instead of a new network with
+    /// the heads of the method
+    #[arg(long)]
+    model: Option<PathBuf>,
+    /// Filters of the 3x3 convolutions of a new network
+    #[arg(long, value_delimiter = ',', default_value = "32,32")]
+    filters: Vec<usize>,
+    /// Path of the trained network's spec, saved next to its weights
+    #[arg(long)]
+    output: PathBuf,
+    #[arg(long, default_value_t = 1)]
+    epochs: usize,
+    #[arg(long, default_value_t = 32)]
+    batch_size: usize,
+    #[arg(long, default_value_t = 0.01)]
+    learning_rate: f32,
+    #[arg(long, value_enum, default_value_t = OptimizerKind::Sgd)]
+    optimizer: OptimizerKind,
+    /// Momentum of SGD
+    #[arg(long, default_value_t = 0.0)]
+    momentum: f32,
+    /// What is subtracted from the rewards with policy gradients
+    #[arg(long, value_enum, default_value_t = BaselineKind::Mean)]
+    baseline: BaselineKind,
+    /// Weight of the entropy bonus
+    #[arg(long, default_value_t = 0.01)]
+    entropy: f32,
+    /// Weight of the value's squared error with actor-critic
+    #[arg(long, default_value_t = 0.5)]
+    value_weight: f32,
+    /// Epochs between matches against the previously evaluated policy with
+    /// policy gradients, none if not given
+    #[arg(long)]
+    evaluate_every: Option<usize>,
+    #[arg(long, default_value_t = 20)]
+    evaluation_games: u32,
+    /// Feature encoder of the network's input, for the evaluation games
+    #[arg(long, default_value = "sevenplane")]
+    encoder: String,
+    #[arg(long, default_value_t = 7.5)]
+    komi: f32,
+    /// Seed for the initial weights, the order of the experience and the
+    /// evaluation games
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RlMethod {
+    /// REINFORCE on a policy head
+    Reinforce,
+    /// Q-learning on a Q head
+    Q,
+    /// Actor-critic on policy and value heads
+    ActorCritic,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BaselineKind {
+    None,
+    Mean,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OptimizerKind {
+    Sgd,
+    Adam,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ScheduleKind {
+    Constant,
+    Step,
+    Cosine,
+}
+
+/// What the command line needs of a game besides the game rules
+trait CliGame: GameOutcome + Clone + Display + 'static {
+    fn new_game(args: &GameArgs) -> Self;
+    fn player_name(index: usize) -> &'static str;
+    fn build_agent(spec: &AgentSpec, seed: u64, verbose: bool) -> Result<Box<dyn Agent<Self>>>;
+    /// Fail on an invalid agent spec without building the agent
+    fn check_agent(spec: &AgentSpec) -> Result<()>;
+    fn parse_move(s: &str) -> Result<Self::Move>;
+    fn move_text(the_move: &Self::Move) -> String;
+    fn result_text(record: &GameRecord<Self>) -> String;
+    /// Contents of the game record file
+    fn record_text(record: &GameRecord<Self>) -> String;
+    /// Search the state with the agent and print the statistics it has
+    fn analyze(spec: &AgentSpec, seed: u64, state: &Self) -> Result<()>;
+}
 
-    while !game.is_over() {
-        std::thread::sleep(Duration::from_millis(100));
-        //print!("{}[2J", 27 as char);
-        println!("{:?}", &game.board);
-        let mut s=String::new();
-        print!("Proceed? (press enter) ");
-        let _=stdout().flush();
-        stdin().read_line(&mut s).expect("Something went wrong");
-        let bot_move = bots.get_mut(&game.next_player.color).unwrap().select_move(&game);
-        print_move(game.next_player.color, &bot_move);
-        game = game.apply_move(&bot_move);
+impl CliGame for GoState {
+    fn new_game(args: &GameArgs) -> Self {
+        GoState::new(args.size).with_komi(args.komi)
+    }
+
+    fn player_name(index: usize) -> &'static str {
+        ["Black", "White"][index]
+    }
+
+    fn build_agent(spec: &AgentSpec, seed: u64, verbose: bool) -> Result<Box<dyn Agent<Self>>> {
+        spec.build_go(seed, observer(verbose))
+    }
+
+    fn check_agent(spec: &AgentSpec) -> Result<()> {
+        spec.check_go()
+    }
+
+    fn parse_move(s: &str) -> Result<go::Move> {
+        go::Move::from_str(s)
+    }
+
+    fn move_text(the_move: &go::Move) -> String {
+        the_move.to_string()
+    }
+
+    fn result_text(record: &GameRecord<Self>) -> String {
+        if record.final_state.is_over() {
+            go::sgf::sgf_result(&record.final_state)
+        } else {
+            "unfinished".to_string()
+        }
+    }
+
+    fn record_text(record: &GameRecord<Self>) -> String {
+        go::sgf::write_sgf(&record.final_state)
+    }
+
+    fn analyze(spec: &AgentSpec, seed: u64, state: &Self) -> Result<()> {
+        match spec.name.as_str() {
+            "mcts" => {
+                let mut bot = MctsBot::with_seed(spec.mcts_config()?, FastRandomBot::with_seed, spec.seed(seed)?);
+                let stats = bot.search(state);
+                print_move_stats("win rate", stats.iter().map(|s| (s.the_move.to_string(), s.visits, s.win_rate())));
+            }
+            "rave" => {
+                let mut bot = RaveBot::with_seed(spec.rave_config()?, spec.seed(seed)?);
+                let stats = bot.search(state);
+                print_move_stats("value", stats.iter().map(|(m, visits, value)| (m.to_string(), *visits, *value)));
+            }
+            "puct" => {
+                let mut bot = PuctBot::with_seed(spec.puct_config()?, UniformEvaluator, spec.seed(seed)?);
+                print_visits(&bot.search(state));
+            }
+            _ => analyze_with_agent(spec, seed, state)?,
+        }
+        Ok(())
+    }
+}
+
+impl CliGame for OneTwoThreeState {
+    fn new_game(_args: &GameArgs) -> Self {
+        OneTwoThreeState::new()
+    }
+
+    fn player_name(index: usize) -> &'static str {
+        ["Player 1", "Player 2"][index]
+    }
+
+    fn build_agent(spec: &AgentSpec, seed: u64, verbose: bool) -> Result<Box<dyn Agent<Self>>> {
+        spec.build_one_two_three(seed, observer(verbose))
+    }
+
+    fn check_agent(spec: &AgentSpec) -> Result<()> {
+        spec.check_one_two_three()
+    }
+
+    fn parse_move(s: &str) -> Result<one_two_three::Move> {
+        one_two_three::Move::from_str(s)
+    }
+
+    fn move_text(the_move: &one_two_three::Move) -> String {
+        the_move.to_string()
+    }
+
+    fn result_text(record: &GameRecord<Self>) -> String {
+        match record.winner() {
+            Some(winner) => format!("{} wins", Self::player_name(winner)),
+            None => "no winner".to_string(),
+        }
+    }
+
+    fn record_text(record: &GameRecord<Self>) -> String {
+        let moves: Vec<String> = record.moves.iter().map(Self::move_text).collect();
+        format!("{}\n{}\n", moves.join(" "), Self::result_text(record))
+    }
+
+    fn analyze(spec: &AgentSpec, seed: u64, state: &Self) -> Result<()> {
+        match spec.name.as_str() {
+            "mcts" => {
+                let mut bot = MctsBot::with_seed(spec.mcts_config()?, RandomBot::with_seed, spec.seed(seed)?);
+                let stats = bot.search(state);
+                print_move_stats("win rate", stats.iter().map(|s| (s.the_move.to_string(), s.visits, s.win_rate())));
+            }
+            "puct" => {
+                let mut bot = PuctBot::with_seed(spec.puct_config()?, UniformEvaluator, spec.seed(seed)?);
+                print_visits(&bot.search(state));
+            }
+            _ => analyze_with_agent(spec, seed, state)?,
+        }
+        Ok(())
     }
-    // print!("{}[2J", 27 as char);
-    println!("{:?}", &game.board);
-    println!("Game over!");
 }
 
-fn print_move(player: Color, the_move: &Move) {
-    let player = match player {
-        Color::Black => "Black",
-        Color::White => "White"
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match (&cli.command, command_game(&cli.command)) {
+        (Command::Play(args), GameKind::Go) => play::<GoState>(args),
+        (Command::Play(args), GameKind::OneTwoThree) => play::<OneTwoThreeState>(args),
+        (Command::Match(args), GameKind::Go) => run_match::<GoState>(args),
+        (Command::Match(args), GameKind::OneTwoThree) => run_match::<OneTwoThreeState>(args),
+        (Command::Tournament(args), GameKind::Go) => run_tournament::<GoState>(&args.game, &args.agents, &args.options).map(|_| ()),
+        (Command::Tournament(args), GameKind::OneTwoThree) => {
+            run_tournament::<OneTwoThreeState>(&args.game, &args.agents, &args.options).map(|_| ())
+        }
+        (Command::Sprt(args), GameKind::Go) => run_sprt::<GoState>(args),
+        (Command::Sprt(args), GameKind::OneTwoThree) => run_sprt::<OneTwoThreeState>(args),
+        (Command::Analyze(args), GameKind::Go) => analyze::<GoState>(args),
+        (Command::Analyze(args), GameKind::OneTwoThree) => analyze::<OneTwoThreeState>(args),
+        (Command::Dataset(args), _) => generate_dataset(args),
+        (Command::Train(args), _) => train_network(args),
+        (Command::SelfPlay(args), _) => self_play(args),
+        (Command::Reinforce(args), _) => reinforce(args),
+    }
+}
+
+fn command_game(command: &Command) -> GameKind {
+    match command {
+        Command::Play(args) => args.game.game,
+        Command::Match(args) => args.game.game,
+        Command::Tournament(args) => args.game.game,
+        Command::Sprt(args) => args.game.game,
+        Command::Analyze(args) => args.game.game,
+        Command::Dataset(_) | Command::Train(_) | Command::SelfPlay(_) | Command::Reinforce(_) => GameKind::Go,
+    }
+}
+
+/// The given seed, or a random one which is printed so that the run can be
+/// repeated
+fn seed(args: &GameArgs) -> u64 {
+    args.seed.unwrap_or_else(|| {
+        let seed = rand::random();
+        println!("Seed {}", seed);
+        seed
+    })
+}
+
+/// Search observer of the command line agents
+type Observer<M> = Box<dyn FnMut(&SearchInfo<M>)>;
+
+fn observer<M: Display + 'static>(verbose: bool) -> Observer<M> {
+    if verbose {
+        Box::new(print_search_info)
+    } else {
+        Box::new(|_: &SearchInfo<M>| {})
+    }
+}
+
+fn play<S: CliGame>(args: &PlayArgs) -> Result<()> {
+    let seed = seed(&args.game);
+    let mut black = S::build_agent(&args.black, seed, args.verbose)?;
+    let mut white = S::build_agent(&args.white, seed.wrapping_add(1), args.verbose)?;
+    let humans = [args.black.name == "human", args.white.name == "human"];
+
+    // Human agents show the state themselves when asked for a move
+    let state = S::new_game(&args.game);
+    if !humans[0] {
+        println!("{}", state);
+    }
+    let mut move_number = 0;
+    let record = play_game(state, &mut [black.as_mut(), white.as_mut()], args.max_moves, |state, the_move| {
+        println!("{} plays {}", S::player_name(move_number % 2), S::move_text(the_move));
+        move_number += 1;
+        let state = state.apply_move(the_move);
+        if state.is_over() || !humans[move_number % 2] {
+            println!("{}", state);
+        }
+        if state.is_over() || humans[move_number % 2] {
+            return;
+        }
+        if args.auto {
+            thread::sleep(Duration::from_millis(args.delay_ms));
+        } else {
+            wait_for_enter();
+        }
+    });
+    println!("Game over: {}", S::result_text(&record));
+
+    if let Some(path) = &args.output {
+        fs::write(path, S::record_text(&record)).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn run_match<S: CliGame>(args: &MatchArgs) -> Result<()> {
+    let results = run_tournament::<S>(&args.game, &[args.agent.clone(), args.opponent.clone()], &args.options)?;
+    let pair = results.pair(0, 1);
+    println!(
+        "{} scores {:.1}% ({:.1}%-{:.1}%) against {}, Elo difference {:.0} ({:.0} to {:.0})",
+        args.agent,
+        100.0 * pair.score_rate,
+        100.0 * pair.score_interval.0,
+        100.0 * pair.score_interval.1,
+        args.opponent,
+        pair.elo_difference,
+        pair.elo_interval.0,
+        pair.elo_interval.1
+    );
+    Ok(())
+}
+
+/// Tournament entrant building new agents from the spec, for games played
+/// on the number of threads
+fn entrant<S: CliGame>(spec: &AgentSpec, threads: usize) -> Result<Entrant<S>> {
+    // Games played at the same time would all ask the human for moves
+    anyhow::ensure!(spec.name != "human" || threads <= 1, "A human can only play on a single thread");
+    // Fail on invalid specs before playing
+    S::check_agent(spec)?;
+    let factory_spec = spec.clone();
+    Ok(Entrant::new(&spec.to_string(), move |seed| {
+        S::build_agent(&factory_spec, seed, false).expect("Agent spec was checked")
+    }))
+}
+
+fn run_sprt<S: CliGame>(args: &SprtArgs) -> Result<()> {
+    let config = TournamentConfig {
+        threads: args.threads,
+        max_moves: args.max_moves,
+        seed: seed(&args.game),
+        ..TournamentConfig::default()
+    };
+    let sprt = Sprt::new(args.elo0, args.elo1, args.alpha, args.beta);
+    let (lower, upper) = sprt.bounds();
+    println!("H0: elo {} H1: elo {}, LLR bounds [{:.2}, {:.2}]", args.elo0, args.elo1, lower, upper);
+
+    let sprt = tournament::run_sprt(
+        entrant::<S>(&args.candidate, args.threads)?,
+        entrant::<S>(&args.baseline, args.threads)?,
+        || S::new_game(&args.game),
+        sprt,
+        args.max_games,
+        &config,
+        |_, sprt| {
+            println!(
+                "Games {}: +{} ={} -{} LLR {:.2}",
+                sprt.games(),
+                sprt.wins,
+                sprt.draws,
+                sprt.losses,
+                sprt.llr()
+            )
+        },
+    );
+    match sprt.decision() {
+        SprtDecision::AcceptH1 => println!("H1 accepted: {} is stronger than {}", args.candidate, args.baseline),
+        SprtDecision::AcceptH0 => println!("H0 accepted: {} is not stronger than {}", args.candidate, args.baseline),
+        SprtDecision::Continue => println!("No decision after {} games", sprt.games()),
+    }
+    Ok(())
+}
+
+/// Play the games, print the standings and write the CSV files
+fn run_tournament<S: CliGame>(game: &GameArgs, specs: &[AgentSpec], options: &TournamentOptions) -> Result<TournamentResults> {
+    let seed = seed(game);
+    let entrants = specs.iter().map(|spec| entrant::<S>(spec, options.threads)).collect::<Result<Vec<_>>>()?;
+    let config = TournamentConfig {
+        games_per_pair: options.games,
+        threads: options.threads,
+        max_moves: options.max_moves,
+        seed,
+    };
+
+    let results = round_robin(&entrants, || S::new_game(game), &config);
+    print!("{}", results.table());
+    if let Some(path) = &options.output {
+        fs::write(path, results.standings_csv()).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    if let Some(path) = &options.games_output {
+        fs::write(path, results.games_csv()).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(results)
+}
+
+fn analyze<S: CliGame>(args: &AnalyzeArgs) -> Result<()> {
+    let seed = seed(&args.game);
+    let mut state = S::new_game(&args.game);
+    for text in args.moves.split_whitespace() {
+        let the_move = S::parse_move(text)?;
+        anyhow::ensure!(state.is_valid_move(&the_move), "Illegal move {}", text);
+        state = state.apply_move(&the_move);
+    }
+    println!("{}", state);
+    anyhow::ensure!(!state.is_over(), "The game is over");
+    S::analyze(&args.agent, seed, &state)
+}
+
+fn generate_dataset(args: &DatasetArgs) -> Result<()> {
+    let encoder = get_encoder_by_name(&args.encoder, args.size)?;
+    let mut config = DatasetConfig {
+        validation_fraction: args.validation,
+        augment: args.augment,
+        seed: args.seed,
+        ..DatasetConfig::default()
+    };
+    if let Some(rulesets) = &args.rulesets {
+        config.rulesets = rulesets.clone();
+    }
+
+    let mut generator = DatasetGenerator::new(encoder.as_ref(), config)?;
+    let mut sink = NpySampleSink::create(&args.output, encoder.shape())?;
+    for input in &args.inputs {
+        generator.add_path(input, &mut sink)?;
+    }
+    sink.finish()?;
+    let report = generator.into_report();
+    for skipped in &report.skipped {
+        println!("Skipped {}: {}", skipped.name, skipped.reason);
+    }
+    println!(
+        "{} training samples from {} games, {} validation samples from {} games, {} games skipped",
+        report.train_samples,
+        report.train_games,
+        report.validation_samples,
+        report.validation_games,
+        report.skipped.len()
+    );
+    Ok(())
+}
+
+fn train_network(args: &TrainArgs) -> Result<()> {
+    let train = Examples::load(&args.data.join("train_features.npy"), &args.data.join("train_labels.npy"))?;
+    let validation_features = args.data.join("validation_features.npy");
+    let validation = if validation_features.exists() {
+        Some(Examples::load(&validation_features, &args.data.join("validation_labels.npy"))?)
+    } else {
+        None
+    };
+    println!(
+        "{} training and {} validation examples",
+        train.len(),
+        validation.as_ref().map_or(0, |v| v.len())
+    );
+
+    let network = match &args.model {
+        Some(model) => Network::load(model)?,
+        None => Network::random(train.shape(), &args.filters, false, args.seed)?,
+    };
+    let config = TrainConfig {
+        epochs: args.epochs,
+        batch_size: args.batch_size,
+        learning_rate: args.learning_rate,
+        optimizer: match args.optimizer {
+            OptimizerKind::Sgd => Optimizer::Sgd { momentum: args.momentum },
+            OptimizerKind::Adam => Optimizer::adam(),
+        },
+        schedule: match args.schedule {
+            ScheduleKind::Constant => Schedule::Constant,
+            ScheduleKind::Step => Schedule::Step { every: args.step_epochs, factor: 0.5 },
+            ScheduleKind::Cosine => Schedule::Cosine,
+        },
+        seed: args.seed,
+        checkpoint_dir: args.checkpoint_dir.clone(),
+        checkpoint_every: 1,
+    };
+    let mut trainer = Trainer::new(network, config)?;
+    trainer.fit(&train, validation.as_ref(), |report| {
+        print!(
+            "Epoch {}: learning rate {:.5}, loss {:.4}, accuracy {:.1}%",
+            report.epoch,
+            report.learning_rate,
+            report.train.loss,
+            100.0 * report.train.accuracy
+        );
+        if let Some(validation) = report.validation {
+            print!(", validation loss {:.4}, accuracy {:.1}%", validation.loss, 100.0 * validation.accuracy);
+        }
+        println!();
+    })?;
+    trainer.network().save(&args.output)?;
+    println!("Saved {}", args.output.display());
+    Ok(())
+}
+
+fn self_play(args: &SelfPlayArgs) -> Result<()> {
+    let seed = args.seed.unwrap_or_else(|| {
+        let seed = rand::random();
+        println!("Seed {}", seed);
+        seed
+    });
+    let opponent_spec = args.opponent.as_ref().unwrap_or(&args.agent);
+    let collector = || -> Result<ExperienceCollector> {
+        Ok(ExperienceCollector::new(get_encoder_by_name(&args.encoder, args.size)?))
+    };
+    let mut agent = RecordingAgent::new(args.agent.build_go(seed, observer(false))?, collector()?);
+    let mut opponent = RecordingAgent::new(opponent_spec.build_go(seed.wrapping_add(1), observer(false))?, collector()?);
+
+    let mut wins = 0;
+    for game in 0..args.games {
+        let state = GoState::new(args.size).with_komi(args.komi);
+        // Alternate colors, the agent moving first in the first game
+        let mut agents: [&mut dyn Agent<GoState>; 2] =
+            if game % 2 == 0 { [&mut agent, &mut opponent] } else { [&mut opponent, &mut agent] };
+        let record = play_game(state, &mut agents, args.max_moves, |_, _| {});
+        // Stopped games are draws, like in the experience
+        if record.final_state.is_over() && record.winner() == Some(game % 2) {
+            wins += 1;
+        }
+    }
+
+    let mut buffers = Vec::new();
+    if args.append && args.output.exists() {
+        buffers.push(ExperienceBuffer::load(&args.output)?);
+    }
+    buffers.push(agent.into_collector().into_buffer());
+    if args.record_opponent {
+        buffers.push(opponent.into_collector().into_buffer());
+    }
+    let buffer = ExperienceBuffer::combine(&buffers)?;
+    buffer.save(&args.output)?;
+    println!(
+        "{} won {} of {} games, {} decisions of {} games in {}",
+        args.agent,
+        wins,
+        args.games,
+        buffer.len(),
+        buffer.num_episodes(),
+        args.output.display()
+    );
+    Ok(())
+}
+
+fn reinforce(args: &ReinforceArgs) -> Result<()> {
+    let buffers = args.experience.iter().map(|path| ExperienceBuffer::load(path)).collect::<Result<Vec<_>>>()?;
+    let experience = ExperienceBuffer::combine(&buffers)?;
+    println!("{} decisions of {} games", experience.len(), experience.num_episodes());
+    if args.evaluate_every.is_some() && !matches!(args.method, RlMethod::Reinforce) {
+        bail!("Evaluation matches are only played with policy gradients");
+    }
+
+    let network = match (&args.model, args.method) {
+        (Some(model), _) => Network::load(model)?,
+        (None, RlMethod::Q) => Network::random_q(experience.shape(), &args.filters, args.seed)?,
+        (None, method) => {
+            let value_head = matches!(method, RlMethod::ActorCritic);
+            Network::random(experience.shape(), &args.filters, value_head, args.seed)?
+        }
+    };
+    let optimizer = match args.optimizer {
+        OptimizerKind::Sgd => Optimizer::Sgd { momentum: args.momentum },
+        OptimizerKind::Adam => Optimizer::adam(),
     };
-    let the_move = match the_move {
-        Move::Play(point) => format!("plays in ({},{})", point.row, point.col),
-        Move::Pass => "passes".to_string(),
-        Move::Resign => "resigns".to_string(),
+    let network = match args.method {
+        RlMethod::Reinforce => {
+            let config = ReinforceConfig {
+                epochs: args.epochs,
+                batch_size: args.batch_size,
+                learning_rate: args.learning_rate,
+                optimizer,
+                baseline: match args.baseline {
+                    BaselineKind::None => Baseline::None,
+                    BaselineKind::Mean => Baseline::Mean,
+                },
+                entropy_weight: args.entropy,
+                seed: args.seed,
+                evaluation: args.evaluate_every.map(|every| Evaluation {
+                    every,
+                    games: args.evaluation_games,
+                    encoder: args.encoder.clone(),
+                    komi: args.komi,
+                    ..Evaluation::default()
+                }),
+            };
+            let mut trainer = ReinforceTrainer::new(network, config)?;
+            trainer.fit(&experience, |report| {
+                print!("Epoch {}: loss {:.4}, entropy {:.3}", report.epoch, report.train.loss, report.train.entropy);
+                if let Some(pair) = &report.evaluation {
+                    print!(
+                        ", scores {:.1}% against the previous policy, Elo difference {:.0} ({:.0} to {:.0})",
+                        100.0 * pair.score_rate,
+                        pair.elo_difference,
+                        pair.elo_interval.0,
+                        pair.elo_interval.1
+                    );
+                }
+                println!();
+            })?;
+            trainer.into_network()
+        }
+        RlMethod::Q => {
+            let config = QConfig {
+                epochs: args.epochs,
+                batch_size: args.batch_size,
+                learning_rate: args.learning_rate,
+                optimizer,
+                seed: args.seed,
+            };
+            let mut trainer = QTrainer::new(network, config)?;
+            trainer.fit(&experience, |report| println!("Epoch {}: loss {:.4}", report.epoch, report.loss))?;
+            trainer.into_network()
+        }
+        RlMethod::ActorCritic => {
+            let config = ActorCriticConfig {
+                epochs: args.epochs,
+                batch_size: args.batch_size,
+                learning_rate: args.learning_rate,
+                optimizer,
+                value_weight: args.value_weight,
+                entropy_weight: args.entropy,
+                seed: args.seed,
+            };
+            let mut trainer = ActorCriticTrainer::new(network, config)?;
+            trainer.fit(&experience, |report| {
+                println!(
+                    "Epoch {}: policy loss {:.4}, value loss {:.4}, entropy {:.3}",
+                    report.epoch, report.train.policy_loss, report.train.value_loss, report.train.entropy
+                );
+            })?;
+            trainer.into_network()
+        }
     };
+    network.save(&args.output)?;
+    println!("Saved {}", args.output.display());
+    Ok(())
+}
+
+/// Analysis by agents without statistics of their own: the searching agents
+/// report each search iteration
+fn analyze_with_agent<S: CliGame>(spec: &AgentSpec, seed: u64, state: &S) -> Result<()> {
+    let mut agent = S::build_agent(spec, seed, true)?;
+    let the_move = agent.select_move(state);
+    println!("Best move {}", S::move_text(&the_move));
+    Ok(())
+}
+
+fn print_search_info<M: Display>(info: &SearchInfo<M>) {
+    println!(
+        "depth {} nodes {} nps {:.0} pv {}",
+        info.depth,
+        info.nodes,
+        info.nodes_per_second(),
+        join(&info.principal_variation)
+    );
+}
 
-    println!("{} {}", player, the_move);
-}
\ No newline at end of file
+/// Moves with their visits and values, most visited first
+fn print_move_stats(value_name: &str, stats: impl Iterator<Item = (String, u32, f64)>) {
+    let mut stats: Vec<_> = stats.collect();
+    stats.sort_by_key(|s| std::cmp::Reverse(s.1));
+    println!("{:>8} {:>8} {:>8}", "move", "visits", value_name);
+    for (the_move, visits, value) in stats {
+        println!("{:>8} {:>8} {:>8.3}", the_move, visits, value);
+    }
+}
+
+fn print_visits<M: Display + Copy>(visits: &[(M, u32)]) {
+    let shares = visit_distribution(visits);
+    print_move_stats("share", visits.iter().zip(shares).map(|((m, n), (_, share))| (m.to_string(), *n, share)));
+}
+
+fn join<M: Display>(moves: &[M]) -> String {
+    moves.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn wait_for_enter() {
+    let mut s = String::new();
+    print!("Proceed? (press enter) ");
+    let _ = stdout().flush();
+    stdin().read_line(&mut s).expect("Something went wrong");
+}
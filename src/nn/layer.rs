@@ -0,0 +1,203 @@
+use std::io::{Read, Seek, Write};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::nn::tensor::{self, BatchNormStats, Tensor};
+use crate::npy::{Array, NpzReader, NpzWriter};
+
+/// Weight of the current batch in the running statistics of batch norm
+const BATCH_NORM_MOMENTUM: f32 = 0.1;
+
+#[derive(Clone, Debug)]
+pub enum Layer {
+    /// Weight of shape (out channels, in channels, kernel rows, kernel
+    /// columns), with stride 1
+    Conv2d { weight: Tensor, bias: Option<Vec<f32>>, padding: usize },
+    BatchNorm { gamma: Vec<f32>, beta: Vec<f32>, mean: Vec<f32>, var: Vec<f32>, eps: f32 },
+    /// Weight of shape (outputs, inputs), applied to the flattened input
+    Dense { weight: Tensor, bias: Option<Vec<f32>> },
+    Relu,
+    Tanh,
+    Softmax,
+}
+
+impl Layer {
+    pub fn forward(&self, input: Tensor) -> Result<Tensor> {
+        Ok(match self {
+            Layer::Conv2d { weight, bias, padding } => tensor::conv2d(&input, weight, bias.as_deref(), *padding)?,
+            Layer::BatchNorm { gamma, beta, mean, var, eps } => tensor::batch_norm(&input, gamma, beta, mean, var, *eps)?,
+            Layer::Dense { weight, bias } => tensor::dense(&input, weight, bias.as_deref())?,
+            Layer::Relu => tensor::relu(input),
+            Layer::Tanh => tensor::tanh(input),
+            Layer::Softmax => tensor::softmax(input),
+        })
+    }
+
+    /// Forward pass in training, keeping what the backward pass needs. Batch
+    /// norm normalizes with the statistics of the batch and adds them to its
+    /// running statistics.
+    pub(crate) fn forward_train(&mut self, input: Tensor) -> Result<(Tensor, Cache)> {
+        let mut stats = None;
+        let output = match self {
+            Layer::BatchNorm { gamma, beta, mean, var, eps } => {
+                let (output, batch) = tensor::batch_norm_train(&input, gamma, beta, *eps)?;
+                // Running variance is unbiased, like PyTorch's
+                let correction = batch.count as f32 / (batch.count as f32 - 1.0).max(1.0);
+                for c in 0..mean.len() {
+                    mean[c] += BATCH_NORM_MOMENTUM * (batch.mean[c] - mean[c]);
+                    var[c] += BATCH_NORM_MOMENTUM * (batch.var[c] * correction - var[c]);
+                }
+                stats = Some(batch);
+                output
+            }
+            _ => self.forward(input.clone())?,
+        };
+        let cache = Cache { input, output: output.clone(), stats };
+        Ok((output, cache))
+    }
+
+    /// Gradient of the layer's input, and of its parameters in the order of
+    /// `parameters_mut`, given the gradient of its output
+    pub(crate) fn backward(&self, cache: &Cache, grad: &Tensor) -> Result<(Tensor, Vec<Vec<f32>>)> {
+        Ok(match self {
+            Layer::Conv2d { weight, bias, padding } => {
+                let (grad_input, grad_weight, grad_bias) = tensor::conv2d_backward(&cache.input, weight, *padding, grad);
+                (grad_input, with_bias(grad_weight.into_data(), grad_bias, bias.is_some()))
+            }
+            Layer::BatchNorm { gamma, .. } => {
+                let stats = cache.stats.as_ref().context("Batch norm was not run in training")?;
+                let (grad_input, grad_gamma, grad_beta) = tensor::batch_norm_backward(stats, gamma, grad);
+                (grad_input, vec![grad_gamma, grad_beta])
+            }
+            Layer::Dense { weight, bias } => {
+                let (grad_input, grad_weight, grad_bias) = tensor::dense_backward(&cache.input, weight, grad);
+                (grad_input, with_bias(grad_weight.into_data(), grad_bias, bias.is_some()))
+            }
+            Layer::Relu => (elementwise(grad, &cache.output, |y| if y > 0.0 { 1.0 } else { 0.0 })?, Vec::new()),
+            Layer::Tanh => (elementwise(grad, &cache.output, |y| 1.0 - y * y)?, Vec::new()),
+            Layer::Softmax => bail!("Softmax can only be trained as the last layer of a policy"),
+        })
+    }
+
+    /// The trainable parameters. Batch norm's running statistics are not
+    /// among them.
+    pub(crate) fn parameters_mut(&mut self) -> Vec<&mut [f32]> {
+        match self {
+            Layer::Conv2d { weight, bias, .. } | Layer::Dense { weight, bias } => {
+                let mut parameters = vec![weight.data_mut()];
+                parameters.extend(bias.as_deref_mut());
+                parameters
+            }
+            Layer::BatchNorm { gamma, beta, .. } => vec![gamma, beta],
+            Layer::Relu | Layer::Tanh | Layer::Softmax => Vec::new(),
+        }
+    }
+
+    /// JSON object of the layer for the spec, with its arrays written to the
+    /// weights under names starting with the prefix
+    pub(crate) fn to_json<W: Write + Seek>(&self, prefix: &str, weights: &mut NpzWriter<W>) -> Result<Value> {
+        let mut add = |name: &str, shape: &[usize], data: &[f32]| -> Result<String> {
+            let name = format!("{}.{}", prefix, name);
+            weights.add(&name, &Array::new(shape.to_vec(), data.to_vec())?)?;
+            Ok(name)
+        };
+        Ok(match self {
+            Layer::Conv2d { weight, bias, padding } => {
+                let mut spec = json!({"type": "conv2d", "padding": padding});
+                spec["weight"] = add("weight", weight.shape(), weight.data())?.into();
+                if let Some(bias) = bias {
+                    spec["bias"] = add("bias", &[bias.len()], bias)?.into();
+                }
+                spec
+            }
+            Layer::BatchNorm { gamma, beta, mean, var, eps } => json!({
+                "type": "batchnorm",
+                "gamma": add("gamma", &[gamma.len()], gamma)?,
+                "beta": add("beta", &[beta.len()], beta)?,
+                "mean": add("mean", &[mean.len()], mean)?,
+                "var": add("var", &[var.len()], var)?,
+                "eps": eps,
+            }),
+            Layer::Dense { weight, bias } => {
+                let mut spec = json!({"type": "dense"});
+                spec["weight"] = add("weight", weight.shape(), weight.data())?.into();
+                if let Some(bias) = bias {
+                    spec["bias"] = add("bias", &[bias.len()], bias)?.into();
+                }
+                spec
+            }
+            Layer::Relu => json!({"type": "relu"}),
+            Layer::Tanh => json!({"type": "tanh"}),
+            Layer::Softmax => json!({"type": "softmax"}),
+        })
+    }
+
+    /// Layer described by a JSON object of the spec, with its arrays read
+    /// from the weights
+    pub(crate) fn from_json<R: Read + Seek>(spec: &Value, weights: &mut NpzReader<R>) -> Result<Self> {
+        let kind = spec.get("type").and_then(Value::as_str).context("Layer has no type")?;
+        let mut array = |key: &str| -> Result<Tensor> {
+            let name = spec.get(key).and_then(Value::as_str).with_context(|| format!("No {} in {} layer", key, kind))?;
+            Ok(weights.read::<f32>(name)?.into())
+        };
+        let mut optional = |key: &str| -> Result<Option<Vec<f32>>> {
+            match spec.get(key) {
+                Some(_) => Ok(Some(array(key)?.into_data())),
+                None => Ok(None),
+            }
+        };
+
+        Ok(match kind {
+            "conv2d" => {
+                let bias = optional("bias")?;
+                let weight = array("weight")?;
+                // Same padding by default, which only odd square kernels have
+                let padding = match (spec.get("padding"), weight.shape()) {
+                    (Some(padding), _) => padding.as_u64().context("Bad padding")? as usize,
+                    (None, [_, _, rows, cols]) if rows == cols && rows % 2 == 1 => rows / 2,
+                    (None, [_, _, rows, cols]) => bail!("Convolution with a {}x{} kernel needs padding", rows, cols),
+                    // Rejected by the convolution
+                    (None, _) => 0,
+                };
+                Layer::Conv2d { weight, bias, padding }
+            }
+            "batchnorm" => Layer::BatchNorm {
+                gamma: array("gamma")?.into_data(),
+                beta: array("beta")?.into_data(),
+                mean: array("mean")?.into_data(),
+                var: array("var")?.into_data(),
+                eps: spec.get("eps").map_or(Some(1e-5), Value::as_f64).context("Bad eps")? as f32,
+            },
+            "dense" => {
+                let bias = optional("bias")?;
+                Layer::Dense { weight: array("weight")?, bias }
+            }
+            "relu" => Layer::Relu,
+            "tanh" => Layer::Tanh,
+            "softmax" => Layer::Softmax,
+            other => bail!("Unknown layer type {}", other),
+        })
+    }
+}
+
+/// What a layer keeps of a forward pass in training for the backward pass
+pub(crate) struct Cache {
+    input: Tensor,
+    output: Tensor,
+    stats: Option<BatchNormStats>,
+}
+
+fn with_bias(grad_weight: Vec<f32>, grad_bias: Vec<f32>, has_bias: bool) -> Vec<Vec<f32>> {
+    if has_bias {
+        vec![grad_weight, grad_bias]
+    } else {
+        vec![grad_weight]
+    }
+}
+
+/// Gradient of an elementwise activation, from its output
+fn elementwise(grad: &Tensor, output: &Tensor, derivative: impl Fn(f32) -> f32) -> Result<Tensor> {
+    let data = grad.data().iter().zip(output.data()).map(|(g, y)| g * derivative(*y)).collect();
+    Tensor::new(grad.shape().to_vec(), data)
+}
@@ -0,0 +1,394 @@
+//! Small convolutional networks on the CPU, evaluated here and trained in
+//! `train`.
+//!
+//! A network is stored as a JSON spec and an uncompressed `.npz` archive of
+//! f32 weights:
+//!
+//! ```json
+//! {
+//!   "input": [7, 9, 9],
+//!   "weights": "model.npz",
+//!   "layers": [
+//!     {"type": "conv2d", "weight": "conv.weight", "bias": "conv.bias"},
+//!     {"type": "batchnorm", "gamma": "bn.weight", "beta": "bn.bias",
+//!      "mean": "bn.running_mean", "var": "bn.running_var", "eps": 1e-5},
+//!     {"type": "relu"}
+//!   ],
+//!   "heads": {
+//!     "policy": [{"type": "dense", "weight": "policy.weight", "bias": "policy.bias"}, {"type": "softmax"}],
+//!     "value": [{"type": "dense", "weight": "value.weight"}, {"type": "tanh"}]
+//!   }
+//! }
+//! ```
+//!
+//! `input` is the (planes, rows, columns) shape of an encoded position and
+//! `weights` the archive's path relative to the spec. The `layers` are
+//! shared by the `heads`, each of which gives one output. Without heads the
+//! network has a single output named `output`.
+//!
+//! Layers take the names of their arrays in the archive, laid out like
+//! PyTorch's: convolution weights are (out channels, in channels, kernel
+//! rows, kernel columns) and dense weights (outputs, inputs). Biases are
+//! optional. Convolutions have stride 1 and keep the image size unless
+//! `padding` is given, which kernels of even or unequal sides need. Dense
+//! layers flatten their input.
+
+pub mod layer;
+pub mod tensor;
+pub mod train;
+
+pub use layer::Layer;
+pub use tensor::Tensor;
+pub use train::{Examples, Trainer, TrainConfig};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+use serde_json::{json, Value};
+
+use crate::game::go::encoders::Encoder;
+use crate::game::go::GoState;
+use crate::npy::{NpzReader, NpzWriter};
+
+/// Name of the output of a network without heads
+pub const DEFAULT_OUTPUT: &str = "output";
+
+/// Head giving move probabilities, one per point of the board
+pub const POLICY_HEAD: &str = "policy";
+
+/// Head giving the value of the position for the player to move, between -1
+/// and 1
+pub const VALUE_HEAD: &str = "value";
+
+/// Head giving the value of each point to play for the player to move,
+/// between -1 and 1
+pub const Q_HEAD: &str = "q";
+
+/// Outputs of a network by head name, each with the batch as the first
+/// dimension
+pub type Outputs = BTreeMap<String, Tensor>;
+
+/// Feed-forward network with a shared trunk and any number of heads.
+/// Evaluation is sequential and so deterministic.
+#[derive(Clone, Debug)]
+pub struct Network {
+    input_shape: (usize, usize, usize),
+    trunk: Vec<Layer>,
+    heads: Vec<(String, Vec<Layer>)>,
+}
+
+impl Network {
+    /// Network for inputs of the shape, checked by evaluating an empty
+    /// position
+    pub fn new(input_shape: (usize, usize, usize), trunk: Vec<Layer>, heads: Vec<(String, Vec<Layer>)>) -> Result<Self> {
+        let heads = if heads.is_empty() { vec![(DEFAULT_OUTPUT.to_string(), Vec::new())] } else { heads };
+        let network = Self { input_shape, trunk, heads };
+        let (planes, rows, cols) = input_shape;
+        network.forward(&Tensor::zeros(vec![1, planes, rows, cols])).context("Layers don't fit together")?;
+        Ok(network)
+    }
+
+    /// Untrained network to train from scratch: 3x3 convolutions with the
+    /// numbers of filters, each followed by ReLU, then a softmax policy head
+    /// with one output per point and, if asked for, a tanh value head.
+    /// Weights are drawn with He initialization and biases are zero.
+    pub fn random(input_shape: (usize, usize, usize), filters: &[usize], value_head: bool, seed: u64) -> Result<Self> {
+        let mut init = RandomInit::new(seed);
+        let trunk = init.trunk(input_shape, filters)?;
+        let features = filters.last().unwrap_or(&input_shape.0) * input_shape.1 * input_shape.2;
+        let points = input_shape.1 * input_shape.2;
+        let mut heads = vec![(POLICY_HEAD.to_string(), vec![init.dense(points, features)?, Layer::Softmax])];
+        if value_head {
+            heads.push((VALUE_HEAD.to_string(), vec![init.dense(1, features)?, Layer::Tanh]));
+        }
+        Self::new(input_shape, trunk, heads)
+    }
+
+    /// Untrained network like `random`, with a tanh Q head of one output
+    /// per point instead of the policy and value heads
+    pub fn random_q(input_shape: (usize, usize, usize), filters: &[usize], seed: u64) -> Result<Self> {
+        let mut init = RandomInit::new(seed);
+        let trunk = init.trunk(input_shape, filters)?;
+        let features = filters.last().unwrap_or(&input_shape.0) * input_shape.1 * input_shape.2;
+        let q = vec![init.dense(input_shape.1 * input_shape.2, features)?, Layer::Tanh];
+        Self::new(input_shape, trunk, vec![(Q_HEAD.to_string(), q)])
+    }
+
+    /// Load the network of a JSON spec and the weights it refers to
+    pub fn load(spec_path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(spec_path).with_context(|| format!("Failed to read {}", spec_path.display()))?;
+        let spec: Value = serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", spec_path.display()))?;
+        let weights = spec.get("weights").and_then(Value::as_str).context("No weights in the network spec")?;
+        let weights_path = spec_path.parent().unwrap_or_else(|| Path::new("")).join(weights);
+        let mut weights = NpzReader::open(&weights_path)?;
+        Self::from_json(&spec, &mut weights).with_context(|| format!("Failed to load {}", spec_path.display()))
+    }
+
+    /// Network of a parsed spec, with the weights read from the archive
+    pub fn from_json<R: Read + Seek>(spec: &Value, weights: &mut NpzReader<R>) -> Result<Self> {
+        let input: Vec<usize> = spec
+            .get("input")
+            .and_then(Value::as_array)
+            .map(|dims| dims.iter().filter_map(Value::as_u64).map(|d| d as usize).collect())
+            .unwrap_or_default();
+        ensure!(input.len() == 3, "The network spec has no input shape of planes, rows and columns");
+
+        let trunk = layers(spec.get("layers"), weights).context("Failed to load the layers")?;
+        let mut heads = Vec::new();
+        if let Some(spec_heads) = spec.get("heads") {
+            let spec_heads = spec_heads.as_object().context("The heads aren't an object")?;
+            for (name, head) in spec_heads {
+                let head = layers(Some(head), weights).with_context(|| format!("Failed to load head {}", name))?;
+                heads.push((name.clone(), head));
+            }
+        }
+        Self::new((input[0], input[1], input[2]), trunk, heads)
+    }
+
+    /// Save the network as a JSON spec and an archive of its weights with
+    /// the same name and the `.npz` extension
+    pub fn save(&self, spec_path: &Path) -> Result<()> {
+        let weights_path = spec_path.with_extension("npz");
+        let mut weights = NpzWriter::create(&weights_path)?;
+        let trunk = self
+            .trunk
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| layer.to_json(&format!("layers.{}", i), &mut weights))
+            .collect::<Result<Vec<_>>>()?;
+        let mut heads = serde_json::Map::new();
+        for (name, head) in &self.heads {
+            let layers = head
+                .iter()
+                .enumerate()
+                .map(|(i, layer)| layer.to_json(&format!("{}.{}", name, i), &mut weights))
+                .collect::<Result<Vec<_>>>()?;
+            heads.insert(name.clone(), layers.into());
+        }
+        weights.finish()?;
+
+        let (planes, rows, cols) = self.input_shape;
+        let weights_name = weights_path.file_name().context("No file name for the weights")?.to_string_lossy();
+        let spec = json!({
+            "input": [planes, rows, cols],
+            "weights": weights_name,
+            "layers": trunk,
+            "heads": heads,
+        });
+        fs::write(spec_path, serde_json::to_string_pretty(&spec)? + "\n")
+            .with_context(|| format!("Failed to write {}", spec_path.display()))
+    }
+
+    pub fn input_shape(&self) -> (usize, usize, usize) {
+        self.input_shape
+    }
+
+    pub fn head_names(&self) -> impl Iterator<Item = &str> {
+        self.heads.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Name of the head giving move probabilities: the policy head, or the
+    /// only output of a network without heads
+    pub fn policy_head(&self) -> Option<&str> {
+        [POLICY_HEAD, DEFAULT_OUTPUT].iter().copied().find(|name| self.head_names().any(|h| h == *name))
+    }
+
+    /// Outputs for a batch of inputs of shape (batch, planes, rows, columns)
+    pub fn forward(&self, input: &Tensor) -> Result<Outputs> {
+        let (planes, rows, cols) = self.input_shape;
+        ensure!(
+            input.shape().len() == 4 && input.shape()[1..] == [planes, rows, cols],
+            "Input of shape {:?} doesn't match the network's {:?}",
+            input.shape(),
+            self.input_shape
+        );
+        let mut features = input.clone();
+        for layer in &self.trunk {
+            features = layer.forward(features)?;
+        }
+        let mut outputs = Outputs::new();
+        for (name, head) in &self.heads {
+            let mut output = features.clone();
+            for layer in head {
+                output = layer.forward(output)?;
+            }
+            outputs.insert(name.clone(), output);
+        }
+        Ok(outputs)
+    }
+
+    /// Outputs for the positions, encoded with the encoder
+    pub fn evaluate(&self, encoder: &dyn Encoder, states: &[GoState]) -> Result<Outputs> {
+        ensure!(
+            encoder.shape() == self.input_shape,
+            "Encoder {} of shape {:?} doesn't match the network's {:?}",
+            encoder.name(),
+            encoder.shape(),
+            self.input_shape
+        );
+        let (planes, rows, cols) = self.input_shape;
+        let data = states.iter().flat_map(|state| encoder.encode(state)).collect();
+        self.forward(&Tensor::new(vec![states.len(), planes, rows, cols], data)?)
+    }
+}
+
+/// He initialization of the layers of random networks
+struct RandomInit {
+    rng: rand_pcg::Pcg64,
+}
+
+impl RandomInit {
+    fn new(seed: u64) -> Self {
+        Self { rng: rand_pcg::Pcg64::seed_from_u64(seed) }
+    }
+
+    fn tensor(&mut self, shape: Vec<usize>, fan_in: usize) -> Result<Tensor> {
+        let normal = Normal::new(0.0, (2.0 / fan_in as f32).sqrt())?;
+        let len = shape.iter().product();
+        Tensor::new(shape, normal.sample_iter(&mut self.rng).take(len).collect())
+    }
+
+    /// 3x3 convolutions with the numbers of filters, each followed by ReLU
+    fn trunk(&mut self, input_shape: (usize, usize, usize), filters: &[usize]) -> Result<Vec<Layer>> {
+        let mut trunk = Vec::new();
+        let mut channels = input_shape.0;
+        for &out in filters {
+            let weight = self.tensor(vec![out, channels, 3, 3], channels * 9)?;
+            trunk.push(Layer::Conv2d { weight, bias: Some(vec![0.0; out]), padding: 1 });
+            trunk.push(Layer::Relu);
+            channels = out;
+        }
+        Ok(trunk)
+    }
+
+    fn dense(&mut self, outputs: usize, inputs: usize) -> Result<Layer> {
+        Ok(Layer::Dense { weight: self.tensor(vec![outputs, inputs], inputs)?, bias: Some(vec![0.0; outputs]) })
+    }
+}
+
+fn layers<R: Read + Seek>(spec: Option<&Value>, weights: &mut NpzReader<R>) -> Result<Vec<Layer>> {
+    let spec = spec.and_then(Value::as_array).context("Layers must be an array")?;
+    spec.iter()
+        .enumerate()
+        .map(|(i, layer)| Layer::from_json(layer, weights).with_context(|| format!("Bad layer {}", i)))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::game::go::encoders::OnePlaneEncoder;
+    use crate::game::go::{Move, Point};
+    use crate::game::GameState;
+    use crate::npy::{Array, NpzWriter};
+    use std::io::Cursor;
+
+    /// Weights of a network on 5x5 one-plane encodings: a 2-filter
+    /// convolution, policy over the points and a value
+    pub(crate) fn test_weights() -> NpzReader<Cursor<Vec<u8>>> {
+        let mut npz = NpzWriter::new(Cursor::new(Vec::new()));
+        let conv: Vec<f32> = (0..18).map(|i| (i as f32 - 9.0) / 10.0).collect();
+        npz.add("conv.weight", &Array::new(vec![2, 1, 3, 3], conv).unwrap()).unwrap();
+        npz.add("conv.bias", &Array::new(vec![2], vec![0.1, -0.1]).unwrap()).unwrap();
+        for (name, value) in [("gamma", 1.0), ("beta", 0.0), ("mean", 0.0), ("var", 1.0)] {
+            npz.add(&format!("bn.{}", name), &Array::new(vec![2], vec![value; 2]).unwrap()).unwrap();
+        }
+        let policy: Vec<f32> = (0..25 * 50).map(|i| ((i * 7) % 11) as f32 / 11.0 - 0.5).collect();
+        npz.add("policy.weight", &Array::new(vec![25, 50], policy).unwrap()).unwrap();
+        let value: Vec<f32> = (0..50).map(|i| ((i * 3) % 5) as f32 / 5.0 - 0.4).collect();
+        npz.add("value.weight", &Array::new(vec![1, 50], value).unwrap()).unwrap();
+        NpzReader::new(Cursor::new(npz.finish().unwrap().into_inner())).unwrap()
+    }
+
+    pub(crate) const TEST_SPEC: &str = r#"{
+        "input": [1, 5, 5],
+        "layers": [
+            {"type": "conv2d", "weight": "conv.weight", "bias": "conv.bias"},
+            {"type": "batchnorm", "gamma": "bn.gamma", "beta": "bn.beta", "mean": "bn.mean", "var": "bn.var"},
+            {"type": "relu"}
+        ],
+        "heads": {
+            "policy": [{"type": "dense", "weight": "policy.weight"}, {"type": "softmax"}],
+            "value": [{"type": "dense", "weight": "value.weight"}, {"type": "tanh"}]
+        }
+    }"#;
+
+    #[test]
+    fn test_evaluate_batch_deterministically() {
+        let spec = serde_json::from_str(TEST_SPEC).unwrap();
+        let network = Network::from_json(&spec, &mut test_weights()).unwrap();
+        assert_eq!(network.head_names().collect::<Vec<_>>(), vec!["policy", "value"]);
+
+        let encoder = OnePlaneEncoder::new(5);
+        let empty = GoState::new(5);
+        let played = empty.apply_move(&Move::Play(Point::new(2, 3)));
+        let outputs = network.evaluate(&encoder, &[empty.clone(), played.clone()]).unwrap();
+        let policy = &outputs["policy"];
+        let value = &outputs["value"];
+        assert_eq!(policy.shape(), &[2, 25]);
+        assert_eq!(value.shape(), &[2, 1]);
+        assert!((policy.item(1).iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(value.data().iter().all(|v| v.abs() <= 1.0));
+        assert_ne!(policy.item(0), policy.item(1));
+
+        // Items of a batch are evaluated as if they were alone
+        let single = network.evaluate(&encoder, &[played]).unwrap();
+        assert_eq!(single["policy"].item(0), policy.item(1));
+        assert_eq!(single["value"].item(0), value.item(1));
+        assert_eq!(network.evaluate(&encoder, &[empty.clone(), empty]).unwrap()["policy"].item(1), policy.item(0));
+
+        let none = network.evaluate(&encoder, &[]).unwrap();
+        assert_eq!(none["policy"].shape(), &[0, 25]);
+        assert_eq!(none["value"].shape(), &[0, 1]);
+    }
+
+    #[test]
+    fn test_mismatched_spec_is_rejected() {
+        let bad_input = TEST_SPEC.replace("[1, 5, 5]", "[1, 4, 4]");
+        let spec = serde_json::from_str(&bad_input).unwrap();
+        assert!(Network::from_json(&spec, &mut test_weights()).is_err());
+
+        let missing = TEST_SPEC.replace("conv.bias", "conv.offset");
+        let spec = serde_json::from_str(&missing).unwrap();
+        assert!(Network::from_json(&spec, &mut test_weights()).is_err());
+
+        let network = Network::from_json(&serde_json::from_str(TEST_SPEC).unwrap(), &mut test_weights()).unwrap();
+        assert!(network.evaluate(&OnePlaneEncoder::new(9), &[GoState::new(9)]).is_err());
+    }
+
+    #[test]
+    fn test_even_kernel_needs_padding() {
+        let mut npz = NpzWriter::new(Cursor::new(Vec::new()));
+        npz.add("conv.weight", &Array::new(vec![1, 1, 2, 2], vec![1.0; 4]).unwrap()).unwrap();
+        let bytes = npz.finish().unwrap().into_inner();
+        let weights = || NpzReader::new(Cursor::new(bytes.clone())).unwrap();
+
+        let spec = r#"{"input": [1, 5, 5], "layers": [{"type": "conv2d", "weight": "conv.weight"}]}"#;
+        assert!(Network::from_json(&serde_json::from_str(spec).unwrap(), &mut weights()).is_err());
+        let padded = spec.replace(r#""weight": "conv.weight""#, r#""weight": "conv.weight", "padding": 1"#);
+        let network = Network::from_json(&serde_json::from_str(&padded).unwrap(), &mut weights()).unwrap();
+        let outputs = network.evaluate(&OnePlaneEncoder::new(5), &[GoState::new(5)]).unwrap();
+        assert_eq!(outputs[DEFAULT_OUTPUT].shape(), &[1, 1, 6, 6]);
+    }
+
+    #[test]
+    fn test_network_without_heads() {
+        let spec = r#"{"input": [1, 5, 5], "layers": [{"type": "dense", "weight": "value.weight"}]}"#;
+        let mut weights = test_weights();
+        let conv: Array<f32> = weights.read("conv.weight").unwrap();
+        assert_eq!(conv.shape, vec![2, 1, 3, 3]);
+        // Dense over 25 inputs doesn't fit the 50-input weight
+        assert!(Network::from_json(&serde_json::from_str(spec).unwrap(), &mut weights).is_err());
+
+        let layer = Layer::Dense { weight: Tensor::new(vec![1, 25], vec![1.0; 25]).unwrap(), bias: None };
+        let network = Network::new((1, 5, 5), vec![layer], Vec::new()).unwrap();
+        let game = GoState::new(5).apply_move(&Move::Play(Point::new(1, 1)));
+        let outputs = network.evaluate(&OnePlaneEncoder::new(5), &[game]).unwrap();
+        assert_eq!(outputs[DEFAULT_OUTPUT].data(), &[-1.0]);
+    }
+}
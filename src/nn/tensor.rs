@@ -0,0 +1,378 @@
+//! Dense f32 tensors and the operations of inference and their gradients.
+//! Batches are the first dimension, and images are in (batch, channel, row,
+//! column) order.
+
+use anyhow::{ensure, Result};
+
+use crate::npy::Array;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tensor {
+    shape: Vec<usize>,
+    /// Elements in row-major order
+    data: Vec<f32>,
+}
+
+impl Tensor {
+    pub fn new(shape: Vec<usize>, data: Vec<f32>) -> Result<Self> {
+        let len: usize = shape.iter().product();
+        ensure!(len == data.len(), "Shape {:?} has {} elements, but there are {}", shape, len, data.len());
+        Ok(Self { shape, data })
+    }
+
+    pub fn zeros(shape: Vec<usize>) -> Self {
+        let len = shape.iter().product();
+        Self { shape, data: vec![0.0; len] }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<f32> {
+        self.data
+    }
+
+    pub fn reshape(self, shape: Vec<usize>) -> Result<Self> {
+        Self::new(shape, self.data)
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.shape.first().copied().unwrap_or(1)
+    }
+
+    /// Elements of one item of the batch
+    pub fn item(&self, index: usize) -> &[f32] {
+        if self.batch_size() == 0 {
+            return &[];
+        }
+        let len = self.data.len() / self.batch_size();
+        &self.data[index * len..(index + 1) * len]
+    }
+
+    fn map(mut self, f: impl Fn(f32) -> f32) -> Self {
+        self.data.iter_mut().for_each(|x| *x = f(*x));
+        self
+    }
+}
+
+impl From<Array<f32>> for Tensor {
+    fn from(array: Array<f32>) -> Self {
+        Self { shape: array.shape, data: array.data }
+    }
+}
+
+/// Convolution with stride 1 and `padding` zeros around the image. The
+/// weight has shape (out channels, in channels, kernel rows, kernel columns).
+pub fn conv2d(input: &Tensor, weight: &Tensor, bias: Option<&[f32]>, padding: usize) -> Result<Tensor> {
+    ensure!(input.shape.len() == 4, "Convolution input of shape {:?} is not a batch of images", input.shape);
+    ensure!(weight.shape.len() == 4, "Convolution weight of shape {:?} is not 4-dimensional", weight.shape);
+    let (batch, channels, rows, cols) = (input.shape[0], input.shape[1], input.shape[2], input.shape[3]);
+    let (filters, kernel_rows, kernel_cols) = (weight.shape[0], weight.shape[2], weight.shape[3]);
+    ensure!(
+        weight.shape[1] == channels,
+        "Convolution weight of shape {:?} doesn't take {} channels",
+        weight.shape,
+        channels
+    );
+    ensure!(
+        rows + 2 * padding >= kernel_rows && cols + 2 * padding >= kernel_cols,
+        "Kernel of shape {:?} is larger than the padded image",
+        weight.shape
+    );
+    if let Some(bias) = bias {
+        ensure!(bias.len() == filters, "Convolution bias has {} values for {} filters", bias.len(), filters);
+    }
+
+    let out_rows = rows + 2 * padding - kernel_rows + 1;
+    let out_cols = cols + 2 * padding - kernel_cols + 1;
+    let mut output = Tensor::zeros(vec![batch, filters, out_rows, out_cols]);
+    for n in 0..batch {
+        for filter in 0..filters {
+            let out = &mut output.data[(n * filters + filter) * out_rows * out_cols..][..out_rows * out_cols];
+            if let Some(bias) = bias {
+                out.fill(bias[filter]);
+            }
+            for channel in 0..channels {
+                let image = &input.data[(n * channels + channel) * rows * cols..][..rows * cols];
+                for ky in 0..kernel_rows {
+                    for kx in 0..kernel_cols {
+                        let w = weight.data[((filter * channels + channel) * kernel_rows + ky) * kernel_cols + kx];
+                        for y in 0..out_rows {
+                            // Input row and column, skipping the zero padding
+                            let row = match (y + ky).checked_sub(padding) {
+                                Some(row) if row < rows => row,
+                                _ => continue,
+                            };
+                            for x in 0..out_cols {
+                                if let Some(col) = (x + kx).checked_sub(padding).filter(|c| *c < cols) {
+                                    out[y * out_cols + x] += w * image[row * cols + col];
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Normalization of the second dimension, i.e. channels of images or
+/// features of vectors, with the statistics collected in training
+pub fn batch_norm(input: &Tensor, gamma: &[f32], beta: &[f32], mean: &[f32], var: &[f32], eps: f32) -> Result<Tensor> {
+    ensure!(input.shape.len() >= 2, "Batch norm input of shape {:?} has no channels", input.shape);
+    let channels = input.shape[1];
+    ensure!(
+        [gamma, beta, mean, var].iter().all(|p| p.len() == channels),
+        "Batch norm parameters don't match {} channels",
+        channels
+    );
+    let inner: usize = input.shape[2..].iter().product();
+    let mut output = input.clone();
+    for (index, chunk) in output.data.chunks_mut(inner).enumerate() {
+        let c = index % channels;
+        let scale = gamma[c] / (var[c] + eps).sqrt();
+        let shift = beta[c] - mean[c] * scale;
+        chunk.iter_mut().for_each(|x| *x = *x * scale + shift);
+    }
+    Ok(output)
+}
+
+/// Fully connected layer on the flattened items of the batch. The weight
+/// has shape (outputs, inputs).
+pub fn dense(input: &Tensor, weight: &Tensor, bias: Option<&[f32]>) -> Result<Tensor> {
+    ensure!(weight.shape.len() == 2, "Dense weight of shape {:?} is not 2-dimensional", weight.shape);
+    let batch = input.batch_size();
+    let (outputs, inputs) = (weight.shape[0], weight.shape[1]);
+    ensure!(
+        input.data.len() == batch * inputs,
+        "Dense weight of shape {:?} doesn't take input of shape {:?}",
+        weight.shape,
+        input.shape
+    );
+    if let Some(bias) = bias {
+        ensure!(bias.len() == outputs, "Dense bias has {} values for {} outputs", bias.len(), outputs);
+    }
+
+    let mut output = Tensor::zeros(vec![batch, outputs]);
+    for n in 0..batch {
+        let item = input.item(n);
+        for (o, out) in output.data[n * outputs..(n + 1) * outputs].iter_mut().enumerate() {
+            let row = &weight.data[o * inputs..(o + 1) * inputs];
+            *out = row.iter().zip(item).map(|(w, x)| w * x).sum::<f32>() + bias.map_or(0.0, |b| b[o]);
+        }
+    }
+    Ok(output)
+}
+
+/// Gradients of the input, weight and bias of `conv2d` given the gradient
+/// of its output
+pub fn conv2d_backward(input: &Tensor, weight: &Tensor, padding: usize, grad: &Tensor) -> (Tensor, Tensor, Vec<f32>) {
+    let (batch, channels, rows, cols) = (input.shape[0], input.shape[1], input.shape[2], input.shape[3]);
+    let (filters, kernel_rows, kernel_cols) = (weight.shape[0], weight.shape[2], weight.shape[3]);
+    let (out_rows, out_cols) = (grad.shape[2], grad.shape[3]);
+    let mut grad_input = Tensor::zeros(input.shape.clone());
+    let mut grad_weight = Tensor::zeros(weight.shape.clone());
+    let mut grad_bias = vec![0.0; filters];
+    for n in 0..batch {
+        for (filter, grad_b) in grad_bias.iter_mut().enumerate() {
+            let out = &grad.data[(n * filters + filter) * out_rows * out_cols..][..out_rows * out_cols];
+            *grad_b += out.iter().sum::<f32>();
+            for channel in 0..channels {
+                let image_start = (n * channels + channel) * rows * cols;
+                for ky in 0..kernel_rows {
+                    for kx in 0..kernel_cols {
+                        let w_index = ((filter * channels + channel) * kernel_rows + ky) * kernel_cols + kx;
+                        let w = weight.data[w_index];
+                        let mut grad_w = 0.0;
+                        for y in 0..out_rows {
+                            let row = match (y + ky).checked_sub(padding) {
+                                Some(row) if row < rows => row,
+                                _ => continue,
+                            };
+                            for x in 0..out_cols {
+                                if let Some(col) = (x + kx).checked_sub(padding).filter(|c| *c < cols) {
+                                    let g = out[y * out_cols + x];
+                                    let at = image_start + row * cols + col;
+                                    grad_w += g * input.data[at];
+                                    grad_input.data[at] += g * w;
+                                }
+                            }
+                        }
+                        grad_weight.data[w_index] += grad_w;
+                    }
+                }
+            }
+        }
+    }
+    (grad_input, grad_weight, grad_bias)
+}
+
+/// Statistics of a batch normalized in training
+#[derive(Clone, Debug)]
+pub struct BatchNormStats {
+    pub mean: Vec<f32>,
+    pub var: Vec<f32>,
+    /// Number of values per channel
+    pub count: usize,
+    normalized: Tensor,
+    inv_std: Vec<f32>,
+}
+
+/// Batch norm with the statistics of the batch itself, as in training
+pub fn batch_norm_train(input: &Tensor, gamma: &[f32], beta: &[f32], eps: f32) -> Result<(Tensor, BatchNormStats)> {
+    ensure!(input.shape.len() >= 2, "Batch norm input of shape {:?} has no channels", input.shape);
+    let channels = input.shape[1];
+    ensure!(gamma.len() == channels && beta.len() == channels, "Batch norm parameters don't match {} channels", channels);
+    let inner: usize = input.shape[2..].iter().product();
+    let count = input.batch_size() * inner;
+    let mut mean = vec![0.0; channels];
+    let mut var = vec![0.0; channels];
+    for (index, chunk) in input.data.chunks(inner).enumerate() {
+        mean[index % channels] += chunk.iter().sum::<f32>() / count as f32;
+    }
+    for (index, chunk) in input.data.chunks(inner).enumerate() {
+        let c = index % channels;
+        var[c] += chunk.iter().map(|x| (x - mean[c]).powi(2)).sum::<f32>() / count as f32;
+    }
+    let inv_std: Vec<f32> = var.iter().map(|v| 1.0 / (v + eps).sqrt()).collect();
+
+    let mut normalized = input.clone();
+    let mut output = input.clone();
+    for (index, (norm, out)) in normalized.data.chunks_mut(inner).zip(output.data.chunks_mut(inner)).enumerate() {
+        let c = index % channels;
+        for (n, o) in norm.iter_mut().zip(out) {
+            *n = (*n - mean[c]) * inv_std[c];
+            *o = *n * gamma[c] + beta[c];
+        }
+    }
+    Ok((output, BatchNormStats { mean, var, count, normalized, inv_std }))
+}
+
+/// Gradients of the input, gamma and beta of `batch_norm_train`
+pub fn batch_norm_backward(stats: &BatchNormStats, gamma: &[f32], grad: &Tensor) -> (Tensor, Vec<f32>, Vec<f32>) {
+    let channels = gamma.len();
+    let inner: usize = grad.shape[2..].iter().product();
+    let mut grad_gamma = vec![0.0; channels];
+    let mut grad_beta = vec![0.0; channels];
+    for (index, (g, norm)) in grad.data.chunks(inner).zip(stats.normalized.data.chunks(inner)).enumerate() {
+        let c = index % channels;
+        grad_beta[c] += g.iter().sum::<f32>();
+        grad_gamma[c] += g.iter().zip(norm).map(|(g, n)| g * n).sum::<f32>();
+    }
+
+    let count = stats.count as f32;
+    let mut grad_input = grad.clone();
+    for (index, (g, norm)) in grad_input.data.chunks_mut(inner).zip(stats.normalized.data.chunks(inner)).enumerate() {
+        let c = index % channels;
+        let scale = gamma[c] * stats.inv_std[c] / count;
+        for (g, n) in g.iter_mut().zip(norm) {
+            *g = scale * (count * *g - grad_beta[c] - n * grad_gamma[c]);
+        }
+    }
+    (grad_input, grad_gamma, grad_beta)
+}
+
+/// Gradients of the input, weight and bias of `dense`
+pub fn dense_backward(input: &Tensor, weight: &Tensor, grad: &Tensor) -> (Tensor, Tensor, Vec<f32>) {
+    let batch = input.batch_size();
+    let (outputs, inputs) = (weight.shape[0], weight.shape[1]);
+    let mut grad_input = Tensor::zeros(input.shape.clone());
+    let mut grad_weight = Tensor::zeros(weight.shape.clone());
+    let mut grad_bias = vec![0.0; outputs];
+    for n in 0..batch {
+        let item = input.item(n);
+        for (o, grad_b) in grad_bias.iter_mut().enumerate() {
+            let g = grad.data[n * outputs + o];
+            *grad_b += g;
+            let row = &weight.data[o * inputs..(o + 1) * inputs];
+            let grad_row = &mut grad_weight.data[o * inputs..(o + 1) * inputs];
+            let grad_item = &mut grad_input.data[n * inputs..(n + 1) * inputs];
+            for i in 0..inputs {
+                grad_row[i] += g * item[i];
+                grad_item[i] += g * row[i];
+            }
+        }
+    }
+    (grad_input, grad_weight, grad_bias)
+}
+
+pub fn relu(input: Tensor) -> Tensor {
+    input.map(|x| x.max(0.0))
+}
+
+pub fn tanh(input: Tensor) -> Tensor {
+    input.map(f32::tanh)
+}
+
+/// Softmax over all the elements of each item of the batch
+pub fn softmax(mut input: Tensor) -> Tensor {
+    if input.batch_size() == 0 {
+        return input;
+    }
+    let len = input.data.len() / input.batch_size();
+    for item in input.data.chunks_mut(len.max(1)) {
+        let max = item.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        item.iter_mut().for_each(|x| *x = (*x - max).exp());
+        let sum: f32 = item.iter().sum();
+        item.iter_mut().for_each(|x| *x /= sum);
+    }
+    input
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conv2d_with_padding() {
+        // 3x3 image of 1..9 and a kernel summing the neighborhood
+        let input = Tensor::new(vec![1, 1, 3, 3], (1..=9).map(|x| x as f32).collect()).unwrap();
+        let weight = Tensor::new(vec![2, 1, 3, 3], [vec![1.0; 9], vec![0.0; 9]].concat()).unwrap();
+        let output = conv2d(&input, &weight, Some(&[0.0, 0.5]), 1).unwrap();
+        assert_eq!(output.shape(), &[1, 2, 3, 3]);
+        assert_eq!(&output.data()[..9], &[12.0, 21.0, 16.0, 27.0, 45.0, 33.0, 24.0, 39.0, 28.0]);
+        assert_eq!(&output.data()[9..], &[0.5; 9]);
+
+        let valid = conv2d(&input, &weight, None, 0).unwrap();
+        assert_eq!(valid.shape(), &[1, 2, 1, 1]);
+        assert_eq!(valid.data()[0], 45.0);
+        assert!(conv2d(&input, &Tensor::zeros(vec![1, 2, 3, 3]), None, 1).is_err());
+    }
+
+    #[test]
+    fn test_batch_norm_per_channel() {
+        let input = Tensor::new(vec![1, 2, 1, 2], vec![1.0, 3.0, 10.0, 20.0]).unwrap();
+        let output = batch_norm(&input, &[1.0, 2.0], &[0.0, 1.0], &[2.0, 10.0], &[1.0, 4.0], 0.0).unwrap();
+        assert_eq!(output.data(), &[-1.0, 1.0, 1.0, 11.0]);
+    }
+
+    #[test]
+    fn test_dense_flattens_items() {
+        let input = Tensor::new(vec![2, 1, 2], vec![1.0, 2.0, 3.0, 4.0]).unwrap();
+        let weight = Tensor::new(vec![3, 2], vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0]).unwrap();
+        let output = dense(&input, &weight, Some(&[0.0, 0.0, 1.0])).unwrap();
+        assert_eq!(output.shape(), &[2, 3]);
+        assert_eq!(output.data(), &[1.0, 2.0, 4.0, 3.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn test_activations() {
+        let input = Tensor::new(vec![2, 2], vec![-1.0, 1.0, 1000.0, 1000.0]).unwrap();
+        assert_eq!(relu(input.clone()).data(), &[0.0, 1.0, 1000.0, 1000.0]);
+        assert!((tanh(input.clone()).data()[0] + 0.7616).abs() < 1e-4);
+        let probabilities = softmax(input);
+        assert!((probabilities.item(0).iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(probabilities.item(0)[1] > probabilities.item(0)[0]);
+        assert_eq!(probabilities.item(1), &[0.5, 0.5]);
+    }
+}
@@ -0,0 +1,587 @@
+//! Supervised training of policy networks on positions labeled with the
+//! move played, with cross-entropy loss and mini-batch SGD or Adam
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::game::go::encoders::Encoder;
+use crate::game::go::{GoState, Move};
+use crate::nn::layer::Cache;
+use crate::nn::{Layer, Network, Tensor};
+use crate::npy::{read_npy, Array};
+
+/// Encoded positions, each labeled with the index of a move output
+#[derive(Clone, Debug, PartialEq)]
+pub struct Examples {
+    shape: (usize, usize, usize),
+    features: Vec<f32>,
+    labels: Vec<usize>,
+}
+
+impl Examples {
+    /// No examples yet, for features of the (planes, rows, columns) shape
+    pub fn new(shape: (usize, usize, usize)) -> Self {
+        Self { shape, features: Vec::new(), labels: Vec::new() }
+    }
+
+    pub fn add(&mut self, features: &[f32], label: usize) -> Result<()> {
+        let (planes, rows, cols) = self.shape;
+        ensure!(features.len() == planes * rows * cols, "{} features don't have shape {:?}", features.len(), self.shape);
+        self.features.extend_from_slice(features);
+        self.labels.push(label);
+        Ok(())
+    }
+
+    /// Add the position encoded by the encoder, labeled with the point
+    /// played. Passes and resignations have no label.
+    pub fn add_move(&mut self, encoder: &dyn Encoder, game_state: &GoState, the_move: &Move) -> Result<()> {
+        match the_move {
+            Move::Play(point) => self.add(&encoder.encode(game_state), encoder.encode_point(point)),
+            _ => bail!("Only moves on the board can be labeled, not {:?}", the_move),
+        }
+    }
+
+    /// Examples of `.npy` files of features, of shape (examples, planes,
+    /// rows, columns), and labels, as written by the dataset generator
+    pub fn load(features_path: &Path, labels_path: &Path) -> Result<Self> {
+        let features: Array<f32> = read_npy(features_path)?;
+        let labels: Array<i64> = read_npy(labels_path)?;
+        ensure!(features.shape.len() == 4, "Features of shape {:?} aren't encoded positions", features.shape);
+        ensure!(
+            labels.shape == features.shape[..1],
+            "{} labels for {} positions",
+            labels.data.len(),
+            features.shape[0]
+        );
+        let labels = labels
+            .data
+            .into_iter()
+            .map(|label| usize::try_from(label).with_context(|| format!("Invalid label {}", label)))
+            .collect::<Result<_>>()?;
+        let shape = (features.shape[1], features.shape[2], features.shape[3]);
+        Ok(Self { shape, features: features.data, labels })
+    }
+
+    pub fn shape(&self) -> (usize, usize, usize) {
+        self.shape
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Features of the examples as a batch, and their labels
+    fn batch(&self, indices: &[usize]) -> Result<(Tensor, Vec<usize>)> {
+        let (planes, rows, cols) = self.shape;
+        let size = planes * rows * cols;
+        let features = indices.iter().flat_map(|i| &self.features[i * size..(i + 1) * size]).copied().collect();
+        let labels = indices.iter().map(|i| self.labels[*i]).collect();
+        Ok((Tensor::new(vec![indices.len(), planes, rows, cols], features)?, labels))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Optimizer {
+    Sgd { momentum: f32 },
+    Adam { beta1: f32, beta2: f32 },
+}
+
+impl Optimizer {
+    pub fn adam() -> Self {
+        Optimizer::Adam { beta1: 0.9, beta2: 0.999 }
+    }
+}
+
+/// How the learning rate changes from epoch to epoch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Schedule {
+    Constant,
+    /// Multiplied by the factor every `every` epochs
+    Step { every: usize, factor: f32 },
+    /// Cosine decay towards zero over the epochs
+    Cosine,
+}
+
+impl Schedule {
+    /// Learning rate of the epoch, counting from zero
+    pub fn learning_rate(&self, base: f32, epoch: usize, epochs: usize) -> f32 {
+        match *self {
+            Schedule::Constant => base,
+            Schedule::Step { every, factor } => base * factor.powi((epoch / every.max(1)) as i32),
+            Schedule::Cosine => base * 0.5 * (1.0 + (std::f32::consts::PI * epoch as f32 / epochs.max(1) as f32).cos()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrainConfig {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    pub optimizer: Optimizer,
+    pub schedule: Schedule,
+    /// Seed for shuffling the examples
+    pub seed: u64,
+    /// Directory where the network is saved as `epoch-N.json` every
+    /// `checkpoint_every` epochs and after the last one
+    pub checkpoint_dir: Option<PathBuf>,
+    pub checkpoint_every: usize,
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        Self {
+            epochs: 10,
+            batch_size: 32,
+            learning_rate: 0.001,
+            optimizer: Optimizer::adam(),
+            schedule: Schedule::Constant,
+            seed: 0,
+            checkpoint_dir: None,
+            checkpoint_every: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    /// Mean cross-entropy
+    pub loss: f32,
+    /// Share of examples whose label is the most likely move
+    pub accuracy: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpochReport {
+    /// Counting from one
+    pub epoch: usize,
+    pub learning_rate: f32,
+    /// Metrics of the batches while training
+    pub train: Metrics,
+    pub validation: Option<Metrics>,
+}
+
+/// Trains the policy head of a network, and the layers it shares with other
+/// heads. The policy head must end in softmax.
+pub struct Trainer {
+    network: Network,
+    head: usize,
+    config: TrainConfig,
+    rng: rand_pcg::Pcg64,
+    optimizer: OptimizerState,
+}
+
+impl Trainer {
+    pub fn new(network: Network, config: TrainConfig) -> Result<Self> {
+        let name = network.policy_head().context("The network has no policy")?;
+        let head = network.softmax_head(name)?;
+        ensure!(config.batch_size > 0, "The batch size must be positive");
+        let rng = rand_pcg::Pcg64::seed_from_u64(config.seed);
+        let optimizer = OptimizerState::new(config.optimizer);
+        Ok(Self { network, head, config, rng, optimizer })
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn into_network(self) -> Network {
+        self.network
+    }
+
+    /// Train for the configured epochs, reporting each and saving
+    /// checkpoints. Validation examples are evaluated after every epoch.
+    pub fn fit(
+        &mut self,
+        train: &Examples,
+        validation: Option<&Examples>,
+        mut on_epoch: impl FnMut(&EpochReport),
+    ) -> Result<Vec<EpochReport>> {
+        let mut reports = Vec::new();
+        for epoch in 0..self.config.epochs {
+            let learning_rate = self.config.schedule.learning_rate(self.config.learning_rate, epoch, self.config.epochs);
+            let train_metrics = self.train_epoch(train, learning_rate)?;
+            let validation = validation.filter(|v| !v.is_empty()).map(|v| self.evaluate(v)).transpose()?;
+            let report = EpochReport { epoch: epoch + 1, learning_rate, train: train_metrics, validation };
+            on_epoch(&report);
+            reports.push(report);
+
+            if let Some(dir) = &self.config.checkpoint_dir {
+                if (epoch + 1) % self.config.checkpoint_every.max(1) == 0 || epoch + 1 == self.config.epochs {
+                    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+                    self.network.save(&dir.join(format!("epoch-{}.json", epoch + 1)))?;
+                }
+            }
+        }
+        Ok(reports)
+    }
+
+    /// One pass over the examples in shuffled mini-batches
+    pub fn train_epoch(&mut self, examples: &Examples, learning_rate: f32) -> Result<Metrics> {
+        ensure!(!examples.is_empty(), "No examples to train on");
+        ensure!(
+            examples.shape() == self.network.input_shape(),
+            "Examples of shape {:?} don't fit the network's input {:?}",
+            examples.shape(),
+            self.network.input_shape()
+        );
+        let mut order: Vec<usize> = (0..examples.len()).collect();
+        order.shuffle(&mut self.rng);
+        let (mut loss, mut correct) = (0.0, 0);
+        for indices in order.chunks(self.config.batch_size) {
+            let (input, labels) = examples.batch(indices)?;
+            let (batch_loss, batch_correct, gradients) = self.gradients(&input, &labels)?;
+            self.update(gradients, learning_rate);
+            loss += batch_loss * indices.len() as f32;
+            correct += batch_correct;
+        }
+        Ok(Metrics { loss: loss / examples.len() as f32, accuracy: correct as f32 / examples.len() as f32 })
+    }
+
+    /// Loss and accuracy of the network on the examples, as in play
+    pub fn evaluate(&self, examples: &Examples) -> Result<Metrics> {
+        ensure!(!examples.is_empty(), "No examples to evaluate");
+        let name = &self.network.heads[self.head].0;
+        let indices: Vec<usize> = (0..examples.len()).collect();
+        let (mut loss, mut correct) = (0.0, 0);
+        for indices in indices.chunks(self.config.batch_size) {
+            let (input, labels) = examples.batch(indices)?;
+            let outputs = self.network.forward(&input)?;
+            for (n, label) in labels.iter().enumerate() {
+                let probabilities = outputs[name].item(n);
+                ensure!(*label < probabilities.len(), "Label {} is not a move output", label);
+                loss -= probabilities[*label].max(1e-12).ln();
+                if argmax(probabilities) == *label {
+                    correct += 1;
+                }
+            }
+        }
+        Ok(Metrics { loss: loss / examples.len() as f32, accuracy: correct as f32 / examples.len() as f32 })
+    }
+
+    /// Mean loss, number of correct predictions and the gradients of the
+    /// trained parameters for a batch
+    fn gradients(&mut self, input: &Tensor, labels: &[usize]) -> Result<(f32, usize, Vec<Vec<f32>>)> {
+        let pass = self.network.forward_train(input, &[self.head])?;
+        let logits = &pass.outputs[0];
+        let batch = labels.len();
+        let outputs = logits.data().len() / batch;
+        let (mut loss, mut correct) = (0.0, 0);
+        let mut grad = vec![0.0; logits.data().len()];
+        for (n, &label) in labels.iter().enumerate() {
+            ensure!(label < outputs, "Label {} is not a move output", label);
+            let item = logits.item(n);
+            let log_probabilities = log_softmax(item);
+            loss -= log_probabilities[label];
+            if argmax(item) == label {
+                correct += 1;
+            }
+            for (i, g) in grad[n * outputs..(n + 1) * outputs].iter_mut().enumerate() {
+                let target = if i == label { 1.0 } else { 0.0 };
+                *g = (log_probabilities[i].exp() - target) / batch as f32;
+            }
+        }
+
+        let grad = Tensor::new(logits.shape().to_vec(), grad)?;
+        let gradients = self.network.backward(pass, vec![grad])?;
+        Ok((loss / batch as f32, correct, gradients))
+    }
+
+    fn update(&mut self, gradients: Vec<Vec<f32>>, learning_rate: f32) {
+        self.optimizer.step(self.network.parameters_mut(&[self.head]), &gradients, learning_rate);
+    }
+}
+
+/// What a forward pass in training keeps for the backward pass
+pub(crate) struct ForwardPass {
+    heads: Vec<usize>,
+    trunk: Vec<Cache>,
+    head_caches: Vec<Vec<Cache>>,
+    /// Outputs of the heads, logits for the heads ending in softmax
+    pub(crate) outputs: Vec<Tensor>,
+}
+
+impl Network {
+    pub(crate) fn head_index(&self, name: &str) -> Option<usize> {
+        self.heads.iter().position(|(n, _)| n == name)
+    }
+
+    /// Index of the head, which must end in softmax
+    pub(crate) fn softmax_head(&self, name: &str) -> Result<usize> {
+        let head = self.head_index(name).with_context(|| format!("The network has no {} head", name))?;
+        ensure!(matches!(self.heads[head].1.last(), Some(Layer::Softmax)), "The {} head doesn't end in softmax", name);
+        Ok(head)
+    }
+
+    /// Forward pass in training through the trunk and the heads. A softmax
+    /// ending a head is left out, for losses to fold it in.
+    pub(crate) fn forward_train(&mut self, input: &Tensor, heads: &[usize]) -> Result<ForwardPass> {
+        let mut trunk = Vec::new();
+        let mut features = input.clone();
+        for layer in &mut self.trunk {
+            let (output, cache) = layer.forward_train(features)?;
+            trunk.push(cache);
+            features = output;
+        }
+        let (mut head_caches, mut outputs) = (Vec::new(), Vec::new());
+        for &head in heads {
+            let layers = &mut self.heads[head].1;
+            let trained = trained_len(layers);
+            let mut caches = Vec::new();
+            let mut x = features.clone();
+            for layer in &mut layers[..trained] {
+                let (output, cache) = layer.forward_train(x)?;
+                caches.push(cache);
+                x = output;
+            }
+            head_caches.push(caches);
+            outputs.push(x);
+        }
+        Ok(ForwardPass { heads: heads.to_vec(), trunk, head_caches, outputs })
+    }
+
+    /// Gradients of the parameters trained in the pass, in the order of
+    /// `parameters_mut`, given the gradients of the outputs of its heads
+    pub(crate) fn backward(&self, pass: ForwardPass, output_grads: Vec<Tensor>) -> Result<Vec<Vec<f32>>> {
+        ensure!(output_grads.len() == pass.heads.len(), "{} gradients for {} heads", output_grads.len(), pass.heads.len());
+        let mut features_grad: Option<Tensor> = None;
+        let mut head_gradients = Vec::new();
+        for ((&head, caches), mut grad) in pass.heads.iter().zip(&pass.head_caches).zip(output_grads) {
+            let mut gradients = Vec::new();
+            for (layer, cache) in self.heads[head].1.iter().zip(caches).rev() {
+                let (grad_input, layer_gradients) = layer.backward(cache, &grad)?;
+                gradients.push(layer_gradients);
+                grad = grad_input;
+            }
+            gradients.reverse();
+            head_gradients.extend(gradients.into_iter().flatten());
+            // Heads add up their gradients of the trunk's output
+            features_grad = Some(match features_grad {
+                Some(sum) => {
+                    let data = sum.data().iter().zip(grad.data()).map(|(a, b)| a + b).collect();
+                    Tensor::new(grad.shape().to_vec(), data)?
+                }
+                None => grad,
+            });
+        }
+
+        let mut grad = features_grad.context("No heads were trained")?;
+        let mut gradients = Vec::new();
+        for (layer, cache) in self.trunk.iter().zip(&pass.trunk).rev() {
+            let (grad_input, layer_gradients) = layer.backward(cache, &grad)?;
+            gradients.push(layer_gradients);
+            grad = grad_input;
+        }
+        gradients.reverse();
+        Ok(gradients.into_iter().flatten().chain(head_gradients).collect())
+    }
+
+    /// Parameters trained with the heads: those of the trunk and then of
+    /// each head
+    pub(crate) fn parameters_mut(&mut self, heads: &[usize]) -> Vec<&mut [f32]> {
+        let Network { trunk, heads: all_heads, .. } = self;
+        let mut parameters: Vec<&mut [f32]> = trunk.iter_mut().flat_map(|layer| layer.parameters_mut()).collect();
+        let mut selected: Vec<Option<&mut Vec<Layer>>> = all_heads.iter_mut().map(|(_, layers)| Some(layers)).collect();
+        for &head in heads {
+            let layers = selected[head].take().expect("Heads are trained once");
+            let trained = trained_len(layers);
+            parameters.extend(layers[..trained].iter_mut().flat_map(|layer| layer.parameters_mut()));
+        }
+        parameters
+    }
+}
+
+/// Number of layers of the head trained: all but a final softmax
+fn trained_len(layers: &[Layer]) -> usize {
+    match layers.last() {
+        Some(Layer::Softmax) => layers.len() - 1,
+        _ => layers.len(),
+    }
+}
+
+/// Updates parameters with their gradients
+pub(crate) struct OptimizerState {
+    optimizer: Optimizer,
+    /// First and second moments of each parameter array
+    moments: Vec<(Vec<f32>, Vec<f32>)>,
+    steps: i32,
+}
+
+impl OptimizerState {
+    pub(crate) fn new(optimizer: Optimizer) -> Self {
+        Self { optimizer, moments: Vec::new(), steps: 0 }
+    }
+
+    pub(crate) fn step(&mut self, parameters: Vec<&mut [f32]>, gradients: &[Vec<f32>], learning_rate: f32) {
+        if self.moments.is_empty() {
+            self.moments = gradients.iter().map(|g| (vec![0.0; g.len()], vec![0.0; g.len()])).collect();
+        }
+
+        self.steps += 1;
+        for ((parameter, gradient), (m, v)) in parameters.into_iter().zip(gradients).zip(&mut self.moments) {
+            match self.optimizer {
+                Optimizer::Sgd { momentum } => {
+                    for i in 0..parameter.len() {
+                        m[i] = momentum * m[i] + gradient[i];
+                        parameter[i] -= learning_rate * m[i];
+                    }
+                }
+                Optimizer::Adam { beta1, beta2 } => {
+                    let correction1 = 1.0 - beta1.powi(self.steps);
+                    let correction2 = 1.0 - beta2.powi(self.steps);
+                    for i in 0..parameter.len() {
+                        m[i] = beta1 * m[i] + (1.0 - beta1) * gradient[i];
+                        v[i] = beta2 * v[i] + (1.0 - beta2) * gradient[i] * gradient[i];
+                        parameter[i] -= learning_rate * (m[i] / correction1) / ((v[i] / correction2).sqrt() + 1e-8);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Logarithms of the softmax of the logits
+pub(crate) fn log_softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let log_sum = max + logits.iter().map(|x| (x - max).exp()).sum::<f32>().ln();
+    logits.iter().map(|x| x - log_sum).collect()
+}
+
+/// Index of the first largest value
+pub(crate) fn argmax(values: &[f32]) -> usize {
+    values.iter().enumerate().fold(0, |best, (i, v)| if *v > values[best] { i } else { best })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::OnePlaneEncoder;
+    use crate::game::go::Point;
+    use crate::game::GameState;
+    use rand::Rng;
+
+    /// Examples on 3x3 boards whose label is the point of the only stone
+    fn stone_examples() -> Examples {
+        let mut examples = Examples::new((1, 3, 3));
+        for i in 0..9 {
+            let mut features = [0.0; 9];
+            features[i] = 1.0;
+            examples.add(&features, i).unwrap();
+        }
+        examples
+    }
+
+    #[test]
+    fn test_gradients_match_numerical_ones() {
+        let conv = Layer::Conv2d { weight: Tensor::zeros(vec![2, 1, 3, 3]), bias: Some(vec![0.1, -0.1]), padding: 1 };
+        let norm = Layer::BatchNorm { gamma: vec![1.0, 0.5], beta: vec![0.0, 0.2], mean: vec![0.0; 2], var: vec![1.0; 2], eps: 1e-5 };
+        let dense = Layer::Dense { weight: Tensor::zeros(vec![9, 18]), bias: Some(vec![0.0; 9]) };
+        let heads = vec![("policy".to_string(), vec![dense, Layer::Softmax])];
+        let mut network = Network::new((1, 3, 3), vec![conv, norm, Layer::Tanh], heads).unwrap();
+
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(3);
+        let Network { trunk, heads, .. } = &mut network;
+        for layer in trunk.iter_mut().chain(heads[0].1.iter_mut()) {
+            for parameter in layer.parameters_mut() {
+                parameter.iter_mut().for_each(|p| *p += rng.gen_range(-0.5..0.5));
+            }
+        }
+        let input = Tensor::new(vec![3, 1, 3, 3], (0..27).map(|_| rng.gen_range(-1.0..1.0)).collect()).unwrap();
+        let labels = [4, 0, 8];
+
+        let mut trainer = Trainer::new(network, TrainConfig::default()).unwrap();
+        let (_, _, gradients) = trainer.gradients(&input, &labels).unwrap();
+        // Conv weight and bias, gamma and beta, dense weight and bias
+        assert_eq!(gradients.len(), 6);
+
+        // Smooth layers only, as finite differences across ReLU's kink are
+        // off
+        let eps = 1e-3;
+        for (array, index) in [(0, 4), (0, 13), (1, 1), (2, 0), (3, 1), (4, 30), (5, 8)] {
+            let mut loss_at = |delta: f32| {
+                let Network { trunk, heads, .. } = &mut trainer.network;
+                let mut parameters: Vec<&mut [f32]> =
+                    trunk.iter_mut().chain(heads[0].1.iter_mut()).flat_map(|l| l.parameters_mut()).collect();
+                parameters[array][index] += delta;
+                trainer.gradients(&input, &labels).unwrap().0
+            };
+            let plus = loss_at(eps);
+            let minus = loss_at(-2.0 * eps);
+            loss_at(eps);
+            let numerical = (plus - minus) / (2.0 * eps);
+            let analytical = gradients[array][index];
+            assert!(
+                (numerical - analytical).abs() < 2e-3 + 0.05 * analytical.abs(),
+                "Parameter {} of array {}: numerical {} analytical {}",
+                index,
+                array,
+                numerical,
+                analytical
+            );
+        }
+    }
+
+    #[test]
+    fn test_training_learns_examples() {
+        for optimizer in [Optimizer::adam(), Optimizer::Sgd { momentum: 0.9 }] {
+            let network = Network::random((1, 3, 3), &[4], false, 1).unwrap();
+            let config = TrainConfig { epochs: 60, batch_size: 4, learning_rate: 0.05, optimizer, ..TrainConfig::default() };
+            let mut trainer = Trainer::new(network, config).unwrap();
+            let examples = stone_examples();
+            let before = trainer.evaluate(&examples).unwrap();
+            let reports = trainer.fit(&examples, Some(&examples), |_| {}).unwrap();
+            let after = reports.last().unwrap().validation.unwrap();
+            assert!(after.loss < before.loss / 2.0, "{:?}: {:?} then {:?}", optimizer, before, after);
+            assert_eq!(after.accuracy, 1.0, "{:?}", optimizer);
+        }
+    }
+
+    #[test]
+    fn test_learning_rate_schedules() {
+        assert_eq!(Schedule::Constant.learning_rate(0.1, 5, 10), 0.1);
+        let step = Schedule::Step { every: 2, factor: 0.5 };
+        assert_eq!(step.learning_rate(0.1, 1, 10), 0.1);
+        assert_eq!(step.learning_rate(0.1, 5, 10), 0.025);
+        assert_eq!(Schedule::Cosine.learning_rate(0.1, 0, 10), 0.1);
+        assert!((Schedule::Cosine.learning_rate(0.1, 5, 10) - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_checkpoints_can_be_loaded() {
+        let dir = std::env::temp_dir().join(format!("bgai-train-test-{}", std::process::id()));
+        let network = Network::random((1, 3, 3), &[2], true, 2).unwrap();
+        let config = TrainConfig { epochs: 3, checkpoint_dir: Some(dir.clone()), checkpoint_every: 2, ..TrainConfig::default() };
+        let mut trainer = Trainer::new(network, config).unwrap();
+        trainer.fit(&stone_examples(), None, |_| {}).unwrap();
+        assert!(dir.join("epoch-2.json").exists() && dir.join("epoch-2.npz").exists());
+        assert!(!dir.join("epoch-1.json").exists());
+
+        let loaded = Network::load(&dir.join("epoch-3.json")).unwrap();
+        let game = GoState::new(3).apply_move(&Move::Play(Point::new(1, 2)));
+        let encoder = OnePlaneEncoder::new(3);
+        let expected = trainer.network().evaluate(&encoder, std::slice::from_ref(&game)).unwrap();
+        assert_eq!(loaded.evaluate(&encoder, &[game]).unwrap(), expected);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_examples_of_moves() {
+        let encoder = OnePlaneEncoder::new(3);
+        let mut examples = Examples::new(encoder.shape());
+        let game = GoState::new(3);
+        examples.add_move(&encoder, &game, &Move::Play(Point::new(2, 3))).unwrap();
+        assert!(examples.add_move(&encoder, &game, &Move::Pass).is_err());
+        assert!(examples.add(&[0.0; 4], 0).is_err());
+        assert_eq!(examples.len(), 1);
+        assert_eq!(examples.labels, vec![5]);
+    }
+}
@@ -0,0 +1,643 @@
+//! NumPy `.npy` files and uncompressed `.npz` archives of f32, u8 and i64
+//! arrays in C order. Writers stream rows of the first dimension, so arrays
+//! don't need to fit in memory, and readers read them back in chunks of rows.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::Crc;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Type of the array elements, stored little-endian
+pub trait Element: Copy + 'static {
+    /// NumPy type string
+    const DESCR: &'static str;
+    const SIZE: usize;
+
+    fn write_le(self, out: &mut Vec<u8>);
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_element {
+    ($type:ty, $descr:expr) => {
+        impl Element for $type {
+            const DESCR: &'static str = $descr;
+            const SIZE: usize = std::mem::size_of::<$type>();
+
+            fn write_le(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                let mut le = [0; std::mem::size_of::<$type>()];
+                le.copy_from_slice(bytes);
+                <$type>::from_le_bytes(le)
+            }
+        }
+    };
+}
+
+impl_element!(f32, "<f4");
+impl_element!(u8, "|u1");
+impl_element!(i64, "<i8");
+
+/// Whether the type string in a file is the element's. Byte order doesn't
+/// matter for single bytes.
+fn descr_matches<T: Element>(descr: &str) -> bool {
+    descr == T::DESCR || (T::SIZE == 1 && descr.get(1..) == T::DESCR.get(1..))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Array<T> {
+    pub shape: Vec<usize>,
+    /// Elements in C (row-major) order
+    pub data: Vec<T>,
+}
+
+impl<T> Array<T> {
+    pub fn new(shape: Vec<usize>, data: Vec<T>) -> Result<Self> {
+        let len: usize = shape.iter().product();
+        ensure!(len == data.len(), "Shape {:?} has {} elements, but there are {}", shape, len, data.len());
+        Ok(Self { shape, data })
+    }
+}
+
+/// Header of an `.npy` file, padded with spaces so that the data starts at
+/// a multiple of 64 bytes and the header is at least `min_len` long
+fn header_bytes(descr: &str, shape: &[usize], min_len: usize) -> Vec<u8> {
+    let dims: Vec<String> = shape.iter().map(|d| d.to_string()).collect();
+    let shape = match dims.len() {
+        1 => format!("({},)", dims[0]),
+        _ => format!("({})", dims.join(", ")),
+    };
+    let mut dict = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    let unpadded = MAGIC.len() + 4 + dict.len() + 1;
+    let len = (unpadded.div_ceil(64) * 64).max(min_len);
+    dict.extend(std::iter::repeat_n(' ', len - unpadded));
+    dict.push('\n');
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(dict.as_bytes());
+    bytes
+}
+
+struct Header {
+    descr: String,
+    fortran_order: bool,
+    shape: Vec<usize>,
+}
+
+fn read_header(reader: &mut impl Read) -> Result<Header> {
+    let mut start = [0; 8];
+    reader.read_exact(&mut start).context("Failed to read the .npy header")?;
+    ensure!(&start[..6] == MAGIC, "Not a .npy file");
+    let len = match start[6] {
+        1 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        version => bail!("Unsupported .npy version {}", version),
+    };
+    let mut text = vec![0; len];
+    reader.read_exact(&mut text)?;
+    parse_header(&String::from_utf8_lossy(&text))
+}
+
+/// Parse the Python dict literal of a header
+fn parse_header(text: &str) -> Result<Header> {
+    let value = |key: &str| -> Result<&str> {
+        let start = text
+            .find(&format!("'{}'", key))
+            .or_else(|| text.find(&format!("\"{}\"", key)))
+            .with_context(|| format!("No {} in .npy header {}", key, text))?;
+        let rest = &text[start + key.len() + 2..];
+        Ok(rest.trim_start().trim_start_matches(':').trim_start())
+    };
+
+    let descr = value("descr")?;
+    let quote = descr.chars().next().filter(|c| *c == '\'' || *c == '"').context("Bad descr in .npy header")?;
+    let descr = descr[1..].split(quote).next().unwrap_or_default().to_string();
+
+    let fortran_order = value("fortran_order")?.starts_with("True");
+
+    let shape = value("shape")?;
+    ensure!(shape.starts_with('('), "Bad shape in .npy header");
+    let end = shape.find(')').context("Bad shape in .npy header")?;
+    let shape = shape[1..end]
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| d.trim_end_matches('L').parse().with_context(|| format!("Bad dimension {} in .npy header", d)))
+        .collect::<Result<Vec<usize>>>()?;
+
+    Ok(Header { descr, fortran_order, shape })
+}
+
+/// Writes an `.npy` array row by row, i.e. by the first dimension. The
+/// header is written again with the final number of rows on `finish`.
+pub struct NpyWriter<W: Write + Seek, T: Element> {
+    writer: W,
+    start: u64,
+    header_len: usize,
+    row_shape: Vec<usize>,
+    row_len: usize,
+    rows: usize,
+    bytes: u64,
+    crc: Crc,
+    buffer: Vec<u8>,
+    element: PhantomData<T>,
+}
+
+impl<T: Element> NpyWriter<BufWriter<File>, T> {
+    /// Create the file for an array whose rows have the shape
+    pub fn create(path: &Path, row_shape: &[usize]) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Self::new(BufWriter::new(file), row_shape)
+    }
+}
+
+impl<W: Write + Seek, T: Element> NpyWriter<W, T> {
+    /// Start an array at the writer's current position
+    pub fn new(mut writer: W, row_shape: &[usize]) -> Result<Self> {
+        let row_len = row_shape.iter().product();
+        ensure!(row_len > 0, "Rows of shape {:?} are empty", row_shape);
+        let start = writer.stream_position()?;
+        // Leave room for any number of rows
+        let mut shape = vec![usize::MAX];
+        shape.extend_from_slice(row_shape);
+        let header = header_bytes(T::DESCR, &shape, 0);
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            start,
+            header_len: header.len(),
+            row_shape: row_shape.to_vec(),
+            row_len,
+            rows: 0,
+            bytes: 0,
+            crc: Crc::new(),
+            buffer: Vec::new(),
+            element: PhantomData,
+        })
+    }
+
+    /// Append whole rows
+    pub fn write(&mut self, values: &[T]) -> Result<()> {
+        ensure!(
+            values.len().is_multiple_of(self.row_len),
+            "{} values are not whole rows of shape {:?}",
+            values.len(),
+            self.row_shape
+        );
+        self.buffer.clear();
+        for value in values {
+            value.write_le(&mut self.buffer);
+        }
+        self.writer.write_all(&self.buffer)?;
+        self.crc.update(&self.buffer);
+        self.bytes += self.buffer.len() as u64;
+        self.rows += values.len() / self.row_len;
+        Ok(())
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn finish(self) -> Result<W> {
+        Ok(self.finish_entry()?.0)
+    }
+
+    /// Complete the header, and return the writer at the end of the array
+    /// with the CRC-32 and length of the whole file
+    fn finish_entry(mut self) -> Result<(W, Crc, u64)> {
+        let mut shape = vec![self.rows];
+        shape.extend_from_slice(&self.row_shape);
+        let header = header_bytes(T::DESCR, &shape, self.header_len);
+        self.writer.seek(SeekFrom::Start(self.start))?;
+        self.writer.write_all(&header)?;
+        let len = header.len() as u64 + self.bytes;
+        self.writer.seek(SeekFrom::Start(self.start + len))?;
+        self.writer.flush()?;
+
+        let mut crc = Crc::new();
+        crc.update(&header);
+        crc.combine(&self.crc);
+        Ok((self.writer, crc, len))
+    }
+}
+
+/// Reads an `.npy` array in chunks of rows
+pub struct NpyReader<R: Read, T: Element> {
+    reader: R,
+    shape: Vec<usize>,
+    row_len: usize,
+    remaining_rows: usize,
+    buffer: Vec<u8>,
+    element: PhantomData<T>,
+}
+
+impl<T: Element> NpyReader<BufReader<File>, T> {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("Failed to read {}", path.display()))
+    }
+}
+
+impl<R: Read, T: Element> NpyReader<R, T> {
+    /// Read the header. The array must be of type T in C order.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let header = read_header(&mut reader)?;
+        ensure!(descr_matches::<T>(&header.descr), "Array of {} is not of {}", header.descr, T::DESCR);
+        ensure!(!header.fortran_order, "Arrays in Fortran order are not supported");
+        // A scalar is a single row
+        let rows = header.shape.first().copied().unwrap_or(1);
+        let row_len = header.shape.iter().skip(1).product();
+        Ok(Self {
+            reader,
+            shape: header.shape,
+            row_len,
+            remaining_rows: if row_len == 0 { 0 } else { rows },
+            buffer: Vec::new(),
+            element: PhantomData,
+        })
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn remaining_rows(&self) -> usize {
+        self.remaining_rows
+    }
+
+    /// The elements of the next rows, at most `max_rows` of them. Empty at
+    /// the end of the array.
+    pub fn read_rows(&mut self, max_rows: usize) -> Result<Vec<T>> {
+        let rows = max_rows.min(self.remaining_rows);
+        self.buffer.resize(rows * self.row_len * T::SIZE, 0);
+        self.reader.read_exact(&mut self.buffer).context("The .npy array ended early")?;
+        self.remaining_rows -= rows;
+        Ok(self.buffer.chunks_exact(T::SIZE).map(T::read_le).collect())
+    }
+
+    /// The rest of the array
+    pub fn read_all(mut self) -> Result<Array<T>> {
+        let data = self.read_rows(self.remaining_rows)?;
+        let mut shape = self.shape;
+        if let Some(rows) = shape.first_mut() {
+            *rows = data.len().checked_div(self.row_len).unwrap_or(*rows);
+        }
+        Array::new(shape, data)
+    }
+}
+
+pub fn write_npy<T: Element>(path: &Path, array: &Array<T>) -> Result<()> {
+    ensure!(!array.shape.is_empty(), "Arrays need at least one dimension");
+    let mut writer = NpyWriter::create(path, &array.shape[1..])?;
+    writer.write(&array.data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn read_npy<T: Element>(path: &Path) -> Result<Array<T>> {
+    NpyReader::open(path)?.read_all()
+}
+
+/// File in a zip archive
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+/// Version 2.0 of the zip format, and DOS date 1980-01-01
+const ZIP_VERSION: u16 = 20;
+const ZIP_DATE: u16 = (1 << 5) | 1;
+
+fn put_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn get_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn get_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// Fields common to local and central headers, from the version needed on
+fn put_entry_fields(bytes: &mut Vec<u8>, name: &str, crc: u32, size: u32) {
+    put_u16(bytes, ZIP_VERSION);
+    put_u16(bytes, 0); // flags
+    put_u16(bytes, 0); // stored
+    put_u16(bytes, 0); // time
+    put_u16(bytes, ZIP_DATE);
+    put_u32(bytes, crc);
+    put_u32(bytes, size); // compressed
+    put_u32(bytes, size);
+    put_u16(bytes, name.len() as u16);
+    put_u16(bytes, 0); // extra field
+}
+
+fn local_header(name: &str, crc: u32, size: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    put_u32(&mut bytes, LOCAL_HEADER);
+    put_entry_fields(&mut bytes, name, crc, size);
+    bytes.extend_from_slice(name.as_bytes());
+    bytes
+}
+
+/// Writes an `.npz` archive: a zip file of stored, uncompressed `.npy`
+/// files, which `numpy.load` reads by the names without `.npy`. Arrays are
+/// written one after another, and the archive must stay under 4 GiB.
+pub struct NpzWriter<W: Write + Seek> {
+    writer: W,
+    entries: Vec<ZipEntry>,
+}
+
+impl NpzWriter<BufWriter<File>> {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write + Seek> NpzWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, entries: Vec::new() }
+    }
+
+    pub fn add<T: Element>(&mut self, name: &str, array: &Array<T>) -> Result<()> {
+        ensure!(!array.shape.is_empty(), "Arrays need at least one dimension");
+        let mut writer = self.array_writer(name, &array.shape[1..])?;
+        writer.write(&array.data)?;
+        writer.finish()
+    }
+
+    /// Start streaming an array whose rows have the shape. It must be
+    /// finished before the next one is started.
+    pub fn array_writer<T: Element>(&mut self, name: &str, row_shape: &[usize]) -> Result<NpzArrayWriter<'_, W, T>> {
+        let name = format!("{}.npy", name);
+        ensure!(!self.entries.iter().any(|e| e.name == name), "Array {} is already in the archive", name);
+        let offset = self.writer.stream_position()?;
+        self.writer.write_all(&local_header(&name, 0, 0))?;
+        let npy = NpyWriter::new(&mut self.writer, row_shape)?;
+        Ok(NpzArrayWriter { npy, entries: &mut self.entries, name, offset })
+    }
+
+    /// Write the zip directory
+    pub fn finish(mut self) -> Result<W> {
+        ensure!(self.entries.len() <= u16::MAX as usize, "Too many arrays for an .npz archive");
+        let mut bytes = Vec::new();
+        for entry in &self.entries {
+            put_u32(&mut bytes, CENTRAL_HEADER);
+            put_u16(&mut bytes, ZIP_VERSION); // made by
+            put_entry_fields(&mut bytes, &entry.name, entry.crc, entry.size);
+            put_u16(&mut bytes, 0); // comment
+            put_u16(&mut bytes, 0); // disk
+            put_u16(&mut bytes, 0); // internal attributes
+            put_u32(&mut bytes, 0); // external attributes
+            put_u32(&mut bytes, entry.offset);
+            bytes.extend_from_slice(entry.name.as_bytes());
+        }
+        let offset = self.writer.stream_position()?;
+        let size = bytes.len() as u32;
+        ensure!(offset + size as u64 <= u32::MAX as u64, "The .npz archive is over 4 GiB");
+
+        put_u32(&mut bytes, END_OF_CENTRAL_DIRECTORY);
+        put_u16(&mut bytes, 0); // disk
+        put_u16(&mut bytes, 0); // disk of the directory
+        put_u16(&mut bytes, self.entries.len() as u16);
+        put_u16(&mut bytes, self.entries.len() as u16);
+        put_u32(&mut bytes, size);
+        put_u32(&mut bytes, offset as u32);
+        put_u16(&mut bytes, 0); // comment
+        self.writer.write_all(&bytes)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Streams one array into an `.npz` archive
+pub struct NpzArrayWriter<'a, W: Write + Seek, T: Element> {
+    npy: NpyWriter<&'a mut W, T>,
+    entries: &'a mut Vec<ZipEntry>,
+    name: String,
+    offset: u64,
+}
+
+impl<W: Write + Seek, T: Element> NpzArrayWriter<'_, W, T> {
+    /// Append whole rows
+    pub fn write(&mut self, values: &[T]) -> Result<()> {
+        self.npy.write(values)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.npy.rows()
+    }
+
+    /// Complete the array's entry in the archive
+    pub fn finish(self) -> Result<()> {
+        let (writer, crc, size) = self.npy.finish_entry()?;
+        let end = writer.stream_position()?;
+        ensure!(end <= u32::MAX as u64, "The .npz archive is over 4 GiB");
+        writer.seek(SeekFrom::Start(self.offset))?;
+        writer.write_all(&local_header(&self.name, crc.sum(), size as u32))?;
+        writer.seek(SeekFrom::Start(end))?;
+        self.entries.push(ZipEntry { name: self.name, crc: crc.sum(), size: size as u32, offset: self.offset as u32 });
+        Ok(())
+    }
+}
+
+/// Reads arrays of an uncompressed `.npz` archive
+pub struct NpzReader<R: Read + Seek> {
+    reader: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl NpzReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("Failed to read {}", path.display()))
+    }
+}
+
+impl<R: Read + Seek> NpzReader<R> {
+    /// Read the zip directory
+    pub fn new(mut reader: R) -> Result<Self> {
+        // The end of central directory record is followed by a comment of
+        // at most 64 KiB
+        let len = reader.seek(SeekFrom::End(0))?;
+        let tail_len = len.min(22 + u16::MAX as u64);
+        reader.seek(SeekFrom::Start(len - tail_len))?;
+        let mut tail = vec![0; tail_len as usize];
+        reader.read_exact(&mut tail)?;
+        let end = (0..tail.len().saturating_sub(21))
+            .rev()
+            .find(|at| get_u32(&tail, *at) == END_OF_CENTRAL_DIRECTORY)
+            .context("Not a zip archive")?;
+        let count = get_u16(&tail, end + 10) as usize;
+        let size = get_u32(&tail, end + 12);
+        let offset = get_u32(&tail, end + 16);
+        ensure!(offset != u32::MAX, "Zip64 archives are not supported");
+
+        reader.seek(SeekFrom::Start(offset as u64))?;
+        let mut directory = vec![0; size as usize];
+        reader.read_exact(&mut directory).context("Truncated zip directory")?;
+        let mut entries = Vec::with_capacity(count);
+        let mut at = 0;
+        for _ in 0..count {
+            ensure!(at + 46 <= directory.len() && get_u32(&directory, at) == CENTRAL_HEADER, "Bad zip directory");
+            let name_len = get_u16(&directory, at + 28) as usize;
+            let extra_len = get_u16(&directory, at + 30) as usize;
+            let comment_len = get_u16(&directory, at + 32) as usize;
+            ensure!(at + 46 + name_len <= directory.len(), "Bad zip directory");
+            let name = String::from_utf8_lossy(&directory[at + 46..at + 46 + name_len]).to_string();
+            ensure!(get_u16(&directory, at + 10) == 0, "{} is compressed, which is not supported", name);
+            let size = get_u32(&directory, at + 20);
+            ensure!(size != u32::MAX, "Zip64 archives are not supported");
+            entries.push(ZipEntry { name, crc: get_u32(&directory, at + 16), size, offset: get_u32(&directory, at + 42) });
+            at += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(Self { reader, entries })
+    }
+
+    /// Names of the arrays, without `.npy`
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.name.strip_suffix(".npy").unwrap_or(&e.name)).collect()
+    }
+
+    /// Start reading the array by its name, with or without `.npy`
+    pub fn array_reader<T: Element>(&mut self, name: &str) -> Result<NpyReader<io::Take<&mut R>, T>> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.name == name || e.name.strip_suffix(".npy") == Some(name))
+            .with_context(|| format!("No array {} in the archive", name))?;
+        self.reader.seek(SeekFrom::Start(entry.offset as u64))?;
+        let mut header = [0; 30];
+        self.reader.read_exact(&mut header)?;
+        ensure!(get_u32(&header, 0) == LOCAL_HEADER, "Bad zip entry for {}", name);
+        let skip = get_u16(&header, 26) as i64 + get_u16(&header, 28) as i64;
+        self.reader.seek(SeekFrom::Current(skip))?;
+        let size = entry.size as u64;
+        NpyReader::new((&mut self.reader).take(size)).with_context(|| format!("Failed to read array {}", name))
+    }
+
+    pub fn read<T: Element>(&mut self, name: &str) -> Result<Array<T>> {
+        self.array_reader(name)?.read_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn round_trip<T: Element + PartialEq + std::fmt::Debug>(array: &Array<T>) -> Array<T> {
+        let mut writer = NpyWriter::new(Cursor::new(Vec::new()), &array.shape[1..]).unwrap();
+        writer.write(&array.data).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        NpyReader::new(Cursor::new(bytes)).unwrap().read_all().unwrap()
+    }
+
+    #[test]
+    fn test_npy_round_trip() {
+        let floats = Array::new(vec![2, 3], vec![0.5, -1.0, 2.0, 3.5, f32::MAX, 0.0]).unwrap();
+        assert_eq!(round_trip(&floats), floats);
+        let bytes = Array::new(vec![4], vec![0u8, 1, 254, 255]).unwrap();
+        assert_eq!(round_trip(&bytes), bytes);
+        let longs = Array::new(vec![1, 2, 1], vec![i64::MIN, 361]).unwrap();
+        assert_eq!(round_trip(&longs), longs);
+        assert!(Array::new(vec![2, 2], vec![1u8]).is_err());
+    }
+
+    #[test]
+    fn test_header_format() {
+        let mut writer = NpyWriter::<_, i64>::new(Cursor::new(Vec::new()), &[]).unwrap();
+        writer.write(&[1, 2, 3]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(bytes.len(), 10 + header_len + 3 * 8);
+        let header = String::from_utf8_lossy(&bytes[10..10 + header_len]);
+        assert!(header.starts_with("{'descr': '<i8', 'fortran_order': False, 'shape': (3,), }"));
+        assert!(header.ends_with(" \n"));
+    }
+
+    #[test]
+    fn test_streaming_in_chunks() {
+        let mut writer = NpyWriter::<_, f32>::new(Cursor::new(Vec::new()), &[2, 2]).unwrap();
+        for row in 0..5 {
+            writer.write(&[row as f32; 4]).unwrap();
+        }
+        assert!(writer.write(&[1.0; 3]).is_err());
+        assert_eq!(writer.rows(), 5);
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut reader = NpyReader::<_, f32>::new(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(reader.shape(), &[5, 2, 2]);
+        assert_eq!(reader.read_rows(2).unwrap(), vec![0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(reader.read_rows(2).unwrap().len(), 8);
+        assert_eq!(reader.read_rows(2).unwrap(), vec![4.0; 4]);
+        assert!(reader.read_rows(2).unwrap().is_empty());
+
+        // Elements of the wrong type aren't read
+        assert!(NpyReader::<_, i64>::new(Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_parse_numpy_header() {
+        let header = parse_header("{'descr': '<f4', 'fortran_order': False, 'shape': (10, 7, 19, 19), }").unwrap();
+        assert_eq!(header.descr, "<f4");
+        assert!(!header.fortran_order);
+        assert_eq!(header.shape, vec![10, 7, 19, 19]);
+        let header = parse_header("{\"shape\": (), \"fortran_order\": True, \"descr\": \"|u1\"}").unwrap();
+        assert!(header.shape.is_empty());
+        assert!(header.fortran_order);
+        assert!(descr_matches::<u8>(&header.descr) && descr_matches::<u8>("<u1"));
+    }
+
+    #[test]
+    fn test_npz_round_trip() {
+        let features = Array::new(vec![3, 2], vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        let mut npz = NpzWriter::new(Cursor::new(Vec::new()));
+        npz.add("features", &features).unwrap();
+        let mut labels = npz.array_writer::<i64>("labels", &[]).unwrap();
+        labels.write(&[7]).unwrap();
+        labels.write(&[8, 9]).unwrap();
+        labels.finish().unwrap();
+        assert!(npz.add("features", &features).is_err());
+        let bytes = npz.finish().unwrap().into_inner();
+
+        let mut npz = NpzReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(npz.names(), vec!["features", "labels"]);
+        assert_eq!(npz.read::<i64>("labels").unwrap(), Array::new(vec![3], vec![7, 8, 9]).unwrap());
+        assert_eq!(npz.read::<f32>("features.npy").unwrap(), features);
+        assert!(npz.read::<f32>("labels").is_err());
+        assert!(npz.read::<f32>("missing").is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Playing games between agents
+
+use crate::agent::Agent;
+use crate::game::{GameOutcome, GameState, Outcome};
+
+/// A finished game
+#[derive(Clone, Debug)]
+pub struct GameRecord<S: GameState> {
+    pub moves: Vec<S::Move>,
+    pub final_state: S,
+}
+
+impl<S: GameOutcome> GameRecord<S> {
+    /// Index of the winning agent, 0 for the one that moved first, or None
+    /// for a draw
+    pub fn winner(&self) -> Option<usize> {
+        // The agent to move in the final state
+        let next = self.moves.len() % 2;
+        match self.final_state.outcome() {
+            Outcome::Win => Some(next),
+            Outcome::Loss => Some(1 - next),
+            Outcome::Draw => None,
+        }
+    }
+}
+
+/// Play a game from the state, the agents taking turns starting from the
+/// first one, until the game is over or `max_moves` moves have been played.
+/// The agents are notified of the start and end of the game and of every
+/// move, and `on_move` is called with the state before each move.
+pub fn play_game<S, F>(
+    state: S,
+    agents: &mut [&mut dyn Agent<S>; 2],
+    max_moves: Option<usize>,
+    mut on_move: F,
+) -> GameRecord<S>
+where
+    S: GameState,
+    F: FnMut(&S, &S::Move),
+{
+    for agent in agents.iter_mut() {
+        agent.game_started(&state);
+    }
+
+    let mut state = state;
+    let mut moves = Vec::new();
+    while !state.is_over() && max_moves.is_none_or(|max| moves.len() < max) {
+        let the_move = agents[moves.len() % 2].select_move(&state);
+        on_move(&state, &the_move);
+        for agent in agents.iter_mut() {
+            agent.move_played(&state, &the_move);
+        }
+        state = state.apply_move(&the_move);
+        moves.push(the_move);
+    }
+
+    for agent in agents.iter_mut() {
+        agent.game_ended(&state);
+    }
+    GameRecord { moves, final_state: state }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::MinimaxBot;
+    use crate::game::one_two_three::*;
+
+    #[test]
+    fn test_perfect_first_player_wins_one_two_three() {
+        let mut first = MinimaxBot::new(5, score_difference);
+        let mut second = MinimaxBot::new(5, score_difference);
+        let mut moves_seen = 0;
+        let record = play_game(OneTwoThreeState::new(), &mut [&mut first, &mut second], None, |_, _| moves_seen += 1);
+
+        assert_eq!(record.moves, vec![Move::Three; 5]);
+        assert_eq!(moves_seen, 5);
+        assert_eq!(record.winner(), Some(0));
+    }
+
+    #[test]
+    fn test_game_stops_at_max_moves() {
+        let mut first = MinimaxBot::new(1, score_difference);
+        let mut second = MinimaxBot::new(1, score_difference);
+        let record = play_game(OneTwoThreeState::new(), &mut [&mut first, &mut second], Some(2), |_, _| {});
+        assert_eq!(record.moves.len(), 2);
+        assert_eq!(record.winner(), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! Actor-critic training from the experience of games. The value head, the
+//! critic, learns to predict the reward of the game, and the policy head,
+//! the actor, is trained with policy gradients on the advantage: the reward
+//! less the predicted value, so moves are credited for doing better than
+//! expected in their position.
+
+use anyhow::{ensure, Context, Result};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::nn::train::{Optimizer, OptimizerState};
+use crate::nn::{Network, Tensor, POLICY_HEAD, VALUE_HEAD};
+use crate::rl::reinforce::policy_gradient;
+use crate::rl::ExperienceBuffer;
+
+#[derive(Clone, Debug)]
+pub struct ActorCriticConfig {
+    /// Passes over the experience
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    pub optimizer: Optimizer,
+    /// Weight of the value's squared error in the loss
+    pub value_weight: f32,
+    /// Weight of the entropy bonus in the loss
+    pub entropy_weight: f32,
+    /// Seed for shuffling the experience
+    pub seed: u64,
+}
+
+impl Default for ActorCriticConfig {
+    fn default() -> Self {
+        Self {
+            epochs: 1,
+            batch_size: 32,
+            learning_rate: 0.01,
+            optimizer: Optimizer::Sgd { momentum: 0.0 },
+            value_weight: 0.5,
+            entropy_weight: 0.01,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ActorCriticMetrics {
+    /// Mean of the advantage times the negative log probability of the move
+    pub policy_loss: f32,
+    /// Mean squared error of the value
+    pub value_loss: f32,
+    /// Mean entropy of the policy in nats
+    pub entropy: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActorCriticReport {
+    /// Counting from one
+    pub epoch: usize,
+    pub train: ActorCriticMetrics,
+}
+
+/// Trains the policy and value heads of a network and their shared trunk on
+/// the experience of games. The policy head must end in softmax.
+pub struct ActorCriticTrainer {
+    network: Network,
+    /// Indices of the policy and value heads
+    heads: [usize; 2],
+    /// Number of move outputs of the policy
+    moves: usize,
+    config: ActorCriticConfig,
+    rng: rand_pcg::Pcg64,
+    optimizer: OptimizerState,
+}
+
+impl ActorCriticTrainer {
+    pub fn new(network: Network, config: ActorCriticConfig) -> Result<Self> {
+        let policy = network.softmax_head(POLICY_HEAD)?;
+        let value = network.head_index(VALUE_HEAD).with_context(|| format!("The network has no {} head", VALUE_HEAD))?;
+        ensure!(config.batch_size > 0, "The batch size must be positive");
+        ensure!(config.entropy_weight >= 0.0, "The entropy weight can't be negative");
+        let (planes, rows, cols) = network.input_shape();
+        let outputs = network.forward(&Tensor::zeros(vec![1, planes, rows, cols]))?;
+        ensure!(outputs[VALUE_HEAD].data().len() == 1, "The {} head must have one output", VALUE_HEAD);
+        let moves = outputs[POLICY_HEAD].data().len();
+        let rng = rand_pcg::Pcg64::seed_from_u64(config.seed);
+        let optimizer = OptimizerState::new(config.optimizer);
+        Ok(Self { network, heads: [policy, value], moves, config, rng, optimizer })
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn into_network(self) -> Network {
+        self.network
+    }
+
+    /// Train for the configured epochs, reporting each
+    pub fn fit(
+        &mut self,
+        experience: &ExperienceBuffer,
+        mut on_epoch: impl FnMut(&ActorCriticReport),
+    ) -> Result<Vec<ActorCriticReport>> {
+        let mut reports = Vec::new();
+        for epoch in 0..self.config.epochs {
+            let report = ActorCriticReport { epoch: epoch + 1, train: self.train_epoch(experience)? };
+            on_epoch(&report);
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    /// One pass over the experience in shuffled mini-batches. Decisions the
+    /// policy has no output for, such as passes, are skipped.
+    pub fn train_epoch(&mut self, experience: &ExperienceBuffer) -> Result<ActorCriticMetrics> {
+        ensure!(
+            experience.shape() == self.network.input_shape(),
+            "Experience of shape {:?} doesn't fit the network's input {:?}",
+            experience.shape(),
+            self.network.input_shape()
+        );
+        let mut order: Vec<usize> = (0..experience.len()).filter(|i| experience.get(*i).action < self.moves).collect();
+        ensure!(!order.is_empty(), "No moves of the policy to train on");
+
+        order.shuffle(&mut self.rng);
+        let mut totals = ActorCriticMetrics { policy_loss: 0.0, value_loss: 0.0, entropy: 0.0 };
+        for indices in order.chunks(self.config.batch_size) {
+            let input = experience.batch(indices)?;
+            let pass = self.network.forward_train(&input, &self.heads)?;
+            let (logits, values) = (&pass.outputs[0], &pass.outputs[1]);
+            let batch = indices.len() as f32;
+            let mut policy_grad = vec![0.0; logits.data().len()];
+            let mut value_grad = vec![0.0; values.data().len()];
+            for (n, i) in indices.iter().enumerate() {
+                let experience = experience.get(*i);
+                let value = values.data()[n];
+                // The advantage is a target, with no gradient of its own
+                let advantage = experience.reward - value;
+                let item_grad = &mut policy_grad[n * self.moves..(n + 1) * self.moves];
+                let (log_probability, entropy) =
+                    policy_gradient(logits.item(n), experience.action, advantage, self.config.entropy_weight, item_grad);
+                item_grad.iter_mut().for_each(|g| *g /= batch);
+                value_grad[n] = self.config.value_weight * 2.0 * (value - experience.reward) / batch;
+
+                totals.policy_loss -= advantage * log_probability;
+                totals.value_loss += advantage * advantage;
+                totals.entropy += entropy;
+            }
+            let grads = vec![
+                Tensor::new(logits.shape().to_vec(), policy_grad)?,
+                Tensor::new(values.shape().to_vec(), value_grad)?,
+            ];
+            let gradients = self.network.backward(pass, grads)?;
+            self.optimizer.step(self.network.parameters_mut(&self.heads), &gradients, self.config.learning_rate);
+        }
+        let count = order.len() as f32;
+        Ok(ActorCriticMetrics {
+            policy_loss: totals.policy_loss / count,
+            value_loss: totals.value_loss / count,
+            entropy: totals.entropy / count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::{Encoder, OnePlaneEncoder};
+    use crate::game::go::Point;
+
+    /// Experience of two positions: after a black stone in the corner white
+    /// won with the center, and after one in the center black won
+    /// whatever white played
+    fn experience() -> ExperienceBuffer {
+        let encoder = OnePlaneEncoder::new(3);
+        let mut buffer = ExperienceBuffer::new(encoder.shape());
+        let corner = [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let center = [0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0];
+        for episode in 0..8 {
+            buffer.push(&corner, encoder.encode_point(&Point::new(2, 2)), 1.0, episode).unwrap();
+            buffer.push(&center, encoder.encode_point(&Point::new(1, 1)), -1.0, episode).unwrap();
+        }
+        buffer
+    }
+
+    fn outputs(network: &Network, state: &[f32]) -> (Vec<f32>, f32) {
+        let outputs = network.forward(&Tensor::new(vec![1, 1, 3, 3], state.to_vec()).unwrap()).unwrap();
+        (outputs[POLICY_HEAD].data().to_vec(), outputs[VALUE_HEAD].data()[0])
+    }
+
+    #[test]
+    fn test_critic_learns_values_and_actor_the_better_move() {
+        let experience = experience();
+        let network = Network::random((1, 3, 3), &[4], true, 1).unwrap();
+        let corner = experience.get(0).state.to_vec();
+        let (policy_before, _) = outputs(&network, &corner);
+
+        let config = ActorCriticConfig { epochs: 100, batch_size: 4, learning_rate: 0.05, ..ActorCriticConfig::default() };
+        let mut trainer = ActorCriticTrainer::new(network, config).unwrap();
+        let reports = trainer.fit(&experience, |_| {}).unwrap();
+        let (first, last) = (reports[0].train, reports.last().unwrap().train);
+        assert!(last.value_loss < first.value_loss / 4.0, "{:?} then {:?}", first, last);
+
+        let (policy, corner_value) = outputs(trainer.network(), &corner);
+        let (_, center_value) = outputs(trainer.network(), experience.get(1).state);
+        assert!(corner_value > 0.5 && center_value < -0.5, "{} {}", corner_value, center_value);
+        assert!(policy[4] > policy_before[4], "{:?} then {:?}", policy_before, policy);
+    }
+
+    #[test]
+    fn test_network_needs_policy_and_value_heads() {
+        let policy_only = Network::random((1, 3, 3), &[2], false, 1).unwrap();
+        assert!(ActorCriticTrainer::new(policy_only, ActorCriticConfig::default()).is_err());
+        let q = Network::random_q((1, 3, 3), &[2], 1).unwrap();
+        assert!(ActorCriticTrainer::new(q, ActorCriticConfig::default()).is_err());
+    }
+}
@@ -0,0 +1,440 @@
+//! Experience of agents in games: the positions where an agent moved,
+//! encoded for it, the moves it chose and the reward it got at the end of
+//! the game.
+//!
+//! Buffers are saved as uncompressed `.npz` archives of four arrays with one
+//! entry per decision:
+//!
+//! - `states`: f32 of shape (decisions, planes, rows, columns)
+//! - `actions`: i64 of shape (decisions,), the index of the point played as
+//!   given by the encoder, or the number of points for a pass
+//! - `rewards`: f32 of shape (decisions,), 1 if the agent won the game, -1
+//!   if it lost and 0 for a draw or a game stopped before its end
+//! - `episodes`: i64 of shape (decisions,), the game of the decision,
+//!   numbered from zero in the buffer
+//!
+//! Resignations are not recorded, as they only end the game.
+
+use std::convert::TryFrom;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use rand::seq::{index, SliceRandom};
+use rand::Rng;
+
+use crate::agent::Agent;
+use crate::game::go::encoders::Encoder;
+use crate::game::go::{Color, GoState, Move};
+use crate::game::{GameOutcome, GameState, Outcome};
+use crate::nn::Tensor;
+use crate::npy::{Array, NpzReader, NpzWriter};
+
+/// Index of the move among the actions of the encoder's board, or None for
+/// a resignation
+pub fn action_index(encoder: &dyn Encoder, the_move: &Move) -> Option<usize> {
+    match the_move {
+        Move::Play(point) => Some(encoder.encode_point(point)),
+        Move::Pass => Some(encoder.num_points()),
+        Move::Resign => None,
+    }
+}
+
+/// Move of the action index, the inverse of `action_index`
+pub fn action_move(encoder: &dyn Encoder, index: usize) -> Move {
+    if index < encoder.num_points() {
+        Move::Play(encoder.decode_point_index(index))
+    } else {
+        Move::Pass
+    }
+}
+
+/// Reward of the player of the color at the end of the game: 1 for a win,
+/// -1 for a loss and 0 for a draw
+pub fn final_reward(final_state: &GoState, color: Color) -> f32 {
+    let reward = match final_state.outcome() {
+        Outcome::Win => 1.0,
+        Outcome::Loss => -1.0,
+        Outcome::Draw => 0.0,
+    };
+    // The outcome is for the player to move
+    if final_state.next_player.color == color {
+        reward
+    } else {
+        -reward
+    }
+}
+
+/// One decision of a buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Experience<'a> {
+    pub state: &'a [f32],
+    pub action: usize,
+    pub reward: f32,
+    pub episode: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExperienceBuffer {
+    shape: (usize, usize, usize),
+    states: Vec<f32>,
+    actions: Vec<usize>,
+    rewards: Vec<f32>,
+    episodes: Vec<usize>,
+}
+
+impl ExperienceBuffer {
+    /// Empty buffer for states of the (planes, rows, columns) shape
+    pub fn new(shape: (usize, usize, usize)) -> Self {
+        Self { shape, states: Vec::new(), actions: Vec::new(), rewards: Vec::new(), episodes: Vec::new() }
+    }
+
+    pub fn shape(&self) -> (usize, usize, usize) {
+        self.shape
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Number of episodes, counting from zero to the last one recorded
+    pub fn num_episodes(&self) -> usize {
+        self.episodes.iter().max().map_or(0, |e| e + 1)
+    }
+
+    fn state_len(&self) -> usize {
+        self.shape.0 * self.shape.1 * self.shape.2
+    }
+
+    pub fn push(&mut self, state: &[f32], action: usize, reward: f32, episode: usize) -> Result<()> {
+        ensure!(state.len() == self.state_len(), "State of {} values doesn't have shape {:?}", state.len(), self.shape);
+        self.states.extend_from_slice(state);
+        self.actions.push(action);
+        self.rewards.push(reward);
+        self.episodes.push(episode);
+        Ok(())
+    }
+
+    pub fn get(&self, index: usize) -> Experience<'_> {
+        let len = self.state_len();
+        Experience {
+            state: &self.states[index * len..(index + 1) * len],
+            action: self.actions[index],
+            reward: self.rewards[index],
+            episode: self.episodes[index],
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Experience<'_>> {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    /// States of the decisions as a batch of network inputs
+    pub(crate) fn batch(&self, indices: &[usize]) -> Result<Tensor> {
+        let (planes, rows, cols) = self.shape;
+        let states = indices.iter().flat_map(|i| self.get(*i).state).copied().collect();
+        Tensor::new(vec![indices.len(), planes, rows, cols], states)
+    }
+
+    /// Append the experience of the other buffer, its episodes numbered
+    /// after the ones of this buffer
+    pub fn extend(&mut self, other: &ExperienceBuffer) -> Result<()> {
+        ensure!(other.shape == self.shape, "Can't combine states of shapes {:?} and {:?}", self.shape, other.shape);
+        let offset = self.num_episodes();
+        self.states.extend_from_slice(&other.states);
+        self.actions.extend_from_slice(&other.actions);
+        self.rewards.extend_from_slice(&other.rewards);
+        self.episodes.extend(other.episodes.iter().map(|e| e + offset));
+        Ok(())
+    }
+
+    /// All experience of the buffers, e.g. of several self-play runs
+    pub fn combine(buffers: &[ExperienceBuffer]) -> Result<Self> {
+        let first = buffers.first().context("No buffers to combine")?;
+        let mut combined = ExperienceBuffer::new(first.shape);
+        for buffer in buffers {
+            combined.extend(buffer)?;
+        }
+        Ok(combined)
+    }
+
+    /// Put the decisions in random order
+    pub fn shuffle<R: Rng>(&mut self, rng: &mut R) {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.shuffle(rng);
+        *self = self.select(&order);
+    }
+
+    /// Random decisions of the buffer, at most `count` of them and each at
+    /// most once, in random order
+    pub fn sample<R: Rng>(&self, count: usize, rng: &mut R) -> Self {
+        let indices = index::sample(rng, self.len(), count.min(self.len())).into_vec();
+        self.select(&indices)
+    }
+
+    fn select(&self, indices: &[usize]) -> Self {
+        let mut selected = ExperienceBuffer::new(self.shape);
+        for &i in indices {
+            let experience = self.get(i);
+            selected.states.extend_from_slice(experience.state);
+            selected.actions.push(experience.action);
+            selected.rewards.push(experience.reward);
+            selected.episodes.push(experience.episode);
+        }
+        selected
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let (planes, rows, cols) = self.shape;
+        let len = self.len();
+        let mut npz = NpzWriter::create(path)?;
+        npz.add("states", &Array::new(vec![len, planes, rows, cols], self.states.clone())?)?;
+        npz.add("actions", &Array::new(vec![len], self.actions.iter().map(|a| *a as i64).collect())?)?;
+        npz.add("rewards", &Array::new(vec![len], self.rewards.clone())?)?;
+        npz.add("episodes", &Array::new(vec![len], self.episodes.iter().map(|e| *e as i64).collect())?)?;
+        npz.finish()?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut npz = NpzReader::open(path)?;
+        let states: Array<f32> = npz.read("states")?;
+        ensure!(states.shape.len() == 4, "States of shape {:?} aren't encoded positions", states.shape);
+        let len = states.shape[0];
+        let indices = |array: Array<i64>, name: &str| -> Result<Vec<usize>> {
+            ensure!(array.shape == [len], "{} of shape {:?} for {} states", name, array.shape, len);
+            let index = |i: i64| usize::try_from(i).with_context(|| format!("Invalid {} {}", name, i));
+            array.data.into_iter().map(index).collect()
+        };
+        let actions = indices(npz.read("actions")?, "actions")?;
+        let episodes = indices(npz.read("episodes")?, "episodes")?;
+        let rewards: Array<f32> = npz.read("rewards")?;
+        ensure!(rewards.shape == [len], "Rewards of shape {:?} for {} states", rewards.shape, len);
+        Ok(Self {
+            shape: (states.shape[1], states.shape[2], states.shape[3]),
+            states: states.data,
+            actions,
+            rewards: rewards.data,
+            episodes,
+        })
+    }
+}
+
+/// Records the decisions of an agent game by game, and gives them the
+/// reward of the game when it ends
+pub struct ExperienceCollector {
+    encoder: Box<dyn Encoder + Send + Sync>,
+    buffer: ExperienceBuffer,
+    /// Encoded states and actions of the current episode
+    current: Vec<(Vec<f32>, usize)>,
+    episodes: usize,
+}
+
+impl ExperienceCollector {
+    pub fn new(encoder: Box<dyn Encoder + Send + Sync>) -> Self {
+        let buffer = ExperienceBuffer::new(encoder.shape());
+        Self { encoder, buffer, current: Vec::new(), episodes: 0 }
+    }
+
+    /// Start a new episode, forgetting the decisions of an unfinished one
+    pub fn begin_episode(&mut self) {
+        self.current.clear();
+    }
+
+    pub fn record_decision(&mut self, game_state: &GoState, the_move: &Move) {
+        if let Some(action) = action_index(self.encoder.as_ref(), the_move) {
+            self.current.push((self.encoder.encode(game_state), action));
+        }
+    }
+
+    /// Add the decisions of the episode to the buffer with the reward
+    pub fn complete_episode(&mut self, reward: f32) {
+        for (state, action) in self.current.drain(..) {
+            self.buffer.push(&state, action, reward, self.episodes).expect("States are encoded with the buffer's shape");
+        }
+        self.episodes += 1;
+    }
+
+    pub fn buffer(&self) -> &ExperienceBuffer {
+        &self.buffer
+    }
+
+    pub fn into_buffer(self) -> ExperienceBuffer {
+        self.buffer
+    }
+}
+
+/// Agent recording the decisions of another agent, for use in any game
+/// loop. The reward of each game is from its result for the color the
+/// agent played, and zero if the game was stopped before its end.
+pub struct RecordingAgent<A> {
+    agent: A,
+    collector: ExperienceCollector,
+    color: Option<Color>,
+}
+
+impl<A> RecordingAgent<A> {
+    pub fn new(agent: A, collector: ExperienceCollector) -> Self {
+        Self { agent, collector, color: None }
+    }
+
+    pub fn collector(&self) -> &ExperienceCollector {
+        &self.collector
+    }
+
+    pub fn into_collector(self) -> ExperienceCollector {
+        self.collector
+    }
+}
+
+impl<A: Agent<GoState>> Agent<GoState> for RecordingAgent<A> {
+    fn select_move(&mut self, game_state: &GoState) -> Move {
+        let the_move = self.agent.select_move(game_state);
+        self.color = Some(game_state.next_player.color);
+        self.collector.record_decision(game_state, &the_move);
+        the_move
+    }
+
+    fn game_started(&mut self, game_state: &GoState) {
+        self.color = None;
+        self.collector.begin_episode();
+        self.agent.game_started(game_state);
+    }
+
+    fn move_played(&mut self, game_state: &GoState, the_move: &Move) {
+        self.agent.move_played(game_state, the_move);
+    }
+
+    fn game_ended(&mut self, game_state: &GoState) {
+        self.agent.game_ended(game_state);
+        // An agent that never moved has no decisions to reward
+        let reward = match self.color {
+            Some(color) if game_state.is_over() => final_reward(game_state, color),
+            _ => 0.0,
+        };
+        self.collector.complete_episode(reward);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{FastRandomBot, RandomBot};
+    use crate::game::go::encoders::OnePlaneEncoder;
+    use crate::game::go::Point;
+    use crate::play::play_game;
+    use rand::SeedableRng;
+
+    fn recorder(seed: u64) -> RecordingAgent<FastRandomBot> {
+        RecordingAgent::new(FastRandomBot::with_seed(seed), ExperienceCollector::new(Box::new(OnePlaneEncoder::new(5))))
+    }
+
+    /// Buffer of the games between two random agents, both recorded
+    fn self_play(games: usize) -> ExperienceBuffer {
+        let (mut black, mut white) = (recorder(1), recorder(2));
+        for _ in 0..games {
+            let record = play_game(GoState::new(5), &mut [&mut black, &mut white], Some(200), |_, _| {});
+            assert!(record.winner().is_some());
+        }
+        ExperienceBuffer::combine(&[black.into_collector().into_buffer(), white.into_collector().into_buffer()]).unwrap()
+    }
+
+    #[test]
+    fn test_rewards_follow_game_result() {
+        let (mut black, mut white) = (recorder(3), recorder(4));
+        let record = play_game(GoState::new(5), &mut [&mut black, &mut white], None, |_, _| {});
+        let winner = record.winner().unwrap();
+        let buffers = [black.collector().buffer(), white.collector().buffer()];
+        for (agent, buffer) in buffers.iter().enumerate() {
+            let expected = if agent == winner { 1.0 } else { -1.0 };
+            assert!(!buffer.is_empty());
+            assert!(buffer.iter().all(|e| e.reward == expected && e.episode == 0));
+        }
+        // Every move but resignations is recorded by the agent that made it
+        assert_eq!(buffers[0].len() + buffers[1].len(), record.moves.iter().filter(|m| **m != Move::Resign).count());
+
+        // The first decision is black's on the empty board
+        let first = buffers[0].get(0);
+        assert!(first.state.iter().all(|v| *v == 0.0));
+        assert_eq!(action_move(&OnePlaneEncoder::new(5), first.action), record.moves[0]);
+    }
+
+    #[test]
+    fn test_stopped_games_are_draws() {
+        let (mut black, mut white) = (recorder(3), recorder(4));
+        let record = play_game(GoState::new(5), &mut [&mut black, &mut white], Some(6), |_, _| {});
+        assert!(!record.final_state.is_over());
+        for agent in [black, white] {
+            let buffer = agent.into_collector().into_buffer();
+            assert_eq!(buffer.len(), 3);
+            assert!(buffer.iter().all(|e| e.reward == 0.0));
+        }
+    }
+
+    #[test]
+    fn test_resignation_ends_episode_without_decision() {
+        let encoder = OnePlaneEncoder::new(5);
+        let mut collector = ExperienceCollector::new(Box::new(OnePlaneEncoder::new(5)));
+        let game = GoState::new(5);
+        collector.begin_episode();
+        collector.record_decision(&game, &Move::Play(Point::new(3, 3)));
+        let game = game.apply_move(&Move::Play(Point::new(3, 3)));
+        collector.record_decision(&game, &Move::Pass);
+        collector.record_decision(&game, &Move::Resign);
+        collector.complete_episode(final_reward(&game.apply_move(&Move::Resign), Color::Black));
+
+        let buffer = collector.into_buffer();
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.get(0).action, 12);
+        assert_eq!(buffer.get(1).action, encoder.num_points());
+        assert_eq!(action_move(&encoder, buffer.get(1).action), Move::Pass);
+        assert!(buffer.iter().all(|e| e.reward == 1.0));
+    }
+
+    #[test]
+    fn test_combine_shuffle_and_sample() {
+        let first = self_play(2);
+        let second = self_play(1);
+        assert_eq!(first.num_episodes(), 4);
+        let combined = ExperienceBuffer::combine(&[first.clone(), second.clone()]).unwrap();
+        assert_eq!(combined.len(), first.len() + second.len());
+        assert_eq!(combined.num_episodes(), 6);
+        assert_eq!(combined.get(first.len()).episode, second.get(0).episode + 4);
+
+        let mut rng = rand_pcg::Pcg64::seed_from_u64(5);
+        let mut shuffled = combined.clone();
+        shuffled.shuffle(&mut rng);
+        assert_ne!(shuffled, combined);
+        let total = |b: &ExperienceBuffer| b.iter().map(|e| e.action as f32 * e.reward).sum::<f32>();
+        assert_eq!(total(&shuffled), total(&combined));
+
+        let sample = combined.sample(10, &mut rng);
+        assert_eq!(sample.len(), 10);
+        assert!(sample.iter().all(|e| combined.iter().any(|c| c == e)));
+        assert_eq!(combined.sample(combined.len() + 5, &mut rng).len(), combined.len());
+        assert!(ExperienceBuffer::combine(&[first, ExperienceBuffer::new((7, 5, 5))]).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let buffer = self_play(1);
+        let path = std::env::temp_dir().join(format!("bgai-experience-test-{}.npz", std::process::id()));
+        buffer.save(&path).unwrap();
+        let loaded = ExperienceBuffer::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, buffer);
+    }
+
+    #[test]
+    fn test_any_agent_can_be_recorded() {
+        let mut agent = RecordingAgent::new(RandomBot::with_seed(1), ExperienceCollector::new(Box::new(OnePlaneEncoder::new(5))));
+        let game = GoState::new(5);
+        agent.game_started(&game);
+        let the_move = agent.select_move(&game);
+        agent.game_ended(&game.apply_move(&the_move));
+        assert_eq!(agent.collector().buffer().len(), usize::from(the_move != Move::Resign));
+    }
+}
@@ -0,0 +1,11 @@
+//! Reinforcement learning for Go agents
+
+pub mod experience;
+pub mod reinforce;
+pub mod q_learning;
+pub mod actor_critic;
+
+pub use actor_critic::{ActorCriticConfig, ActorCriticTrainer};
+pub use experience::{ExperienceBuffer, ExperienceCollector, RecordingAgent};
+pub use q_learning::{QConfig, QTrainer};
+pub use reinforce::{ReinforceConfig, ReinforceTrainer};
@@ -0,0 +1,155 @@
+//! Q-learning from the experience of games: the value of each move played
+//! is fitted to the reward of its game with squared error, leaving the
+//! values of the other moves alone.
+
+use anyhow::{ensure, Context, Result};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::nn::train::{Optimizer, OptimizerState};
+use crate::nn::{Network, Tensor, Q_HEAD};
+use crate::rl::ExperienceBuffer;
+
+#[derive(Clone, Debug)]
+pub struct QConfig {
+    /// Passes over the experience
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    pub optimizer: Optimizer,
+    /// Seed for shuffling the experience
+    pub seed: u64,
+}
+
+impl Default for QConfig {
+    fn default() -> Self {
+        Self { epochs: 1, batch_size: 32, learning_rate: 0.01, optimizer: Optimizer::Sgd { momentum: 0.0 }, seed: 0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QReport {
+    /// Counting from one
+    pub epoch: usize,
+    /// Mean squared error of the values of the moves played
+    pub loss: f32,
+}
+
+/// Trains the Q head of a network, and the layers it shares with other
+/// heads, on the experience of games
+pub struct QTrainer {
+    network: Network,
+    head: usize,
+    /// Number of move values of the Q head
+    moves: usize,
+    config: QConfig,
+    rng: rand_pcg::Pcg64,
+    optimizer: OptimizerState,
+}
+
+impl QTrainer {
+    pub fn new(network: Network, config: QConfig) -> Result<Self> {
+        let head = network.head_index(Q_HEAD).with_context(|| format!("The network has no {} head", Q_HEAD))?;
+        ensure!(config.batch_size > 0, "The batch size must be positive");
+        let (planes, rows, cols) = network.input_shape();
+        let moves = network.forward(&Tensor::zeros(vec![1, planes, rows, cols]))?[Q_HEAD].data().len();
+        let rng = rand_pcg::Pcg64::seed_from_u64(config.seed);
+        let optimizer = OptimizerState::new(config.optimizer);
+        Ok(Self { network, head, moves, config, rng, optimizer })
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn into_network(self) -> Network {
+        self.network
+    }
+
+    /// Train for the configured epochs, reporting each
+    pub fn fit(&mut self, experience: &ExperienceBuffer, mut on_epoch: impl FnMut(&QReport)) -> Result<Vec<QReport>> {
+        let mut reports = Vec::new();
+        for epoch in 0..self.config.epochs {
+            let report = QReport { epoch: epoch + 1, loss: self.train_epoch(experience)? };
+            on_epoch(&report);
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    /// One pass over the experience in shuffled mini-batches, returning the
+    /// mean loss. Decisions without a value, such as passes, are skipped.
+    pub fn train_epoch(&mut self, experience: &ExperienceBuffer) -> Result<f32> {
+        ensure!(
+            experience.shape() == self.network.input_shape(),
+            "Experience of shape {:?} doesn't fit the network's input {:?}",
+            experience.shape(),
+            self.network.input_shape()
+        );
+        let mut order: Vec<usize> = (0..experience.len()).filter(|i| experience.get(*i).action < self.moves).collect();
+        ensure!(!order.is_empty(), "No moves with values to train on");
+
+        order.shuffle(&mut self.rng);
+        let mut loss = 0.0;
+        for indices in order.chunks(self.config.batch_size) {
+            let input = experience.batch(indices)?;
+            let pass = self.network.forward_train(&input, &[self.head])?;
+            let values = &pass.outputs[0];
+            let mut grad = vec![0.0; values.data().len()];
+            for (n, i) in indices.iter().enumerate() {
+                let experience = experience.get(*i);
+                let error = values.item(n)[experience.action] - experience.reward;
+                loss += error * error;
+                grad[n * self.moves + experience.action] = 2.0 * error / indices.len() as f32;
+            }
+            let grad = Tensor::new(values.shape().to_vec(), grad)?;
+            let gradients = self.network.backward(pass, vec![grad])?;
+            self.optimizer.step(self.network.parameters_mut(&[self.head]), &gradients, self.config.learning_rate);
+        }
+        Ok(loss / order.len() as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::{Encoder, OnePlaneEncoder};
+    use crate::game::go::{GoState, Move, Point};
+    use crate::game::GameState;
+
+    /// Values of the moves on the board after black's center stone
+    fn values(network: &Network) -> Vec<f32> {
+        let game = GoState::new(3).apply_move(&Move::Play(Point::new(2, 2)));
+        network.evaluate(&OnePlaneEncoder::new(3), &[game]).unwrap()[Q_HEAD].data().to_vec()
+    }
+
+    #[test]
+    fn test_values_approach_rewards() {
+        let encoder = OnePlaneEncoder::new(3);
+        let game = GoState::new(3).apply_move(&Move::Play(Point::new(2, 2)));
+        let state = encoder.encode(&game);
+        let mut experience = ExperienceBuffer::new(encoder.shape());
+        for episode in 0..4 {
+            experience.push(&state, encoder.encode_point(&Point::new(1, 2)), 1.0, episode).unwrap();
+            experience.push(&state, encoder.encode_point(&Point::new(3, 3)), -1.0, episode).unwrap();
+            experience.push(&state, encoder.num_points(), 1.0, episode).unwrap();
+        }
+
+        let network = Network::random_q((1, 3, 3), &[4], 1).unwrap();
+        let before = values(&network);
+        let config = QConfig { epochs: 100, batch_size: 4, learning_rate: 0.05, ..QConfig::default() };
+        let mut trainer = QTrainer::new(network, config).unwrap();
+        let reports = trainer.fit(&experience, |_| {}).unwrap();
+        assert!(reports.last().unwrap().loss < reports[0].loss / 4.0, "{:?}", reports);
+
+        let after = values(trainer.network());
+        assert!(after[1] > 0.8 && after[8] < -0.8, "{:?}", after);
+        // Moves not played keep values near their initial ones
+        assert!((after[6] - before[6]).abs() < (after[1] - before[1]).abs());
+    }
+
+    #[test]
+    fn test_network_needs_q_head() {
+        assert!(QTrainer::new(Network::random((1, 3, 3), &[2], true, 1).unwrap(), QConfig::default()).is_err());
+    }
+}
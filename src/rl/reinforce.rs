@@ -0,0 +1,340 @@
+//! Policy-gradient training with REINFORCE: the log probability of every
+//! move in the experience is raised in proportion to its advantage, the
+//! reward of its game less a baseline, while an entropy bonus keeps the
+//! policy from collapsing onto few moves too early.
+
+use anyhow::{ensure, Context, Result};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::agent::PolicyAgent;
+use crate::game::go::encoders::get_encoder_by_name;
+use crate::game::go::GoState;
+use crate::nn::train::{log_softmax, Optimizer, OptimizerState};
+use crate::nn::{Network, Tensor};
+use crate::rl::ExperienceBuffer;
+use crate::tournament::{round_robin, Entrant, PairResult, TournamentConfig};
+
+/// What is subtracted from the rewards to reduce the variance of the
+/// gradient
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Baseline {
+    None,
+    /// Mean reward of the experience trained on
+    Mean,
+}
+
+/// Matches between the policy being trained and the one of the previous
+/// evaluation, or the initial one
+#[derive(Clone, Debug)]
+pub struct Evaluation {
+    /// Epochs between evaluations
+    pub every: usize,
+    pub games: u32,
+    /// Encoder of the network's input
+    pub encoder: String,
+    pub komi: f32,
+    /// Temperature of the agents' moves, above zero for the games to differ
+    pub temperature: f64,
+    pub max_moves: Option<usize>,
+    pub threads: usize,
+}
+
+impl Default for Evaluation {
+    fn default() -> Self {
+        Self {
+            every: 1,
+            games: 20,
+            encoder: "sevenplane".to_string(),
+            komi: 7.5,
+            temperature: 1.0,
+            max_moves: None,
+            threads: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReinforceConfig {
+    /// Passes over the experience
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    pub optimizer: Optimizer,
+    pub baseline: Baseline,
+    /// Weight of the entropy bonus in the loss
+    pub entropy_weight: f32,
+    /// Seed for shuffling the experience and for the evaluation games
+    pub seed: u64,
+    pub evaluation: Option<Evaluation>,
+}
+
+impl Default for ReinforceConfig {
+    fn default() -> Self {
+        Self {
+            epochs: 1,
+            batch_size: 32,
+            learning_rate: 0.01,
+            optimizer: Optimizer::Sgd { momentum: 0.0 },
+            baseline: Baseline::Mean,
+            entropy_weight: 0.01,
+            seed: 0,
+            evaluation: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PolicyMetrics {
+    /// Mean of the advantage times the negative log probability of the move
+    pub loss: f32,
+    /// Mean entropy of the policy in nats
+    pub entropy: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReinforceReport {
+    /// Counting from one
+    pub epoch: usize,
+    pub train: PolicyMetrics,
+    /// Result of the trained policy against the previous one, if evaluated
+    /// after this epoch
+    pub evaluation: Option<PairResult>,
+}
+
+/// Trains the policy head of a network, and the layers it shares with other
+/// heads, on the experience of games. The policy head must end in softmax.
+pub struct ReinforceTrainer {
+    network: Network,
+    head: usize,
+    /// Number of move outputs of the policy
+    moves: usize,
+    config: ReinforceConfig,
+    rng: rand_pcg::Pcg64,
+    optimizer: OptimizerState,
+    /// Policy the trained one is evaluated against
+    previous: Network,
+}
+
+impl ReinforceTrainer {
+    pub fn new(network: Network, config: ReinforceConfig) -> Result<Self> {
+        let name = network.policy_head().context("The network has no policy")?.to_string();
+        let head = network.softmax_head(&name)?;
+        ensure!(config.batch_size > 0, "The batch size must be positive");
+        ensure!(config.entropy_weight >= 0.0, "The entropy weight can't be negative");
+        if let Some(evaluation) = &config.evaluation {
+            // The agents of the evaluation games are built the same way
+            let encoder = get_encoder_by_name(&evaluation.encoder, network.input_shape().1)?;
+            PolicyAgent::new(network.clone(), encoder)?;
+        }
+        let (planes, rows, cols) = network.input_shape();
+        let moves = network.forward(&Tensor::zeros(vec![1, planes, rows, cols]))?[&name].data().len();
+        let rng = rand_pcg::Pcg64::seed_from_u64(config.seed);
+        let optimizer = OptimizerState::new(config.optimizer);
+        Ok(Self { previous: network.clone(), network, head, moves, config, rng, optimizer })
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn into_network(self) -> Network {
+        self.network
+    }
+
+    /// Train for the configured epochs, reporting each and evaluating the
+    /// policy as configured
+    pub fn fit(
+        &mut self,
+        experience: &ExperienceBuffer,
+        mut on_epoch: impl FnMut(&ReinforceReport),
+    ) -> Result<Vec<ReinforceReport>> {
+        let mut reports = Vec::new();
+        for epoch in 0..self.config.epochs {
+            let train = self.train_epoch(experience)?;
+            let evaluation = match &self.config.evaluation {
+                Some(evaluation) if (epoch + 1) % evaluation.every.max(1) == 0 => Some(self.evaluate_against_previous()?),
+                _ => None,
+            };
+            let report = ReinforceReport { epoch: epoch + 1, train, evaluation };
+            on_epoch(&report);
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    /// One pass over the experience in shuffled mini-batches. Decisions the
+    /// policy has no output for, such as passes, are skipped.
+    pub fn train_epoch(&mut self, experience: &ExperienceBuffer) -> Result<PolicyMetrics> {
+        ensure!(
+            experience.shape() == self.network.input_shape(),
+            "Experience of shape {:?} doesn't fit the network's input {:?}",
+            experience.shape(),
+            self.network.input_shape()
+        );
+        let mut order: Vec<usize> = (0..experience.len()).filter(|i| experience.get(*i).action < self.moves).collect();
+        ensure!(!order.is_empty(), "No moves of the policy to train on");
+        let baseline = match self.config.baseline {
+            Baseline::None => 0.0,
+            Baseline::Mean => order.iter().map(|i| experience.get(*i).reward).sum::<f32>() / order.len() as f32,
+        };
+
+        order.shuffle(&mut self.rng);
+        let (mut loss, mut entropy) = (0.0, 0.0);
+        for indices in order.chunks(self.config.batch_size) {
+            let input = experience.batch(indices)?;
+            let actions: Vec<usize> = indices.iter().map(|i| experience.get(*i).action).collect();
+            let advantages: Vec<f32> = indices.iter().map(|i| experience.get(*i).reward - baseline).collect();
+            let (batch_loss, batch_entropy, gradients) = self.gradients(&input, &actions, &advantages)?;
+            self.optimizer.step(self.network.parameters_mut(&[self.head]), &gradients, self.config.learning_rate);
+            loss += batch_loss * indices.len() as f32;
+            entropy += batch_entropy * indices.len() as f32;
+        }
+        Ok(PolicyMetrics { loss: loss / order.len() as f32, entropy: entropy / order.len() as f32 })
+    }
+
+    /// Play the evaluation games between the trained policy and the previous
+    /// one, which the trained policy then replaces
+    pub fn evaluate_against_previous(&mut self) -> Result<PairResult> {
+        let evaluation = self.config.evaluation.clone().context("No evaluation configured")?;
+        let size = self.network.input_shape().1;
+        let entrant = |name: &str, network: &Network| {
+            let (network, evaluation) = (network.clone(), evaluation.clone());
+            Entrant::new(name, move |seed| {
+                let encoder = get_encoder_by_name(&evaluation.encoder, size).expect("Encoder was checked");
+                let agent = PolicyAgent::with_seed(network.clone(), encoder, seed).expect("Network was checked");
+                Box::new(agent.with_temperature(evaluation.temperature))
+            })
+        };
+        let entrants = [entrant("trained", &self.network), entrant("previous", &self.previous)];
+        let config = TournamentConfig {
+            games_per_pair: evaluation.games,
+            threads: evaluation.threads,
+            max_moves: evaluation.max_moves,
+            seed: self.rng.gen(),
+        };
+        let results = round_robin(&entrants, || GoState::new(size).with_komi(evaluation.komi), &config);
+        self.previous = self.network.clone();
+        Ok(results.pair(0, 1))
+    }
+
+    /// Mean loss and entropy, and the gradients of the trained parameters
+    /// for a batch
+    fn gradients(&mut self, input: &Tensor, actions: &[usize], advantages: &[f32]) -> Result<(f32, f32, Vec<Vec<f32>>)> {
+        let pass = self.network.forward_train(input, &[self.head])?;
+        let logits = &pass.outputs[0];
+        let batch = actions.len();
+        let (mut loss, mut entropy) = (0.0, 0.0);
+        let mut grad = vec![0.0; logits.data().len()];
+        for (n, (&action, &advantage)) in actions.iter().zip(advantages).enumerate() {
+            let item_grad = &mut grad[n * self.moves..(n + 1) * self.moves];
+            let (log_probability, item_entropy) =
+                policy_gradient(logits.item(n), action, advantage, self.config.entropy_weight, item_grad);
+            loss -= advantage * log_probability;
+            entropy += item_entropy;
+        }
+        grad.iter_mut().for_each(|g| *g /= batch as f32);
+
+        let grad = Tensor::new(logits.shape().to_vec(), grad)?;
+        let gradients = self.network.backward(pass, vec![grad])?;
+        Ok((loss / batch as f32, entropy / batch as f32, gradients))
+    }
+}
+
+/// Gradient of `-advantage * log p(action) - entropy_weight * entropy` for
+/// the logits of a softmax policy, and the log probability of the action and
+/// the entropy of the policy
+pub(crate) fn policy_gradient(
+    logits: &[f32],
+    action: usize,
+    advantage: f32,
+    entropy_weight: f32,
+    grad: &mut [f32],
+) -> (f32, f32) {
+    let log_probabilities = log_softmax(logits);
+    let entropy: f32 = -log_probabilities.iter().map(|l| l.exp() * l).sum::<f32>();
+    for (i, g) in grad.iter_mut().enumerate() {
+        let p = log_probabilities[i].exp();
+        let played = if i == action { 1.0 } else { 0.0 };
+        *g = advantage * (p - played) + entropy_weight * p * (log_probabilities[i] + entropy);
+    }
+    (log_probabilities[action], entropy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::go::encoders::{Encoder, OnePlaneEncoder};
+    use crate::game::go::{Move, Point};
+    use crate::rl::experience::action_index;
+
+    /// Probabilities of the network's moves on the empty 3x3 board
+    fn policy(network: &Network) -> Vec<f32> {
+        network.evaluate(&OnePlaneEncoder::new(3), &[GoState::new(3)]).unwrap()["policy"].data().to_vec()
+    }
+
+    /// Experience of the empty board where the center won and the corner
+    /// lost, and a pass that has no policy output
+    fn center_experience() -> ExperienceBuffer {
+        let encoder = OnePlaneEncoder::new(3);
+        let state = encoder.encode(&GoState::new(3));
+        let mut buffer = ExperienceBuffer::new(encoder.shape());
+        for episode in 0..8 {
+            let (the_move, reward) = if episode % 2 == 0 { (Point::new(2, 2), 1.0) } else { (Point::new(1, 1), -1.0) };
+            buffer.push(&state, action_index(&encoder, &Move::Play(the_move)).unwrap(), reward, episode).unwrap();
+        }
+        buffer.push(&state, action_index(&encoder, &Move::Pass).unwrap(), 1.0, 8).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_rewarded_moves_become_likelier() {
+        let network = Network::random((1, 3, 3), &[4], true, 1).unwrap();
+        let before = policy(&network);
+        let config = ReinforceConfig { epochs: 20, batch_size: 4, learning_rate: 0.05, ..ReinforceConfig::default() };
+        let mut trainer = ReinforceTrainer::new(network, config).unwrap();
+        let reports = trainer.fit(&center_experience(), |_| {}).unwrap();
+        assert_eq!(reports.len(), 20);
+        assert!(reports.iter().all(|r| r.evaluation.is_none()));
+
+        let after = policy(trainer.network());
+        assert!(after[4] > 2.0 * before[4], "{:?} then {:?}", before, after);
+        assert!(after[0] < before[0], "{:?} then {:?}", before, after);
+    }
+
+    #[test]
+    fn test_entropy_bonus_spreads_the_policy() {
+        // A policy favoring the center, as the empty board gives a uniform one
+        let network = Network::random((1, 3, 3), &[4], false, 2).unwrap();
+        let config = ReinforceConfig { epochs: 50, learning_rate: 0.5, entropy_weight: 0.0, ..ReinforceConfig::default() };
+        let mut trainer = ReinforceTrainer::new(network, config).unwrap();
+        trainer.fit(&center_experience(), |_| {}).unwrap();
+
+        // Without rewards only the entropy bonus is left
+        let mut experience = ExperienceBuffer::new((1, 3, 3));
+        for e in center_experience().iter() {
+            experience.push(e.state, e.action, 0.0, e.episode).unwrap();
+        }
+        let config = ReinforceConfig { epochs: 50, learning_rate: 0.1, entropy_weight: 1.0, ..ReinforceConfig::default() };
+        let mut trainer = ReinforceTrainer::new(trainer.into_network(), config).unwrap();
+        let reports = trainer.fit(&experience, |_| {}).unwrap();
+        let (first, last) = (reports[0].train.entropy, reports.last().unwrap().train.entropy);
+        assert!(first < 0.9 * 9f32.ln(), "{}", first);
+        assert!(last > first && last <= 9f32.ln(), "{} then {}", first, last);
+    }
+
+    #[test]
+    fn test_policy_is_evaluated_against_previous_one() {
+        let evaluation = Evaluation { every: 2, games: 4, encoder: "oneplane".to_string(), max_moves: Some(20), ..Evaluation::default() };
+        let config = ReinforceConfig { epochs: 4, evaluation: Some(evaluation), ..ReinforceConfig::default() };
+        let mut trainer = ReinforceTrainer::new(Network::random((1, 3, 3), &[2], false, 3).unwrap(), config).unwrap();
+        let reports = trainer.fit(&center_experience(), |_| {}).unwrap();
+        let evaluated: Vec<usize> = reports.iter().filter(|r| r.evaluation.is_some()).map(|r| r.epoch).collect();
+        assert_eq!(evaluated, vec![2, 4]);
+        assert_eq!(reports[1].evaluation.as_ref().unwrap().games, 4);
+
+        let wrong_encoder = Evaluation { encoder: "sevenplane".to_string(), ..Evaluation::default() };
+        let config = ReinforceConfig { evaluation: Some(wrong_encoder), ..ReinforceConfig::default() };
+        assert!(ReinforceTrainer::new(Network::random((1, 3, 3), &[2], false, 3).unwrap(), config).is_err());
+    }
+}
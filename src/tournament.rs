@@ -0,0 +1,638 @@
+//! Matches and round-robin tournaments between agents, with win rates,
+//! confidence intervals and Elo estimates
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::agent::Agent;
+use crate::game::GameOutcome;
+use crate::play::play_game;
+
+/// Creates a new agent for every game from the game's seed
+pub type AgentFactory<S> = Box<dyn Fn(u64) -> Box<dyn Agent<S>> + Send + Sync>;
+
+/// An agent taking part in a tournament
+pub struct Entrant<S> {
+    pub name: String,
+    factory: AgentFactory<S>,
+}
+
+impl<S> Entrant<S> {
+    pub fn new<F>(name: &str, factory: F) -> Self
+    where
+        F: Fn(u64) -> Box<dyn Agent<S>> + Send + Sync + 'static,
+    {
+        Self { name: name.to_string(), factory: Box::new(factory) }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TournamentConfig {
+    /// Number of games between each pair of entrants. The entrants take turns
+    /// moving first.
+    pub games_per_pair: u32,
+    /// Number of games played at the same time
+    pub threads: usize,
+    /// Games longer than this are stopped and scored as draws
+    pub max_moves: Option<usize>,
+    /// Seed of the first game, the agents of each game get their own seeds
+    /// from it
+    pub seed: u64,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        Self {
+            games_per_pair: 10,
+            threads: 1,
+            max_moves: None,
+            seed: 0,
+        }
+    }
+}
+
+/// Result of one tournament game
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameSummary {
+    /// Index of the entrant moving first
+    pub first: usize,
+    /// Index of the entrant moving second
+    pub second: usize,
+    /// Index of the winning entrant, None for a draw
+    pub winner: Option<usize>,
+    /// Number of moves played
+    pub moves: usize,
+    /// Seed of the first entrant's agent, the second's is the next one
+    pub seed: u64,
+}
+
+impl GameSummary {
+    /// Score of the entrant in this game, one for a win and a half for a draw
+    pub fn score(&self, entrant: usize) -> f64 {
+        match self.winner {
+            Some(winner) if winner == entrant => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        }
+    }
+
+    fn involves(&self, entrant: usize) -> bool {
+        self.first == entrant || self.second == entrant
+    }
+}
+
+/// Results of an entrant, from all its games
+#[derive(Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub name: String,
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Average game length
+    pub average_moves: f64,
+    /// Score per game, with draws as half wins
+    pub score_rate: f64,
+    /// 95% confidence interval of the score rate
+    pub score_interval: (f64, f64),
+    /// Maximum likelihood rating, the average of the entrants being zero
+    pub elo: f64,
+}
+
+/// Results of the games between two entrants
+#[derive(Clone, Debug, PartialEq)]
+pub struct PairResult {
+    pub entrant: usize,
+    pub opponent: usize,
+    pub games: u32,
+    /// Score rate of the entrant against the opponent
+    pub score_rate: f64,
+    pub score_interval: (f64, f64),
+    /// Elo difference implied by the score rate, and its 95% confidence
+    /// interval
+    pub elo_difference: f64,
+    pub elo_interval: (f64, f64),
+}
+
+#[derive(Clone, Debug)]
+pub struct TournamentResults {
+    pub names: Vec<String>,
+    /// All games, in the order they were scheduled
+    pub games: Vec<GameSummary>,
+}
+
+/// Play `games_per_pair` games between every pair of entrants starting from
+/// the state given by `new_game`. Each game gets new agents from the
+/// factories, so the results don't depend on the order the games are played
+/// in.
+pub fn round_robin<S, G>(entrants: &[Entrant<S>], new_game: G, config: &TournamentConfig) -> TournamentResults
+where
+    S: GameOutcome,
+    G: Fn() -> S + Sync,
+{
+    let mut schedule = Vec::new();
+    for i in 0..entrants.len() {
+        for j in i + 1..entrants.len() {
+            for game in 0..config.games_per_pair {
+                let (first, second) = if game % 2 == 0 { (i, j) } else { (j, i) };
+                let seed = config.seed.wrapping_add(2 * schedule.len() as u64);
+                schedule.push((first, second, seed));
+            }
+        }
+    }
+
+    TournamentResults {
+        names: entrants.iter().map(|e| e.name.clone()).collect(),
+        games: play_schedule(entrants, &schedule, &new_game, config.threads, config.max_moves),
+    }
+}
+
+/// Play the games given as (first entrant, second entrant, seed) on the
+/// threads, and return their summaries in the same order
+fn play_schedule<S, G>(
+    entrants: &[Entrant<S>],
+    schedule: &[(usize, usize, u64)],
+    new_game: &G,
+    threads: usize,
+    max_moves: Option<usize>,
+) -> Vec<GameSummary>
+where
+    S: GameOutcome,
+    G: Fn() -> S + Sync,
+{
+    let next_game = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; schedule.len()]);
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| loop {
+                let index = next_game.fetch_add(1, Ordering::SeqCst);
+                let (first, second, seed) = match schedule.get(index) {
+                    Some(game) => *game,
+                    None => break,
+                };
+                let mut first_agent = (entrants[first].factory)(seed);
+                let mut second_agent = (entrants[second].factory)(seed.wrapping_add(1));
+                let record = play_game(new_game(), &mut [first_agent.as_mut(), second_agent.as_mut()], max_moves, |_, _| {});
+                let winner = match record.winner() {
+                    // Unfinished games are draws
+                    Some(_) if !record.final_state.is_over() => None,
+                    Some(0) => Some(first),
+                    Some(_) => Some(second),
+                    None => None,
+                };
+                let summary = GameSummary { first, second, winner, moves: record.moves.len(), seed };
+                results.lock().unwrap()[index] = Some(summary);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(Option::unwrap).collect()
+}
+
+/// Mean score and its 95% Wilson score interval, which stays meaningful when
+/// one side wins every game
+fn score_interval(scores: &[f64]) -> (f64, (f64, f64)) {
+    if scores.is_empty() {
+        return (0.5, (0.0, 1.0));
+    }
+    const Z: f64 = 1.96;
+    let n = scores.len() as f64;
+    let p = scores.iter().sum::<f64>() / n;
+    let denominator = 1.0 + Z * Z / n;
+    let center = (p + Z * Z / (2.0 * n)) / denominator;
+    let margin = Z / denominator * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt();
+    (p, ((center - margin).max(0.0), (center + margin).min(1.0)))
+}
+
+/// Elo difference corresponding to the expected score, clamped to +-1000
+pub fn elo_difference(score_rate: f64) -> f64 {
+    let p = score_rate.clamp(1e-3, 1.0 - 1e-3);
+    (-400.0 * (1.0 / p - 1.0).log10()).clamp(-1000.0, 1000.0)
+}
+
+impl TournamentResults {
+    fn scores(&self, entrant: usize, opponent: Option<usize>) -> Vec<f64> {
+        self.games
+            .iter()
+            .filter(|g| g.involves(entrant) && opponent.is_none_or(|o| g.involves(o)))
+            .map(|g| g.score(entrant))
+            .collect()
+    }
+
+    /// Results of the entrant against the opponent
+    pub fn pair(&self, entrant: usize, opponent: usize) -> PairResult {
+        let scores = self.scores(entrant, Some(opponent));
+        let (score_rate, score_interval) = score_interval(&scores);
+        PairResult {
+            entrant,
+            opponent,
+            games: scores.len() as u32,
+            score_rate,
+            score_interval,
+            elo_difference: elo_difference(score_rate),
+            elo_interval: (elo_difference(score_interval.0), elo_difference(score_interval.1)),
+        }
+    }
+
+    /// Ratings maximizing the likelihood of the results in the Bradley-Terry
+    /// model, by minorization-maximization. Each pair is given one virtual
+    /// draw so that entrants without wins or losses get finite ratings.
+    pub fn elo_ratings(&self) -> Vec<f64> {
+        let n = self.names.len();
+        let mut wins = vec![0.0; n];
+        let mut games = vec![vec![0.0; n]; n];
+        for (i, row) in games.iter_mut().enumerate() {
+            for (j, count) in row.iter_mut().enumerate() {
+                if i != j && self.games.iter().any(|g| g.involves(i) && g.involves(j)) {
+                    wins[i] += 0.5;
+                    *count += 1.0;
+                }
+            }
+        }
+        for game in &self.games {
+            wins[game.first] += game.score(game.first);
+            wins[game.second] += game.score(game.second);
+            games[game.first][game.second] += 1.0;
+            games[game.second][game.first] += 1.0;
+        }
+
+        let mut strength = vec![1.0; n];
+        for _ in 0..1000 {
+            let mut next: Vec<f64> = (0..n)
+                .map(|i| {
+                    let denominator: f64 = (0..n)
+                        .filter(|j| games[i][*j] > 0.0)
+                        .map(|j| games[i][j] / (strength[i] + strength[j]))
+                        .sum();
+                    if denominator > 0.0 {
+                        wins[i] / denominator
+                    } else {
+                        strength[i]
+                    }
+                })
+                .collect();
+            // Normalize to a geometric mean of one
+            let log_mean = next.iter().map(|s: &f64| s.ln()).sum::<f64>() / n as f64;
+            next.iter_mut().for_each(|s| *s /= log_mean.exp());
+            let change = next.iter().zip(&strength).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+            strength = next;
+            if change < 1e-9 {
+                break;
+            }
+        }
+        strength.iter().map(|s| 400.0 * s.log10()).collect()
+    }
+
+    /// Standings of the entrants, best rated first
+    pub fn standings(&self) -> Vec<Standing> {
+        let ratings = self.elo_ratings();
+        let mut standings: Vec<Standing> = (0..self.names.len())
+            .map(|i| {
+                let games: Vec<&GameSummary> = self.games.iter().filter(|g| g.involves(i)).collect();
+                let count = |score: f64| games.iter().filter(|g| g.score(i) == score).count() as u32;
+                let (score_rate, score_interval) = score_interval(&self.scores(i, None));
+                Standing {
+                    name: self.names[i].clone(),
+                    games: games.len() as u32,
+                    wins: count(1.0),
+                    draws: count(0.5),
+                    losses: count(0.0),
+                    average_moves: games.iter().map(|g| g.moves).sum::<usize>() as f64 / games.len().max(1) as f64,
+                    score_rate,
+                    score_interval,
+                    elo: ratings[i],
+                }
+            })
+            .collect();
+        standings.sort_by(|a, b| b.elo.partial_cmp(&a.elo).unwrap());
+        standings
+    }
+
+    /// Standings as a table for the terminal
+    pub fn table(&self) -> String {
+        let width = self.names.iter().map(|n| n.len()).max().unwrap_or(0).max(5);
+        let mut table = format!(
+            "{:<width$} {:>6} {:>6} {:>6} {:>6} {:>7} {:>15} {:>6} {:>7}\n",
+            "agent",
+            "games",
+            "wins",
+            "draws",
+            "losses",
+            "score",
+            "95% interval",
+            "elo",
+            "length",
+            width = width
+        );
+        for s in self.standings() {
+            writeln!(
+                table,
+                "{:<width$} {:>6} {:>6} {:>6} {:>6} {:>6.1}% {:>6.1}%-{:>5.1}% {:>6.0} {:>7.1}",
+                s.name,
+                s.games,
+                s.wins,
+                s.draws,
+                s.losses,
+                100.0 * s.score_rate,
+                100.0 * s.score_interval.0,
+                100.0 * s.score_interval.1,
+                s.elo,
+                s.average_moves,
+                width = width
+            )
+            .unwrap();
+        }
+        table
+    }
+
+    /// Standings as CSV
+    pub fn standings_csv(&self) -> String {
+        let mut csv = String::from("agent,games,wins,draws,losses,score_rate,score_low,score_high,elo,average_moves\n");
+        for s in self.standings() {
+            writeln!(
+                csv,
+                "{},{},{},{},{},{:.4},{:.4},{:.4},{:.1},{:.1}",
+                csv_field(&s.name),
+                s.games,
+                s.wins,
+                s.draws,
+                s.losses,
+                s.score_rate,
+                s.score_interval.0,
+                s.score_interval.1,
+                s.elo,
+                s.average_moves
+            )
+            .unwrap();
+        }
+        csv
+    }
+
+    /// Every game as CSV
+    pub fn games_csv(&self) -> String {
+        let mut csv = String::from("game,first,second,winner,moves,seed\n");
+        for (i, game) in self.games.iter().enumerate() {
+            let winner = game.winner.map_or("draw".to_string(), |w| csv_field(&self.names[w]));
+            writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                i + 1,
+                csv_field(&self.names[game.first]),
+                csv_field(&self.names[game.second]),
+                winner,
+                game.moves,
+                game.seed
+            )
+            .unwrap();
+        }
+        csv
+    }
+}
+
+/// Names may contain commas, e.g. agent specs
+fn csv_field(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Sequential probability ratio test of whether a candidate agent is
+/// stronger than a baseline: H0 is that the candidate is `elo0` stronger, H1
+/// that it is `elo1` stronger. Games are added until the log-likelihood
+/// ratio crosses one of the bounds given by the error rates `alpha` (of
+/// accepting H1 when H0 holds) and `beta` (of accepting H0 when H1 holds).
+///
+/// The LLR uses the normal approximation of the generalized SPRT, which
+/// handles draws without a draw model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    /// Results of the candidate
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtDecision {
+    /// The candidate is `elo1` stronger
+    AcceptH1,
+    /// The candidate is at most `elo0` stronger
+    AcceptH0,
+    /// More games are needed
+    Continue,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64, alpha: f64, beta: f64) -> Self {
+        Self { elo0, elo1, alpha, beta, wins: 0, draws: 0, losses: 0 }
+    }
+
+    /// Add a game with the candidate's score, one for a win and a half for a
+    /// draw
+    pub fn record(&mut self, score: f64) {
+        if score > 0.5 {
+            self.wins += 1;
+        } else if score < 0.5 {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Bounds of the LLR for accepting H0 and H1
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    /// Log-likelihood ratio of H1 to H0 given the games so far
+    pub fn llr(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        // Half a virtual win and loss keep the variance positive when every
+        // game has had the same result
+        let wins = self.wins as f64 + 0.5;
+        let losses = self.losses as f64 + 0.5;
+        let draws = self.draws as f64;
+        let n = wins + draws + losses;
+        let score = (wins + 0.5 * draws) / n;
+        let variance = (wins * (1.0 - score).powi(2) + draws * (0.5 - score).powi(2) + losses * score.powi(2)) / n;
+
+        let expected_score = |elo: f64| 1.0 / (1.0 + 10f64.powf(-elo / 400.0));
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        self.games() as f64 * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn decision(&self) -> SprtDecision {
+        let (lower, upper) = self.bounds();
+        let llr = self.llr();
+        if llr >= upper {
+            SprtDecision::AcceptH1
+        } else if llr <= lower {
+            SprtDecision::AcceptH0
+        } else {
+            SprtDecision::Continue
+        }
+    }
+}
+
+/// Play games between the candidate and the baseline, alternating who moves
+/// first, until the test reaches a decision or `max_games` games have been
+/// played. Games are played `config.threads` at a time but added to the test
+/// in order, and `on_game` is called after each one.
+pub fn run_sprt<S, G, F>(
+    candidate: Entrant<S>,
+    baseline: Entrant<S>,
+    new_game: G,
+    mut sprt: Sprt,
+    max_games: u32,
+    config: &TournamentConfig,
+    mut on_game: F,
+) -> Sprt
+where
+    S: GameOutcome,
+    G: Fn() -> S + Sync,
+    F: FnMut(&GameSummary, &Sprt),
+{
+    let entrants = [candidate, baseline];
+    let batch_size = config.threads.max(1) as u32;
+    let mut game = 0;
+    while game < max_games {
+        let schedule: Vec<(usize, usize, u64)> = (game..max_games.min(game + batch_size))
+            .map(|g| {
+                let seed = config.seed.wrapping_add(2 * g as u64);
+                if g % 2 == 0 {
+                    (0, 1, seed)
+                } else {
+                    (1, 0, seed)
+                }
+            })
+            .collect();
+        game += schedule.len() as u32;
+
+        for summary in play_schedule(&entrants, &schedule, &new_game, config.threads, config.max_moves) {
+            sprt.record(summary.score(0));
+            on_game(&summary, &sprt);
+            if sprt.decision() != SprtDecision::Continue {
+                return sprt;
+            }
+        }
+    }
+    sprt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{MinimaxBot, RandomBot};
+    use crate::game::one_two_three::*;
+
+    fn entrants() -> Vec<Entrant<OneTwoThreeState>> {
+        vec![
+            Entrant::new("minimax", |_| Box::new(MinimaxBot::new(5, score_difference))),
+            Entrant::new("random", |seed| Box::new(RandomBot::with_seed(seed))),
+            Entrant::new("random2", |seed| Box::new(RandomBot::with_seed(seed))),
+        ]
+    }
+
+    fn config(threads: usize) -> TournamentConfig {
+        TournamentConfig { games_per_pair: 20, threads, seed: 7, ..TournamentConfig::default() }
+    }
+
+    #[test]
+    fn test_round_robin_schedules_every_pair_with_alternating_colors() {
+        let results = round_robin(&entrants(), OneTwoThreeState::new, &config(1));
+        assert_eq!(results.games.len(), 60);
+        let first_moves = results.games.iter().filter(|g| g.first == 0 && g.second == 1).count();
+        let second_moves = results.games.iter().filter(|g| g.first == 1 && g.second == 0).count();
+        assert_eq!((first_moves, second_moves), (10, 10));
+    }
+
+    #[test]
+    fn test_results_do_not_depend_on_threads() {
+        let single = round_robin(&entrants(), OneTwoThreeState::new, &config(1));
+        let parallel = round_robin(&entrants(), OneTwoThreeState::new, &config(3));
+        assert_eq!(single.games, parallel.games);
+    }
+
+    #[test]
+    fn test_standings_rank_perfect_player_first() {
+        let results = round_robin(&entrants(), OneTwoThreeState::new, &config(2));
+        let standings = results.standings();
+        assert_eq!(standings[0].name, "minimax");
+        assert_eq!(standings[0].wins, 40);
+        assert!(standings[0].elo > standings[1].elo + 200.0);
+        assert!(standings[0].score_interval.0 <= standings[0].score_rate);
+
+        let pair = results.pair(0, 1);
+        assert_eq!(pair.games, 20);
+        assert_eq!(pair.score_rate, 1.0);
+        assert!(pair.elo_difference > 0.0);
+
+        assert_eq!(results.games_csv().lines().count(), 61);
+        assert_eq!(results.standings_csv().lines().count(), 4);
+        assert!(results.table().contains("minimax"));
+    }
+
+    #[test]
+    fn test_sprt_bounds_and_llr() {
+        let sprt = Sprt::new(0.0, 10.0, 0.05, 0.05);
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 1e-3);
+        assert!((lower + 2.944).abs() < 1e-3);
+        assert_eq!(sprt.llr(), 0.0);
+
+        // An even score is consistent with H0
+        let mut sprt = Sprt::new(0.0, 50.0, 0.05, 0.05);
+        for _ in 0..500 {
+            sprt.record(1.0);
+            sprt.record(0.0);
+        }
+        assert!(sprt.llr() < 0.0);
+        assert_eq!(sprt.decision(), SprtDecision::AcceptH0);
+
+        let mut sprt = Sprt::new(0.0, 10.0, 0.05, 0.05);
+        for _ in 0..600 {
+            sprt.record(1.0);
+            sprt.record(0.5);
+            sprt.record(0.0);
+            sprt.record(1.0);
+        }
+        assert_eq!(sprt.decision(), SprtDecision::AcceptH1);
+    }
+
+    #[test]
+    fn test_sprt_accepts_stronger_candidate() {
+        let mut entrants = entrants();
+        let baseline = entrants.remove(1);
+        let candidate = entrants.remove(0);
+        let mut games = 0;
+        let sprt = run_sprt(
+            candidate,
+            baseline,
+            OneTwoThreeState::new,
+            Sprt::new(0.0, 50.0, 0.05, 0.05),
+            1000,
+            &config(2),
+            |_, _| games += 1,
+        );
+        assert_eq!(sprt.decision(), SprtDecision::AcceptH1);
+        assert_eq!(sprt.losses, 0);
+        assert_eq!(sprt.games(), games);
+        assert!(games < 100);
+    }
+
+    #[test]
+    fn test_elo_difference() {
+        assert_eq!(elo_difference(0.5), 0.0);
+        assert!((elo_difference(0.75) - 190.8).abs() < 0.1);
+        assert!((elo_difference(0.25) + 190.8).abs() < 0.1);
+    }
+}